@@ -0,0 +1,37 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/system_audio.m");
+    println!("cargo:rerun-if-changed=src/diagnostics.m");
+    println!("cargo:rerun-if-changed=src/power.m");
+
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("macos") {
+        cc::Build::new()
+            .file("src/system_audio.m")
+            .flag("-fobjc-arc")
+            .flag("-fmodules")
+            .compile("system_audio");
+        cc::Build::new()
+            .file("src/diagnostics.m")
+            .flag("-fobjc-arc")
+            .flag("-fmodules")
+            .compile("diagnostics");
+        cc::Build::new()
+            .file("src/power.m")
+            .flag("-fobjc-arc")
+            .flag("-fmodules")
+            .compile("power");
+        println!("cargo:rustc-link-lib=framework=Foundation");
+        println!("cargo:rustc-link-lib=framework=CoreMedia");
+        println!("cargo:rustc-link-lib=framework=AVFoundation");
+        println!("cargo:rustc-link-lib=framework=Metal");
+        println!("cargo:rustc-link-lib=framework=IOKit");
+        // Weak so the binary still starts on macOS versions without it.
+        println!("cargo:rustc-link-arg=-Wl,-weak_framework,ScreenCaptureKit");
+    }
+
+    // Requires `protoc` on PATH (or PROTOC set) when building with --features grpc.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/parakeet.proto")?;
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! Who may use a network server, and how often (`serve --grpc
+//! --auth-token-file`, `--rate-limit`).
+//!
+//! The token is a shared secret read from a file, so it never shows up in
+//! `ps`; clients send it as `authorization: Bearer <token>`. Rate limits
+//! are a token bucket per client address holding a minute's worth of
+//! requests, so a client may burst up to the limit and then gets one
+//! request back every `60 / limit` seconds. Every transcription counts as
+//! a request, including each utterance of a stream.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Clients tracked before buckets that have refilled are dropped
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+pub enum Denied {
+    /// Missing or wrong token
+    Unauthenticated,
+    /// Over the rate limit until this much later
+    RateLimited { retry_after: Duration },
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct Access {
+    token: Option<String>,
+    per_minute: Option<u32>,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl Access {
+    pub fn new(token_file: Option<&Path>, per_minute: Option<u32>) -> Result<Self> {
+        let token = match token_file {
+            Some(path) => {
+                let token = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+                    .trim()
+                    .to_string();
+                if token.is_empty() {
+                    bail!("{} holds no token", path.display());
+                }
+                Some(token)
+            }
+            None => None,
+        };
+        Ok(Self {
+            token,
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn requires_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Checks the bearer token a client sent, if one is required.
+    pub fn authenticate(&self, sent: Option<&str>) -> Result<(), Denied> {
+        match (&self.token, sent) {
+            (None, _) => Ok(()),
+            (Some(token), Some(sent)) if constant_time_eq(token.as_bytes(), sent.as_bytes()) => {
+                Ok(())
+            }
+            _ => Err(Denied::Unauthenticated),
+        }
+    }
+
+    /// Counts one request from `client` against its rate limit.
+    pub fn take(&self, client: Option<IpAddr>) -> Result<(), Denied> {
+        let (Some(per_minute), Some(client)) = (self.per_minute, client) else {
+            return Ok(());
+        };
+        let capacity = per_minute as f64;
+        let per_sec = capacity / 60.0;
+        let now = Instant::now();
+
+        let refilled = |b: &Bucket| {
+            (b.tokens + now.duration_since(b.updated).as_secs_f64() * per_sec).min(capacity)
+        };
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, b| refilled(b) < capacity);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = refilled(bucket);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Denied::RateLimited {
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec),
+            });
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Compares without returning early, so timing doesn't reveal how much of
+/// a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,264 @@
+//! `--word-alternatives`: what else the decoder might have heard, so a
+//! misheard word can be corrected with a tap instead of retyped.
+//!
+//! transcribe-rs hands back the TDT decoder's single best path without
+//! token scores, so, as in `second_pass`, confidence is measured by
+//! agreement. The transcript is cut into the same spans, and each span's
+//! audio is decoded on its own again with several amounts of surrounding
+//! audio. Shifting the context moves the decoder off the words it was
+//! guessing at and leaves the ones it was sure of. Every reading is lined
+//! up with the transcript word by word, and a word's confidence is the
+//! share of readings, the transcript's own included, that heard it. The
+//! words the other readings heard in its place are its alternatives, and
+//! their shares are their probabilities, so those come in steps of
+//! 1 / (`READING_PADS_SECS.len()` + 1). Each reading costs another
+//! Parakeet pass over the recording.
+
+use crate::retry::RetryPolicy;
+use crate::second_pass;
+use crate::words::{self, Word};
+use crate::{AudioInput, Segment};
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use transcribe_rs::engines::parakeet::ParakeetEngine;
+
+/// Audio kept either side of a span for each extra reading of it
+const READING_PADS_SECS: [f64; 4] = [0.0, 0.2, 0.5, 1.0];
+/// Alternatives listed per word, most likely first
+const MAX_ALTERNATIVES: usize = 3;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+pub struct UncertainWord {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Share of readings that heard this word, 0 to 1
+    pub confidence: f32,
+    /// What the other readings heard instead, most likely first
+    pub alternatives: Vec<Alternative>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+pub struct Alternative {
+    pub text: String,
+    /// Share of readings that heard this instead, 0 to 1
+    pub probability: f32,
+}
+
+/// Words of `segments` that other readings of `samples`, the 16 kHz mono
+/// audio they were decoded from, heard differently.
+pub fn find(
+    engine: &mut ParakeetEngine,
+    samples: &[f32],
+    segments: &[Segment],
+    retry: RetryPolicy,
+) -> Result<Vec<UncertainWord>> {
+    let mut uncertain = Vec::new();
+    let mut rest = segments;
+
+    for len in second_pass::span_lens(segments) {
+        let (span, tail) = rest.split_at(len);
+        rest = tail;
+        let words = words::from_segments(span);
+        if words.is_empty() {
+            continue;
+        }
+
+        let (start, end) = (span[0].start, span[len - 1].end);
+        let mut votes: Vec<Vec<String>> = words.iter().map(|w| vec![w.text.clone()]).collect();
+        for pad_secs in READING_PADS_SECS {
+            let audio = second_pass::pad(samples, start, end, pad_secs);
+            let reading = AudioInput::Samples(audio.to_vec())
+                .transcribe(engine, retry)
+                .map_err(|e| anyhow!("Transcription failed: {}", e))?;
+            let heard: Vec<&str> = reading.text.split_whitespace().collect();
+            for (votes, at) in votes.iter_mut().zip(align(&words, &heard)) {
+                votes.push(at.map_or_else(String::new, |j| heard[j].to_string()));
+            }
+        }
+
+        uncertain.extend(
+            words
+                .into_iter()
+                .zip(votes)
+                .filter_map(|(word, votes)| tally(word, &votes)),
+        );
+    }
+
+    Ok(uncertain)
+}
+
+/// Case-folded word without punctuation, for comparing readings.
+fn key(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// For each of `words`, the index of the word in `heard` it lines up with
+/// by word-level edit distance, or `None` where `heard` has no word for it.
+fn align(words: &[Word], heard: &[&str]) -> Vec<Option<usize>> {
+    let a: Vec<String> = words.iter().map(|w| key(&w.text)).collect();
+    let b: Vec<String> = heard.iter().map(|w| key(w)).collect();
+
+    // cost[i][j]: edit distance between the first i of `a` and first j of `b`
+    let mut cost = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in cost.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in cost[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = cost[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            cost[i][j] = substitution.min(cost[i - 1][j] + 1).min(cost[i][j - 1] + 1);
+        }
+    }
+
+    let mut aligned = vec![None; a.len()];
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 {
+        if j > 0 && cost[i][j] == cost[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]) {
+            aligned[i - 1] = Some(j - 1);
+            i -= 1;
+            j -= 1;
+        } else if j > 0 && cost[i][j] == cost[i][j - 1] + 1 {
+            j -= 1;
+        } else {
+            i -= 1;
+        }
+    }
+    aligned
+}
+
+/// `word` with its alternatives, given what each reading heard in its
+/// place, the transcript's own reading first; `None` when no reading heard
+/// another word. A reading that heard nothing there counts against the
+/// word without offering an alternative.
+fn tally(word: Word, votes: &[String]) -> Option<UncertainWord> {
+    let own = key(&word.text);
+    if own.is_empty() {
+        return None;
+    }
+
+    // Each distinct word heard, as its key, first spelling and count
+    let mut heard: Vec<(String, &str, usize)> = Vec::new();
+    for vote in votes {
+        let vote_key = key(vote);
+        match heard.iter_mut().find(|(k, _, _)| *k == vote_key) {
+            Some((_, _, count)) => *count += 1,
+            None => heard.push((vote_key, vote.as_str(), 1)),
+        }
+    }
+
+    let share = |count: usize| count as f32 / votes.len() as f32;
+    let confidence = heard
+        .iter()
+        .find(|(k, _, _)| *k == own)
+        .map_or(0.0, |&(_, _, count)| share(count));
+    heard.retain(|(k, _, _)| !k.is_empty() && *k != own);
+    if heard.is_empty() {
+        return None;
+    }
+    // Stable, so ties stay in reading order.
+    heard.sort_by(|a, b| b.2.cmp(&a.2));
+
+    Some(UncertainWord {
+        start: word.start,
+        end: word.end,
+        text: word.text,
+        confidence,
+        alternatives: heard
+            .into_iter()
+            .take(MAX_ALTERNATIVES)
+            .map(|(_, text, count)| Alternative {
+                text: text
+                    .trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_string(),
+                probability: share(count),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<Word> {
+        text.split_whitespace()
+            .enumerate()
+            .map(|(i, text)| Word {
+                start: i as f64,
+                end: i as f64 + 1.0,
+                text: text.to_string(),
+            })
+            .collect()
+    }
+
+    fn votes(votes: &[&str]) -> Vec<String> {
+        votes.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn aligns_substitutions_insertions_and_deletions() {
+        let transcript = words("the cat sat on the mat");
+        assert_eq!(
+            align(&transcript, &["The", "hat", "sat", "on", "mat."]),
+            [Some(0), Some(1), Some(2), Some(3), None, Some(4)]
+        );
+        assert_eq!(
+            align(
+                &transcript,
+                &["so", "the", "cat", "sat", "on", "the", "mat"]
+            ),
+            [Some(1), Some(2), Some(3), Some(4), Some(5), Some(6)]
+        );
+        assert_eq!(align(&transcript, &[]), [None; 6]);
+    }
+
+    #[test]
+    fn agreed_words_have_no_alternatives() {
+        let word = words("Kubernetes,").remove(0);
+        let votes = votes(&["Kubernetes,", "kubernetes", "Kubernetes.", "Kubernetes", ""]);
+        assert_eq!(tally(word, &votes), None);
+    }
+
+    #[test]
+    fn alternatives_are_ranked_by_share_of_readings() {
+        let word = words("cat").remove(0);
+        let votes = votes(&["cat", "hat", "Cat", "hat,", "bat"]);
+        assert_eq!(
+            tally(word, &votes),
+            Some(UncertainWord {
+                start: 0.0,
+                end: 1.0,
+                text: "cat".to_string(),
+                confidence: 0.4,
+                alternatives: vec![
+                    Alternative {
+                        text: "hat".to_string(),
+                        probability: 0.4,
+                    },
+                    Alternative {
+                        text: "bat".to_string(),
+                        probability: 0.2,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn dropped_readings_count_against_the_word() {
+        let word = words("um").remove(0);
+        let votes = votes(&["um", "", "", "", "uh"]);
+        let uncertain = tally(word, &votes).unwrap();
+        assert_eq!(uncertain.confidence, 0.2);
+        assert_eq!(uncertain.alternatives.len(), 1);
+        assert_eq!(uncertain.alternatives[0].probability, 0.2);
+    }
+}
@@ -0,0 +1,125 @@
+//! Checks on input audio made before it reaches the engine.
+
+use crate::dsp;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// Peak amplitude below which input counts as silence (about -60 dBFS).
+/// Parakeet tends to invent a word or two for digital silence, so such
+/// input is answered with `no_speech` without decoding it.
+pub const SILENCE_PEAK: f32 = 0.001;
+
+pub fn is_silent(samples: &[f32], peak: f32) -> bool {
+    dsp::peak(samples) < peak
+}
+
+/// Like `is_silent`, streaming the samples of a WAV file. Files hound cannot
+/// read are reported as not silent and left for the engine to judge.
+pub fn file_is_silent(path: &Path, peak: f32) -> bool {
+    let Ok(mut reader) = hound::WavReader::open(path) else {
+        return false;
+    };
+    let spec = reader.spec();
+
+    match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .all(|s| s.is_ok_and(|s| s.abs() < peak)),
+        hound::SampleFormat::Int => {
+            let threshold = peak * (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .all(|s| s.is_ok_and(|s| (s as f32).abs() < threshold))
+        }
+    }
+}
+
+/// `--max-input-duration` / `--max-input-bytes`, checked from the file
+/// header before anything is decoded.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InputLimits {
+    pub max_duration_secs: Option<f64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl InputLimits {
+    pub fn check_file(&self, path: &Path) -> Result<()> {
+        if let Some(max_bytes) = self.max_bytes {
+            let bytes = std::fs::metadata(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .len();
+            if bytes > max_bytes {
+                bail!(
+                    "{} is {} bytes, over the --max-input-bytes limit of {}",
+                    path.display(),
+                    bytes,
+                    max_bytes
+                );
+            }
+        }
+
+        if let Some(max_secs) = self.max_duration_secs {
+            // A file hound can't parse is left for the engine to reject.
+            if let Ok(reader) = hound::WavReader::open(path) {
+                let spec = reader.spec();
+                let secs = reader.duration() as f64 / spec.sample_rate as f64;
+                if secs > max_secs {
+                    bail!(
+                        "{} is {}, over the --max-input-duration limit of {}",
+                        path.display(),
+                        format_duration(secs),
+                        format_duration(max_secs)
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn check_samples(&self, samples: usize, sample_rate: u32) -> Result<()> {
+        let bytes = samples as u64 * 4;
+        if let Some(max_bytes) = self.max_bytes.filter(|&max| bytes > max) {
+            bail!(
+                "Input is {} bytes, over the --max-input-bytes limit of {}",
+                bytes,
+                max_bytes
+            );
+        }
+
+        let secs = samples as f64 / sample_rate as f64;
+        if let Some(max_secs) = self.max_duration_secs.filter(|&max| secs > max) {
+            bail!(
+                "Input is {}, over the --max-input-duration limit of {}",
+                format_duration(secs),
+                format_duration(max_secs)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `90`, `90s`, `45m` or `2h` into seconds.
+pub fn parse_duration(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let (number, scale) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1.0),
+        Some('m') => (&value[..value.len() - 1], 60.0),
+        Some('h') => (&value[..value.len() - 1], 3600.0),
+        _ => (value, 1.0),
+    };
+
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 => Ok(n * scale),
+        _ => Err(format!(
+            "expected a duration like 90s, 45m or 2h, got {}",
+            value
+        )),
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let total = secs.round() as u64;
+    format!("{}:{:02}:{:02}", total / 3600, total / 60 % 60, total % 60)
+}
@@ -0,0 +1,323 @@
+//! `batch`: transcribing many short clips in few engine calls.
+//!
+//! The engine decodes one buffer per call and has no batch dimension, so
+//! for a folder of voice memos the fixed cost of every call dominates.
+//! Clips are instead packed end to end, separated by a short stretch of
+//! silence, into buffers of up to `--pack-secs`, and each packed buffer is
+//! decoded once. Segments are then handed back to the clip they fall in and
+//! moved onto its own timeline. Clips longer than a pack are decoded alone.
+//!
+//! Prints one JSON line per input file, in input order: the usual
+//! transcription output plus `path`, or `path` and `error` for files that
+//! could not be read or decoded.
+//!
+//! With `--concat` the files are instead the consecutive parts of one
+//! recording, such as a recorder's 30-minute splits, and a single line
+//! holds the merged transcript with `paths` and times on the whole
+//! recording's timeline. Parts are decoded one at a time, so memory stays
+//! that of one part. A recorder cuts wherever the clock says, often
+//! mid-word, so the audio from the start of each part's last segment (at
+//! most `MAX_CARRY_SECS` of it) is held back and decoded again at the head
+//! of the next part, and only that decode's segments for it are kept.
+//! Any part that can't be read or decoded fails the whole run.
+
+use crate::incremental::SAMPLE_RATE;
+use crate::retry::RetryPolicy;
+use crate::timestamps::TimestampFormat;
+use crate::{memory, sanity, wav, AudioInput, Segment, TranscriptionOutput, TranscriptionStatus};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use transcribe_rs::engines::parakeet::ParakeetEngine;
+
+/// Most audio held back from one part to decode with the next
+const MAX_CARRY_SECS: f64 = 30.0;
+
+pub struct Options {
+    /// Longest packed buffer, in seconds
+    pub pack_secs: f64,
+    /// Silence between packed clips, in seconds
+    pub gap_secs: f64,
+    pub timestamps: TimestampFormat,
+    pub sanity_check: sanity::Mode,
+    /// Treat the files as parts of one recording
+    pub concat: bool,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Line<'a> {
+    Transcribed {
+        path: &'a Path,
+        #[serde(flatten)]
+        output: TranscriptionOutput,
+    },
+    Failed {
+        path: &'a Path,
+        error: String,
+    },
+    Concatenated {
+        paths: &'a [PathBuf],
+        #[serde(flatten)]
+        output: TranscriptionOutput,
+    },
+}
+
+struct Clip {
+    path: PathBuf,
+    samples: Vec<f32>,
+}
+
+struct Packer<'a, W> {
+    engine: &'a mut ParakeetEngine,
+    retry: RetryPolicy,
+    out: W,
+    pack_len: usize,
+    gap_len: usize,
+    pending: Vec<Clip>,
+    pending_len: usize,
+    timestamps: TimestampFormat,
+    sanity_check: sanity::Mode,
+}
+
+pub fn run<W: Write>(
+    engine: &mut ParakeetEngine,
+    retry: RetryPolicy,
+    files: &[PathBuf],
+    options: &Options,
+    out: W,
+) -> Result<()> {
+    let mut packer = Packer {
+        engine,
+        retry,
+        out,
+        pack_len: (options.pack_secs * SAMPLE_RATE as f64) as usize,
+        gap_len: (options.gap_secs * SAMPLE_RATE as f64) as usize,
+        pending: Vec::new(),
+        pending_len: 0,
+        timestamps: options.timestamps,
+        sanity_check: options.sanity_check,
+    };
+    if options.concat {
+        return packer.concat(files);
+    }
+
+    for path in files {
+        match wav::read(path) {
+            Ok(samples) => packer.add(Clip {
+                path: path.clone(),
+                samples,
+            })?,
+            Err(e) => {
+                // Earlier clips are still waiting; keep the output in input order.
+                packer.flush()?;
+                packer.write(&Line::Failed {
+                    path,
+                    error: format!("{:#}", e),
+                })?;
+            }
+        }
+    }
+
+    packer.flush()
+}
+
+/// How `run` would group clips of these lengths in samples (`None` for
+/// files that fail to read) into engine calls, as indices into `lens`.
+pub fn plan_packs(lens: &[Option<usize>], options: &Options) -> Vec<Vec<usize>> {
+    if options.concat {
+        return (0..lens.len()).map(|i| vec![i]).collect();
+    }
+    let pack_len = (options.pack_secs * SAMPLE_RATE as f64) as usize;
+    let gap_len = (options.gap_secs * SAMPLE_RATE as f64) as usize;
+    let mut packs = Vec::new();
+    let mut pending = Vec::new();
+    let mut pending_len = 0;
+
+    for (i, len) in lens.iter().enumerate() {
+        let Some(len) = len else {
+            if !pending.is_empty() {
+                packs.push(std::mem::take(&mut pending));
+            }
+            pending_len = 0;
+            continue;
+        };
+        let added = gap_len + len;
+        if !pending.is_empty() && pending_len + added > pack_len {
+            packs.push(std::mem::take(&mut pending));
+            pending_len = 0;
+        }
+        pending_len += added;
+        pending.push(i);
+    }
+
+    if !pending.is_empty() {
+        packs.push(pending);
+    }
+    packs
+}
+
+impl<W: Write> Packer<'_, W> {
+    fn add(&mut self, clip: Clip) -> Result<()> {
+        let added = self.gap_len + clip.samples.len();
+        if !self.pending.is_empty() && self.pending_len + added > self.pack_len {
+            self.flush()?;
+        }
+        self.pending_len += added;
+        self.pending.push(clip);
+        Ok(())
+    }
+
+    /// Decodes the pending clips as one buffer and writes their results.
+    fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let clips = std::mem::take(&mut self.pending);
+        self.pending_len = 0;
+
+        let mut packed =
+            Vec::with_capacity(clips.iter().map(|c| c.samples.len() + self.gap_len).sum());
+        let mut offsets = Vec::with_capacity(clips.len());
+        for clip in &clips {
+            offsets.push(packed.len() as f64 / SAMPLE_RATE as f64);
+            packed.extend_from_slice(&clip.samples);
+            packed.resize(packed.len() + self.gap_len, 0.0);
+        }
+        let packed_secs = packed.len() as f64 / SAMPLE_RATE as f64;
+
+        let start_time = Instant::now();
+        let result = AudioInput::Samples(packed).transcribe(self.engine, self.retry);
+        let elapsed = start_time.elapsed();
+
+        let mut segments = match result {
+            Ok(result) => crate::to_output(result, elapsed).segments,
+            Err(e) => {
+                let error = format!("Transcription failed: {}", e);
+                for clip in &clips {
+                    self.write(&Line::Failed {
+                        path: &clip.path,
+                        error: error.clone(),
+                    })?;
+                }
+                return Ok(());
+            }
+        }
+        .into_iter()
+        .peekable();
+
+        for (i, clip) in clips.iter().enumerate() {
+            // A clip owns everything up to where the next one starts,
+            // including the silence after it.
+            let offset = offsets[i];
+            let end = offsets.get(i + 1).copied().unwrap_or(f64::INFINITY);
+            let mut own = Vec::new();
+            while let Some(segment) = segments.next_if(|s| (s.start + s.end) / 2.0 < end) {
+                own.push(Segment {
+                    start: (segment.start - offset).max(0.0),
+                    end: segment.end - offset,
+                    text: segment.text,
+                    overlap: false,
+                    suspect: None,
+                });
+            }
+
+            // Processing time is shared out by duration.
+            let clip_secs = (clip.samples.len() + self.gap_len) as f64 / SAMPLE_RATE as f64;
+            let mut output = clip_output(own, elapsed.mul_f64(clip_secs / packed_secs));
+            sanity::check(&mut output, self.sanity_check);
+            self.write(&Line::Transcribed {
+                path: &clip.path,
+                output,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes `files` as one recording and writes the merged transcript.
+    fn concat(&mut self, files: &[PathBuf]) -> Result<()> {
+        let start_time = Instant::now();
+        let mut segments = Vec::new();
+        let mut carry: Vec<f32> = Vec::new();
+        // Where the decoded buffer starts on the recording's timeline
+        let mut offset = 0.0;
+
+        for (i, path) in files.iter().enumerate() {
+            let mut samples = std::mem::take(&mut carry);
+            samples.extend(wav::read(path)?);
+            let len = samples.len();
+            // Only the end can be carried, so only it is kept past decoding.
+            let tail_start = len.saturating_sub((MAX_CARRY_SECS * SAMPLE_RATE as f64) as usize);
+            let tail = samples[tail_start..].to_vec();
+
+            let result = AudioInput::Samples(samples)
+                .transcribe(self.engine, self.retry)
+                .map_err(|e| anyhow::anyhow!("Transcription failed: {}", e))
+                .with_context(|| format!("Failed to transcribe {}", path.display()))?;
+            let mut part = crate::to_output(result, Duration::ZERO).segments;
+
+            let kept = if i + 1 < files.len() {
+                let carry_from = part.last().map_or(len, |s| {
+                    ((s.start * SAMPLE_RATE as f64) as usize).clamp(tail_start, len)
+                });
+                part.retain(|s| s.start < carry_from as f64 / SAMPLE_RATE as f64);
+                carry = tail[carry_from - tail_start..].to_vec();
+                carry_from
+            } else {
+                len
+            };
+            for segment in &mut part {
+                segment.start += offset;
+                segment.end += offset;
+            }
+            offset += kept as f64 / SAMPLE_RATE as f64;
+            segments.extend(part);
+        }
+
+        let mut output = clip_output(segments, start_time.elapsed());
+        sanity::check(&mut output, self.sanity_check);
+        self.write(&Line::Concatenated {
+            paths: files,
+            output,
+        })
+    }
+
+    fn write(&mut self, line: &Line<'_>) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &self.timestamps.to_value(line)?)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn clip_output(segments: Vec<Segment>, processing_time: Duration) -> TranscriptionOutput {
+    let text = segments
+        .iter()
+        .map(|s| s.text.as_str())
+        .collect::<String>()
+        .trim()
+        .to_string();
+    let status = if text.is_empty() {
+        TranscriptionStatus::NoSpeech
+    } else {
+        TranscriptionStatus::Ok
+    };
+
+    TranscriptionOutput {
+        schema_version: crate::SCHEMA_VERSION,
+        status,
+        text,
+        segments,
+        processing_time_ms: processing_time.as_millis() as u64,
+        peak_rss_bytes: memory::peak_rss_bytes(),
+        cues: Vec::new(),
+        chapters: Vec::new(),
+        revisions: Vec::new(),
+        alternatives: Vec::new(),
+        post_processing_output: None,
+        cached: false,
+    }
+}
@@ -0,0 +1,103 @@
+//! Transcript cache (`--cache-dir`), keyed by what decides a transcript.
+//!
+//! The key hashes together the audio's content hash, the model (its
+//! directory plus the size and modification time of each file in it, so a
+//! re-downloaded model misses) and the options that change what the engine
+//! decodes: timestamp granularity and the silence threshold. Everything
+//! applied after decoding (response format, vocabulary, locale, cues, the
+//! second pass) stays out of the key and is applied to the cached
+//! transcript, so re-running a file with only those changed is answered
+//! without touching the model.
+//!
+//! Entries are one JSON file each, written to a temporary name and renamed
+//! into place. An entry that can't be read or parsed counts as a miss.
+
+use crate::{memory, TranscribeOptions, TranscriptionOutput};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The raw transcript cached under `key`, marked as `cached`.
+    /// `processing_time_ms` is still the original run's.
+    pub fn get(&self, key: &str) -> Option<TranscriptionOutput> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        let mut output: TranscriptionOutput = serde_json::from_slice(&bytes)
+            .map_err(|e| log::warn!("Ignoring corrupt cache entry {}: {}", key, e))
+            .ok()?;
+        output.cached = true;
+        output.peak_rss_bytes = memory::peak_rss_bytes();
+        Some(output)
+    }
+
+    /// Stores a transcript as the engine produced it, before any of the
+    /// options applied afterwards.
+    pub fn put(&self, key: &str, output: &TranscriptionOutput) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create cache directory {}", self.dir.display()))?;
+        let path = self.path(key);
+        let temp = path.with_extension(format!("tmp.{}", std::process::id()));
+        fs::write(&temp, serde_json::to_vec(output)?)
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Cache key for audio with content hash `audio_sha256`, decoded by the
+/// model in `model` with `options`.
+pub fn key(audio_sha256: &str, model: &Path, options: &TranscribeOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(audio_sha256.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(model_stamp(model).as_bytes());
+    hasher.update(b"\0");
+    hasher.update(format!("{:?}", options.granularity).as_bytes());
+    hasher.update(options.silence_peak().to_le_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// The model directory plus the name, size and modification time of every
+/// file in it.
+fn model_stamp(model: &Path) -> String {
+    let dir = fs::canonicalize(model).unwrap_or_else(|_| model.to_path_buf());
+    let mut stamp = dir.display().to_string();
+
+    let mut files: Vec<(String, u64, u64)> = fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            Some((
+                entry.file_name().to_string_lossy().into_owned(),
+                metadata.len(),
+                modified,
+            ))
+        })
+        .collect();
+    files.sort();
+
+    for (name, len, modified) in files {
+        stamp.push_str(&format!("\n{} {} {}", name, len, modified));
+    }
+    stamp
+}
@@ -0,0 +1,159 @@
+//! Live caption files (`capture --live-captions`).
+//!
+//! Every finished utterance is appended to an SRT or WebVTT file as soon as
+//! it is transcribed, so OBS and other tools that tail a caption file show
+//! it while the capture is still running. The format follows the file's
+//! extension. Utterances are split into cues of at most two lines and
+//! `CUE_MAX_SECS`, broken at word boundaries using the word timings, and
+//! times are on the capture's timeline. Cues from the mic and system
+//! sources are appended as each finishes, so with both enabled a cue can
+//! start before the one above it.
+
+use crate::words::Word;
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+const LINE_CHARS: usize = 42;
+const CUE_MAX_SECS: f64 = 6.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Srt,
+    Vtt,
+}
+
+pub struct CaptionWriter {
+    file: File,
+    format: Format,
+    /// SRT sequence number of the next cue
+    next_index: u32,
+}
+
+impl CaptionWriter {
+    /// Creates (or truncates) the caption file at `path`, an `.srt` or
+    /// `.vtt` file.
+    pub fn create(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let format = match extension.as_deref() {
+            Some("srt") => Format::Srt,
+            Some("vtt") => Format::Vtt,
+            _ => bail!(
+                "Can't tell the caption format of {}: use a .srt or .vtt file",
+                path.display()
+            ),
+        };
+
+        let mut file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        if format == Format::Vtt {
+            file.write_all(b"WEBVTT\n\n")?;
+        }
+        Ok(Self {
+            file,
+            format,
+            next_index: 1,
+        })
+    }
+
+    /// Appends an utterance's words, which are on the capture's timeline,
+    /// as one or more cues.
+    pub fn append(&mut self, words: &[Word], speaker: Option<&str>) -> Result<()> {
+        let mut chunk = String::new();
+        for cue in cues(words) {
+            let text = match (speaker, self.format) {
+                (Some(speaker), Format::Vtt) => format!("<v {}>{}", speaker, cue.text),
+                (Some(speaker), Format::Srt) => format!("{}: {}", speaker, cue.text),
+                (None, _) => cue.text,
+            };
+            if self.format == Format::Srt {
+                chunk.push_str(&format!("{}\n", self.next_index));
+                self.next_index += 1;
+            }
+            chunk.push_str(&format!(
+                "{} --> {}\n{}\n\n",
+                self.timestamp(cue.start),
+                self.timestamp(cue.end),
+                text
+            ));
+        }
+
+        // One write per utterance, so a reader never sees half a cue.
+        self.file
+            .write_all(chunk.as_bytes())
+            .context("Failed to write live captions")?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn timestamp(&self, secs: f64) -> String {
+        let millis = (secs.max(0.0) * 1000.0).round() as u64;
+        let separator = match self.format {
+            Format::Srt => ',',
+            Format::Vtt => '.',
+        };
+        format!(
+            "{:02}:{:02}:{:02}{}{:03}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            separator,
+            millis % 1000
+        )
+    }
+}
+
+struct Cue {
+    start: f64,
+    end: f64,
+    /// One or two lines
+    text: String,
+}
+
+/// Packs words into cues of up to two `LINE_CHARS` lines and
+/// `CUE_MAX_SECS`.
+fn cues(words: &[Word]) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut lines: Vec<String> = vec![String::new()];
+    let mut start = 0.0;
+    let mut end = 0.0;
+
+    for word in words {
+        let line_len = lines.last().map_or(0, |l| l.chars().count());
+        let fits_line = line_len == 0 || line_len + 1 + word.text.chars().count() <= LINE_CHARS;
+        let too_long = word.end - start > CUE_MAX_SECS;
+
+        if !lines[0].is_empty() && (too_long || (!fits_line && lines.len() == 2)) {
+            cues.push(Cue {
+                start,
+                end,
+                text: lines.join("\n"),
+            });
+            lines = vec![String::new()];
+        } else if !fits_line {
+            lines.push(String::new());
+        }
+
+        if lines.len() == 1 && lines[0].is_empty() {
+            start = word.start;
+        }
+        let line = lines.last_mut().expect("a cue always has a line");
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word.text);
+        end = word.end;
+    }
+
+    if !lines[0].is_empty() {
+        cues.push(Cue {
+            start,
+            end,
+            text: lines.join("\n"),
+        });
+    }
+    cues
+}
@@ -0,0 +1,477 @@
+//! Live capture (`capture`): records the microphone and/or the Mac's own
+//! audio output, splits it into utterances and prints one JSON event per
+//! line as each is transcribed. Runs until interrupted or `--duration`
+//! elapses.
+//!
+//! With `--dictation`, speech is also re-decoded while it is still being
+//! spoken and reported as stable-prefix diffs (see `dictation`).
+//! `--word-events` uses the same partial decodes to send each word, with
+//! its timing, once it has settled. Parakeet returns whole hypotheses
+//! rather than tokens as it goes, so how often those decodes run
+//! (`--partial-interval-ms`) sets the latency.
+//!
+//! With both sources enabled each is segmented separately and every event
+//! says which one it came from, so on a one-on-one call "mic" is the user
+//! and "system" is the other party. `--speaker-embeddings` clusters each
+//! source's utterances by voiceprint, tags every transcript with its
+//! cluster and ends the capture with a voiceprint per cluster, so several
+//! people on the system side each get their own (see `voiceprint`).
+//!
+//! `--live-captions` also appends every transcript to a caption file as it
+//! finishes (see `captions`), and `--stats` ends the capture with talk time
+//! and interruptions per speaker (see `stats`). `--save-audio` records
+//! what each source fed the recognizer (see `recording`).
+
+use crate::captions::CaptionWriter;
+use crate::dictation::{Dictation, Diff, WordStream};
+use crate::dsp::{downmix, Resampler};
+use crate::incremental::SAMPLE_RATE;
+use crate::recording::{self, Recorder};
+use crate::retry::RetryPolicy;
+use crate::speakers::Identifier;
+use crate::stats::Stats;
+use crate::stream::{self, Event, LevelMeter, Segmenter, VadConfig, VadEvent};
+use crate::timestamps::TimestampFormat;
+use crate::voiceprint::Clusters;
+use crate::{words, AudioInput};
+use anyhow::{bail, Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SizedSample};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use transcribe_rs::engines::parakeet::ParakeetEngine;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Default input device
+    Mic,
+    /// Everything the Mac is playing, via ScreenCaptureKit (macOS 13+)
+    System,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Mic => "mic",
+            Source::System => "system",
+        }
+    }
+}
+
+/// Captured audio, already converted to 16 kHz mono.
+pub struct Block {
+    pub source: Source,
+    pub samples: Vec<f32>,
+}
+
+/// Keeps a capture source running for as long as it is alive.
+#[allow(dead_code)] // the handles are only held, never read
+enum Capture {
+    Mic(cpal::Stream),
+    #[cfg(target_os = "macos")]
+    System(crate::system_audio::SystemAudio),
+}
+
+impl Capture {
+    fn start(source: Source, tx: Sender<Block>) -> Result<Self> {
+        match source {
+            Source::Mic => Ok(Capture::Mic(start_mic(tx)?)),
+            #[cfg(target_os = "macos")]
+            Source::System => Ok(Capture::System(crate::system_audio::SystemAudio::start(
+                tx,
+            )?)),
+            #[cfg(not(target_os = "macos"))]
+            Source::System => bail!("System audio capture is only available on macOS"),
+        }
+    }
+}
+
+pub struct Options {
+    pub vad: VadConfig,
+    /// Emit level events this many times a second
+    pub level_hz: Option<f32>,
+    /// Re-decode speech in progress this often
+    pub partial_interval: Option<Duration>,
+    /// Turn partial decodes into dictation diffs
+    pub dictation: bool,
+    /// Turn partial decodes into word events
+    pub word_events: bool,
+    /// Emit a voiceprint per speaker of each source when capture ends
+    pub speaker_embeddings: bool,
+    /// Name the speaker of every transcript from enrolled voiceprints
+    pub speakers: Option<Identifier>,
+    /// Stop after this long
+    pub duration: Option<Duration>,
+    /// Append every transcript to this SRT or WebVTT file
+    pub live_captions: Option<PathBuf>,
+    /// Emit per-speaker statistics when capture ends
+    pub stats: bool,
+    pub timestamps: TimestampFormat,
+    /// Record each source's audio to this WAV or FLAC file
+    pub save_audio: Option<PathBuf>,
+}
+
+/// Per-source state: its own capture handle and utterance segmenter.
+struct Track {
+    source: Source,
+    _capture: Capture,
+    segmenter: Segmenter,
+    meter: Option<LevelMeter>,
+    dictation: Option<Dictation>,
+    words: Option<WordStream>,
+    speakers: Option<Clusters>,
+    recorder: Option<Recorder>,
+    /// Length of the open utterance when it was last partially decoded
+    decoded_len: usize,
+    /// When this source's first audio arrived, relative to the start of the
+    /// capture, so both sources share a timeline
+    offset: f64,
+}
+
+pub fn run(
+    engine: &mut ParakeetEngine,
+    retry: RetryPolicy,
+    sources: &[Source],
+    options: &Options,
+) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+            .context("Failed to install the interrupt handler")?;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let several = sources.iter().any(|&s| s != sources[0]);
+    let mut tracks = Vec::new();
+    for &source in sources {
+        if tracks.iter().any(|t: &Track| t.source == source) {
+            continue;
+        }
+        let recorder = options
+            .save_audio
+            .as_deref()
+            .map(|path| Recorder::create(&recording::source_path(path, source.name(), several)))
+            .transpose()?;
+        tracks.push(Track {
+            source,
+            _capture: Capture::start(source, tx.clone())?,
+            segmenter: Segmenter::new(options.vad),
+            meter: options.level_hz.map(LevelMeter::new),
+            dictation: options.dictation.then(Dictation::default),
+            words: options.word_events.then(WordStream::default),
+            speakers: options.speaker_embeddings.then(Clusters::default),
+            recorder,
+            decoded_len: 0,
+            offset: f64::NAN,
+        });
+        log::info!("Capturing {:?} audio", source);
+    }
+    drop(tx);
+
+    let started = Instant::now();
+    let mut pipeline = Pipeline {
+        engine,
+        retry,
+        out: io::stdout().lock(),
+        partial_interval: options
+            .partial_interval
+            .map(|d| (d.as_secs_f64() * SAMPLE_RATE as f64) as usize),
+        speakers: options.speakers.as_ref(),
+        captions: options
+            .live_captions
+            .as_deref()
+            .map(CaptionWriter::create)
+            .transpose()?,
+        stats: options.stats.then(Stats::default),
+        timestamps: options.timestamps,
+    };
+
+    while running.load(Ordering::SeqCst) && options.duration.is_none_or(|d| started.elapsed() < d) {
+        let block = match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(block) => block,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let Some(track) = tracks.iter_mut().find(|t| t.source == block.source) else {
+            continue;
+        };
+
+        if track.offset.is_nan() {
+            let block_secs = block.samples.len() as f64 / SAMPLE_RATE as f64;
+            track.offset = (started.elapsed().as_secs_f64() - block_secs).max(0.0);
+            if let Some(recorder) = &mut track.recorder {
+                recorder.write_silence(track.offset)?;
+            }
+        }
+        if let Some(recorder) = &mut track.recorder {
+            recorder.write(&block.samples)?;
+        }
+        pipeline.block(track, &block.samples)?;
+    }
+
+    for track in &mut tracks {
+        for event in track.segmenter.flush() {
+            pipeline.vad_event(track, event)?;
+        }
+        if let Some(speakers) = &track.speakers {
+            for (cluster, embedding) in speakers.embeddings() {
+                pipeline.emit(&Event::Speaker {
+                    source: track.source,
+                    cluster,
+                    embedding,
+                })?;
+            }
+        }
+        if let Some(recorder) = track.recorder.take() {
+            recorder.finish()?;
+        }
+    }
+    if let Some(stats) = pipeline.stats.take() {
+        pipeline.emit(&Event::Stats {
+            speakers: stats.report(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Turns each track's audio into events on `out`.
+struct Pipeline<'a, W: Write> {
+    engine: &'a mut ParakeetEngine,
+    retry: RetryPolicy,
+    out: W,
+    /// Samples of new speech between partial decodes
+    partial_interval: Option<usize>,
+    speakers: Option<&'a Identifier>,
+    captions: Option<CaptionWriter>,
+    stats: Option<Stats>,
+    timestamps: TimestampFormat,
+}
+
+impl<W: Write> Pipeline<'_, W> {
+    fn block(&mut self, track: &mut Track, samples: &[f32]) -> Result<()> {
+        if let Some(meter) = &mut track.meter {
+            for level in meter.push(samples) {
+                let event = Event::Level {
+                    source: track.source,
+                    at: track.offset + level.at,
+                    rms_db: level.rms_db,
+                    peak_db: level.peak_db,
+                };
+                self.emit(&event)?;
+            }
+        }
+
+        for event in track.segmenter.push(samples) {
+            self.vad_event(track, event)?;
+        }
+
+        if let Some(interval) = self.partial_interval {
+            self.partial(track, interval)?;
+        }
+        Ok(())
+    }
+
+    fn vad_event(&mut self, track: &mut Track, event: VadEvent) -> Result<()> {
+        let source = track.source;
+        let offset = track.offset;
+
+        let event = match event {
+            VadEvent::SpeechStart { at } => Event::SpeechStart {
+                source,
+                at: offset + at,
+            },
+            VadEvent::SpeechEnd { at } => Event::SpeechEnd {
+                source,
+                at: offset + at,
+            },
+            VadEvent::Utterance(utterance) => {
+                track.decoded_len = 0;
+                let cluster = track
+                    .speakers
+                    .as_mut()
+                    .and_then(|speakers| speakers.push(&utterance.samples));
+                // Stats time the speech itself, not the silence that
+                // confirmed its end, or every reply would be an interruption.
+                let speech_end = offset + utterance.speech_end;
+                let event = self.transcribe(source, offset, cluster, utterance)?;
+                if let Event::Transcript {
+                    text,
+                    segments,
+                    speaker,
+                    start,
+                    ..
+                } = &event
+                {
+                    if let Some(captions) = &mut self.captions {
+                        captions.append(&words::from_segments(segments), speaker.as_deref())?;
+                    }
+                    if let Some(stats) = &mut self.stats {
+                        let words = words::from_segments(segments).len();
+                        stats.record(source, speaker.as_deref(), *start, speech_end, words);
+                    }
+                    if let Some(dictation) = &mut track.dictation {
+                        if let Some(diff) = dictation.finish(text) {
+                            self.emit_diff(source, diff)?;
+                        }
+                    }
+                    if let Some(stream) = &mut track.words {
+                        let words = stream.finish(words::from_segments(segments));
+                        self.emit_words(source, 0.0, words)?;
+                    }
+                }
+                event
+            }
+        };
+        self.emit(&event)
+    }
+
+    /// Re-decodes the open utterance once enough new speech has arrived.
+    fn partial(&mut self, track: &mut Track, interval: usize) -> Result<()> {
+        let (start, samples) = match track.segmenter.active() {
+            Some((start, samples)) if samples.len() >= track.decoded_len + interval => {
+                (start, samples.to_vec())
+            }
+            _ => return Ok(()),
+        };
+        track.decoded_len = samples.len();
+
+        let result = AudioInput::Samples(samples)
+            .transcribe(self.engine, self.retry)
+            .map_err(|e| anyhow::anyhow!("Transcription failed: {}", e))?;
+        let output = crate::to_output(result, Duration::ZERO);
+
+        let diff = track
+            .dictation
+            .as_mut()
+            .and_then(|dictation| dictation.update(&output.text));
+        if let Some(diff) = diff {
+            self.emit_diff(track.source, diff)?;
+        }
+
+        if let Some(stream) = &mut track.words {
+            let words = stream.update(words::from_segments(&output.segments));
+            self.emit_words(track.source, track.offset + start, words)?;
+        }
+        Ok(())
+    }
+
+    fn transcribe(
+        &mut self,
+        source: Source,
+        offset: f64,
+        cluster: Option<usize>,
+        utterance: stream::Utterance,
+    ) -> Result<Event> {
+        let speaker = self
+            .speakers
+            .and_then(|speakers| speakers.identify(&utterance.samples));
+        let result = AudioInput::Samples(utterance.samples)
+            .transcribe(self.engine, self.retry)
+            .map_err(|e| anyhow::anyhow!("Transcription failed: {}", e))?;
+        let output = crate::to_output(result, Duration::ZERO);
+        let start = offset + utterance.start;
+
+        Ok(Event::Transcript {
+            source,
+            speaker,
+            cluster,
+            start,
+            end: offset + utterance.end,
+            text: output.text,
+            segments: output
+                .segments
+                .into_iter()
+                .map(|mut s| {
+                    s.start += start;
+                    s.end += start;
+                    s
+                })
+                .collect(),
+        })
+    }
+
+    fn emit_diff(&mut self, source: Source, diff: Diff) -> Result<()> {
+        self.emit(&Event::Dictation {
+            source,
+            commit: diff.commit,
+            pending: diff.pending,
+        })
+    }
+
+    /// Sends words whose times are `offset` seconds before the capture's
+    /// timeline.
+    fn emit_words(&mut self, source: Source, offset: f64, words: Vec<words::Word>) -> Result<()> {
+        for word in words {
+            self.emit(&Event::Word {
+                source,
+                start: offset + word.start,
+                end: offset + word.end,
+                text: word.text,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, event: &Event) -> Result<()> {
+        self.out
+            .write_all(&stream::encode_event(event, self.timestamps)?)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn start_mic(tx: Sender<Block>) -> Result<cpal::Stream> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .context("No microphone available")?;
+    let config = device
+        .default_input_config()
+        .context("Failed to query the microphone's input format")?;
+
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build_mic_stream::<f32>(&device, &config.into(), tx)?,
+        cpal::SampleFormat::I16 => build_mic_stream::<i16>(&device, &config.into(), tx)?,
+        cpal::SampleFormat::I32 => build_mic_stream::<i32>(&device, &config.into(), tx)?,
+        other => bail!("Unsupported microphone sample format {:?}", other),
+    };
+    stream.play().context("Failed to start the microphone")?;
+    Ok(stream)
+}
+
+fn build_mic_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    tx: Sender<Block>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels as usize;
+    let mut resampler = Resampler::new(config.sample_rate.0, SAMPLE_RATE);
+
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let interleaved: Vec<f32> = data.iter().map(|s| s.to_sample::<f32>()).collect();
+            let samples = resampler.process(&downmix(&interleaved, channels));
+            let _ = tx.send(Block {
+                source: Source::Mic,
+                samples,
+            });
+        },
+        |e| log::warn!("Microphone stream error: {}", e),
+        None,
+    )?;
+    Ok(stream)
+}
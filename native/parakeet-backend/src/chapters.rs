@@ -0,0 +1,221 @@
+//! Chapters (`--chapters`): splitting a long transcript where the topic
+//! changes.
+//!
+//! This is TextTiling over the word stream. The transcript is cut into
+//! blocks of `BLOCK_WORDS` words and each block gets a TF-IDF vector of its
+//! content words. At every gap between blocks, the summed vectors of the
+//! `WINDOW_BLOCKS` blocks on either side are compared by cosine similarity,
+//! and a change of topic shows up as a dip. Gaps whose dip is clearly
+//! deeper than typical become chapter boundaries, taken deepest first and
+//! kept at least `MIN_CHAPTER_SECS` apart.
+//!
+//! There is no sentence embedding model in the tree, so the vectors are
+//! lexical: a topic revisited in entirely different words is not
+//! recognised. Lectures and talks repeat their terms enough for this to
+//! find the structure. Each chapter is labelled with its most distinctive
+//! words.
+
+use crate::words::{self, Word};
+use crate::Segment;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const BLOCK_WORDS: usize = 20;
+const WINDOW_BLOCKS: usize = 6;
+const MIN_CHAPTER_SECS: f64 = 90.0;
+const KEYWORDS: usize = 3;
+
+/// Common English words that say nothing about the topic. Other languages
+/// rely on the IDF weighting alone.
+#[rustfmt::skip]
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "all", "also", "and", "any", "are", "because", "been", "before",
+    "being", "but", "can", "could", "did", "does", "doing", "don't", "down", "each", "even", "for",
+    "from", "get", "going", "gonna", "got", "had", "has", "have", "her", "here", "him", "his",
+    "how", "into", "it's", "its", "just", "know", "let", "like", "look", "lot", "make", "many",
+    "more", "most", "much", "need", "not", "now", "one", "only", "other", "our", "out", "over",
+    "really", "right", "said", "same", "say", "see", "she", "should", "some", "something", "than",
+    "that", "that's", "the", "their", "them", "then", "there", "these", "they", "thing", "things",
+    "think", "this", "those", "through", "too", "two", "very", "want", "was", "way", "we're",
+    "well", "were", "what", "when", "where", "which", "while", "who", "why", "will", "with",
+    "would", "yeah", "yes", "you", "you're", "your",
+];
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    /// The chapter's most distinctive words, most distinctive first
+    pub keywords: Vec<String>,
+}
+
+struct Block {
+    start: f64,
+    end: f64,
+    counts: HashMap<String, f32>,
+}
+
+/// Groups the transcript into chapters. Transcripts too short to hold two
+/// chapters come back as one.
+pub fn detect(segments: &[Segment]) -> Vec<Chapter> {
+    let words = words::from_segments(segments);
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    let blocks: Vec<Block> = words.chunks(BLOCK_WORDS).map(block).collect();
+    let idf = inverse_document_frequency(&blocks);
+    let vectors: Vec<HashMap<&str, f32>> = blocks
+        .iter()
+        .map(|block| {
+            block
+                .counts
+                .iter()
+                .map(|(term, count)| (term.as_str(), count * idf[term.as_str()]))
+                .collect()
+        })
+        .collect();
+
+    let boundaries = boundaries(&blocks, &vectors);
+    let mut chapters = Vec::with_capacity(boundaries.len() + 1);
+    let mut first = 0;
+    for last in boundaries.into_iter().chain([blocks.len()]) {
+        chapters.push(Chapter {
+            start: blocks[first].start,
+            end: blocks[last - 1].end,
+            keywords: keywords(&vectors[first..last]),
+        });
+        first = last;
+    }
+    chapters
+}
+
+fn block(words: &[Word]) -> Block {
+    let mut counts = HashMap::new();
+    for word in words {
+        for term in terms(&word.text) {
+            *counts.entry(term).or_insert(0.0) += 1.0;
+        }
+    }
+    Block {
+        start: words[0].start,
+        end: words[words.len() - 1].end,
+        counts,
+    }
+}
+
+/// Lowercased content words of `text`.
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(|t| t.trim_matches('\'').to_lowercase())
+        .filter(|t| {
+            t.chars().count() >= 3
+                && !t.chars().all(|c| c.is_ascii_digit())
+                && !STOPWORDS.contains(&t.as_str())
+        })
+}
+
+fn inverse_document_frequency(blocks: &[Block]) -> HashMap<&str, f32> {
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for block in blocks {
+        for term in block.counts.keys() {
+            *document_frequency.entry(term.as_str()).or_insert(0) += 1;
+        }
+    }
+    let n = blocks.len() as f32;
+    document_frequency
+        .into_iter()
+        .map(|(term, df)| (term, (n / df as f32).ln() + 1.0))
+        .collect()
+}
+
+/// Block indices that start a new chapter, in order.
+fn boundaries(blocks: &[Block], vectors: &[HashMap<&str, f32>]) -> Vec<usize> {
+    let n = blocks.len();
+    if n < 2 * WINDOW_BLOCKS {
+        return Vec::new();
+    }
+
+    // similarity[g] compares the windows either side of the gap before block g.
+    let similarity: Vec<f32> = (1..n)
+        .map(|gap| {
+            let left = sum(&vectors[gap.saturating_sub(WINDOW_BLOCKS)..gap]);
+            let right = sum(&vectors[gap..(gap + WINDOW_BLOCKS).min(n)]);
+            cosine(&left, &right)
+        })
+        .collect();
+
+    // How far each gap dips below the nearest peaks on both sides.
+    let depth: Vec<f32> = (0..similarity.len())
+        .map(|i| {
+            let mut left = i;
+            while left > 0 && similarity[left - 1] >= similarity[left] {
+                left -= 1;
+            }
+            let mut right = i;
+            while right + 1 < similarity.len() && similarity[right + 1] >= similarity[right] {
+                right += 1;
+            }
+            (similarity[left] - similarity[i]) + (similarity[right] - similarity[i])
+        })
+        .collect();
+
+    let mean = depth.iter().sum::<f32>() / depth.len() as f32;
+    let variance = depth.iter().map(|d| (d - mean).powi(2)).sum::<f32>() / depth.len() as f32;
+    let cutoff = mean + variance.sqrt() / 2.0;
+
+    let mut candidates: Vec<usize> = (0..depth.len()).filter(|&i| depth[i] > cutoff).collect();
+    candidates.sort_by(|&a, &b| depth[b].total_cmp(&depth[a]));
+
+    let (start, end) = (blocks[0].start, blocks[n - 1].end);
+    let mut chosen: Vec<usize> = Vec::new();
+    for gap in candidates {
+        let block_index = gap + 1;
+        let at = blocks[block_index].start;
+        let far_enough = at - start >= MIN_CHAPTER_SECS
+            && end - at >= MIN_CHAPTER_SECS
+            && chosen
+                .iter()
+                .all(|&b| (blocks[b].start - at).abs() >= MIN_CHAPTER_SECS);
+        if far_enough {
+            chosen.push(block_index);
+        }
+    }
+    chosen.sort_unstable();
+    chosen
+}
+
+fn sum<'a>(vectors: &[HashMap<&'a str, f32>]) -> HashMap<&'a str, f32> {
+    let mut total = HashMap::new();
+    for vector in vectors {
+        for (term, weight) in vector {
+            *total.entry(*term).or_insert(0.0) += weight;
+        }
+    }
+    total
+}
+
+fn cosine(a: &HashMap<&str, f32>, b: &HashMap<&str, f32>) -> f32 {
+    let dot: f32 = a
+        .iter()
+        .filter_map(|(term, x)| b.get(term).map(|y| x * y))
+        .sum();
+    let norm = |v: &HashMap<&str, f32>| v.values().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+fn keywords(vectors: &[HashMap<&str, f32>]) -> Vec<String> {
+    let mut weights: Vec<(&str, f32)> = sum(vectors).into_iter().collect();
+    weights.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+    weights
+        .into_iter()
+        .take(KEYWORDS)
+        .map(|(term, _)| term.to_string())
+        .collect()
+}
@@ -0,0 +1,150 @@
+//! Crash-safe checkpoints for chunked transcription (`--checkpoint-dir`).
+//!
+//! A checkpoint is a JSONL file named after the input's SHA-256: a header
+//! line, then one line per finished chunk with its segments and the sample
+//! offset it ends at. Lines are appended and synced as chunks complete, so
+//! after a crash everything up to the last complete line is intact, and
+//! `--resume` picks up from there. The file is removed once the
+//! transcription finishes.
+
+use crate::{Segment, SCHEMA_VERSION};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct Header<'a> {
+    schema_version: u32,
+    source: &'a Path,
+    source_sha256: &'a str,
+    chunk_samples: usize,
+}
+
+/// One finished chunk.
+#[derive(Serialize)]
+pub struct ChunkRecord<'a> {
+    /// Sample offset just past this chunk; transcription resumes here
+    pub end_sample: usize,
+    pub text: &'a str,
+    pub segments: &'a [Segment],
+}
+
+#[derive(Deserialize)]
+struct StoredHeader {
+    source_sha256: String,
+}
+
+/// A finished chunk read back from disk.
+#[derive(Deserialize)]
+pub struct StoredChunk {
+    pub end_sample: usize,
+    pub text: String,
+    pub segments: Vec<Segment>,
+}
+
+pub struct Checkpoint {
+    path: PathBuf,
+    file: File,
+}
+
+pub fn path_for(dir: &Path, source_sha256: &str) -> PathBuf {
+    dir.join(format!("{}.checkpoint.jsonl", source_sha256))
+}
+
+impl Checkpoint {
+    /// Starts a fresh checkpoint, replacing any left over for this input.
+    pub fn create(
+        dir: &Path,
+        source: &Path,
+        source_sha256: &str,
+        chunk_samples: usize,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create checkpoint directory {}", dir.display()))?;
+        let path = path_for(dir, source_sha256);
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create checkpoint {}", path.display()))?;
+
+        let mut checkpoint = Self { path, file };
+        checkpoint.write_line(&Header {
+            schema_version: SCHEMA_VERSION,
+            source,
+            source_sha256,
+            chunk_samples,
+        })?;
+        Ok(checkpoint)
+    }
+
+    /// Reopens the checkpoint left for this input, if any. Every chunk it
+    /// holds is passed to `replay` in order, and the returned checkpoint
+    /// appends after them. Also returns the sample offset to continue from.
+    pub fn resume(
+        dir: &Path,
+        source_sha256: &str,
+        mut replay: impl FnMut(StoredChunk) -> Result<()>,
+    ) -> Result<Option<(Self, usize)>> {
+        let path = path_for(dir, source_sha256);
+        let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to open checkpoint {}", path.display()))
+            }
+        };
+
+        let mut reader = BufReader::new(&file);
+        let mut line = Vec::new();
+
+        reader.read_until(b'\n', &mut line)?;
+        let header: StoredHeader = serde_json::from_slice(&line)
+            .with_context(|| format!("Checkpoint {} is corrupt", path.display()))?;
+        if header.source_sha256 != source_sha256 {
+            bail!("Checkpoint {} belongs to a different input", path.display());
+        }
+
+        // Everything up to `valid_len` is the header and complete chunks. A
+        // line cut short by the crash is truncated away and redone.
+        let mut valid_len = line.len() as u64;
+        let mut resume_at = 0;
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 || !line.ends_with(b"\n") {
+                break;
+            }
+            let Ok(chunk) = serde_json::from_slice::<StoredChunk>(&line) else {
+                break;
+            };
+            valid_len += line.len() as u64;
+            resume_at = chunk.end_sample;
+            replay(chunk)?;
+        }
+        drop(reader);
+
+        file.set_len(valid_len)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Some((Self { path, file }, resume_at)))
+    }
+
+    pub fn record(&mut self, record: &ChunkRecord<'_>) -> Result<()> {
+        self.write_line(record)
+    }
+
+    /// The transcription completed, so the checkpoint is no longer needed.
+    pub fn remove(self) -> Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+            .with_context(|| format!("Failed to remove checkpoint {}", self.path.display()))
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        // A checkpoint is only useful if it survives power loss.
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! Optional compression of CLI output (`--compress`).
+//!
+//! Word-level JSON for a multi-hour recording runs to tens of megabytes, so
+//! callers that read it back over a pipe can ask for it compressed.
+
+use flate2::write::GzEncoder;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+// zstd's default level; higher levels cost far more time than they save here.
+const ZSTD_LEVEL: i32 = 3;
+
+/// A writer that compresses into `inner`. Call `finish` when done so the
+/// trailer is written; dropping it without finishing truncates the stream.
+pub enum Output<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(inner: W, compression: Option<Compression>) -> io::Result<Self> {
+        Ok(match compression {
+            None => Output::Plain(inner),
+            Some(Compression::Gzip) => {
+                Output::Gzip(GzEncoder::new(inner, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => Output::Zstd(zstd::Encoder::new(inner, ZSTD_LEVEL)?),
+        })
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        let mut inner = match self {
+            Output::Plain(inner) => inner,
+            Output::Gzip(encoder) => encoder.finish()?,
+            Output::Zstd(encoder) => encoder.finish()?,
+        };
+        inner.flush()
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            Output::Gzip(w) => w.write(buf),
+            Output::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            Output::Gzip(w) => w.flush(),
+            Output::Zstd(w) => w.flush(),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Paralinguistic cues (`--cues`): questions, hesitations and shouting.
+//!
+//! A light pass over the audio and the word timings, run after
+//! transcription. The transcript is split into phrases at sentence
+//! punctuation and pauses, then:
+//!
+//! - a phrase is a `question` when it ends in "?" or its pitch rises over
+//!   its last stretch,
+//! - a pause of `HESITATION_SECS` or more between words is a `hesitation`,
+//! - a phrase is `shouting` when it is loud in absolute terms and well
+//!   above the recording's typical phrase level.
+//!
+//! Thresholds are fixed heuristics meant for surfacing moments in meeting
+//! notes, not for acoustic research.
+
+use crate::incremental::SAMPLE_RATE;
+use crate::stream::rms_db;
+use crate::words::{self, Word};
+use crate::Segment;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Silence between words that counts as a hesitation
+const HESITATION_SECS: f64 = 1.0;
+/// Silence between words that ends a phrase
+const PHRASE_GAP_SECS: f64 = 0.4;
+/// End of a phrase whose pitch is compared against the rest
+const TAIL_SECS: f64 = 0.4;
+/// Pitch rise over the tail that makes a question
+const RISE_RATIO: f32 = 1.12;
+const SHOUT_MIN_DB: f32 = -14.0;
+const SHOUT_ABOVE_TYPICAL_DB: f32 = 9.0;
+
+/// Pitch is tracked on audio decimated to 8 kHz, in 40 ms frames every 20 ms
+const PITCH_RATE: f32 = SAMPLE_RATE as f32 / 2.0;
+const PITCH_FRAME: usize = 320;
+const PITCH_HOP: usize = 160;
+/// Only this much speech before the tail is compared against it
+const BODY_SECS: f64 = 1.2;
+const MIN_F0: f32 = 70.0;
+const MAX_F0: f32 = 400.0;
+const VOICING: f32 = 0.5;
+const OCTAVE_MARGIN: f32 = 0.9;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CueKind {
+    Question,
+    Hesitation,
+    Shouting,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Cue {
+    pub kind: CueKind,
+    pub start: f64,
+    pub end: f64,
+    /// The phrase the cue applies to; empty for hesitations
+    pub text: String,
+}
+
+struct Phrase {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+/// Finds cues in 16 kHz mono `samples` given the transcript's segments.
+pub fn analyze(samples: &[f32], segments: &[Segment]) -> Vec<Cue> {
+    let words = words::from_segments(segments);
+    let mut cues = Vec::new();
+
+    for pair in words.windows(2) {
+        if pair[1].start - pair[0].end >= HESITATION_SECS {
+            cues.push(Cue {
+                kind: CueKind::Hesitation,
+                start: pair[0].end,
+                end: pair[1].start,
+                text: String::new(),
+            });
+        }
+    }
+
+    let phrases = phrases(&words);
+    let levels: Vec<f32> = phrases
+        .iter()
+        .map(|p| rms_db(slice(samples, p.start, p.end)))
+        .collect();
+    let typical = median(levels.iter().copied().filter(|l| l.is_finite()).collect());
+
+    for (phrase, level) in phrases.into_iter().zip(levels) {
+        let audio = slice(samples, phrase.start, phrase.end);
+        let question = phrase.text.ends_with('?') || rises(audio);
+        let shouting = typical.is_some_and(|typical| {
+            level >= SHOUT_MIN_DB && level - typical >= SHOUT_ABOVE_TYPICAL_DB
+        });
+
+        for (kind, applies) in [(CueKind::Question, question), (CueKind::Shouting, shouting)] {
+            if applies {
+                cues.push(Cue {
+                    kind,
+                    start: phrase.start,
+                    end: phrase.end,
+                    text: phrase.text.clone(),
+                });
+            }
+        }
+    }
+
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    cues
+}
+
+fn phrases(words: &[Word]) -> Vec<Phrase> {
+    let mut phrases: Vec<Phrase> = Vec::new();
+    let mut open = false;
+
+    for word in words {
+        match phrases.last_mut() {
+            Some(phrase) if open && word.start - phrase.end < PHRASE_GAP_SECS => {
+                phrase.end = word.end;
+                phrase.text.push(' ');
+                phrase.text.push_str(&word.text);
+            }
+            _ => phrases.push(Phrase {
+                start: word.start,
+                end: word.end,
+                text: word.text.clone(),
+            }),
+        }
+        open = !word.text.ends_with(['.', '?', '!']);
+    }
+
+    phrases
+}
+
+/// Whether the pitch over the last `TAIL_SECS` is clearly above the rest
+/// of the phrase.
+fn rises(audio: &[f32]) -> bool {
+    let tail_len = (TAIL_SECS * SAMPLE_RATE as f64) as usize;
+    if audio.len() < 2 * tail_len {
+        return false;
+    }
+    let (body, tail) = audio.split_at(audio.len() - tail_len);
+    let body_len = (BODY_SECS * SAMPLE_RATE as f64) as usize;
+    let body = &body[body.len().saturating_sub(body_len)..];
+
+    match (median(pitch_track(body)), median(pitch_track(tail))) {
+        (Some(body), Some(tail)) => tail >= body * RISE_RATIO,
+        _ => false,
+    }
+}
+
+/// F0 estimates of the voiced frames, by autocorrelation.
+fn pitch_track(audio: &[f32]) -> Vec<f32> {
+    let audio: Vec<f32> = audio.chunks_exact(2).map(|p| (p[0] + p[1]) * 0.5).collect();
+    let min_lag = (PITCH_RATE / MAX_F0) as usize;
+    let max_lag = (PITCH_RATE / MIN_F0) as usize;
+    let mut track = Vec::new();
+
+    let mut start = 0;
+    while start + PITCH_FRAME + max_lag <= audio.len() {
+        let frame = &audio[start..start + PITCH_FRAME];
+        let energy: f32 = frame.iter().map(|s| s * s).sum();
+
+        if energy >= 1e-4 {
+            let correlations: Vec<f32> = (min_lag..=max_lag)
+                .map(|lag| {
+                    let shifted = &audio[start + lag..start + lag + PITCH_FRAME];
+                    frame.iter().zip(shifted).map(|(x, y)| x * y).sum()
+                })
+                .collect();
+            let best = correlations.iter().copied().fold(0.0, f32::max);
+            // Multiples of the period correlate almost as well; take the
+            // peak at the shortest lag that comes close, not the octave below.
+            let first = correlations.iter().position(|&c| c >= OCTAVE_MARGIN * best);
+            if let Some(mut lag) = first.filter(|_| best >= VOICING * energy) {
+                while lag + 1 < correlations.len() && correlations[lag + 1] > correlations[lag] {
+                    lag += 1;
+                }
+                track.push(PITCH_RATE / (min_lag + lag) as f32);
+            }
+        }
+        start += PITCH_HOP;
+    }
+
+    track
+}
+
+fn slice(samples: &[f32], start: f64, end: f64) -> &[f32] {
+    let at = |secs: f64| ((secs.max(0.0) * SAMPLE_RATE as f64) as usize).min(samples.len());
+    &samples[at(start)..at(end).max(at(start))]
+}
+
+/// Median of at least three values.
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    if values.len() < 3 {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+    Some(values[values.len() / 2])
+}
@@ -0,0 +1,32 @@
+// Environment probes for `doctor` that only the Objective-C frameworks can
+// answer: microphone permission and the GPU/Neural Engine frameworks.
+
+#import <AVFoundation/AVFoundation.h>
+#import <Foundation/Foundation.h>
+#import <Metal/Metal.h>
+
+// AVAuthorizationStatus: 0 not determined, 1 restricted, 2 denied,
+// 3 authorized.
+int pk_microphone_authorization(void) {
+    return (int)[AVCaptureDevice authorizationStatusForMediaType:AVMediaTypeAudio];
+}
+
+// Copies the default Metal device's name into `name`; returns 0 when the
+// machine has no Metal device.
+int pk_metal_device(char *name, size_t name_len) {
+    @autoreleasepool {
+        id<MTLDevice> device = MTLCreateSystemDefaultDevice();
+        if (device == nil) {
+            return 0;
+        }
+        if (name_len > 0) {
+            snprintf(name, name_len, "%s", device.name.UTF8String);
+        }
+        return 1;
+    }
+}
+
+// Whether Core ML can be loaded, without linking against it.
+int pk_coreml_available(void) {
+    return [[NSBundle bundleWithPath:@"/System/Library/Frameworks/CoreML.framework"] load];
+}
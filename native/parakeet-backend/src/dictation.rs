@@ -0,0 +1,142 @@
+//! Stable-prefix diffs for dictation (`capture --dictation`).
+//!
+//! While an utterance is still being spoken it is re-decoded periodically.
+//! Text on which two consecutive hypotheses agree is committed and never
+//! revised, so the host can type it straight away; the rest is reported as
+//! pending and may change. Each diff carries only the newly committed text
+//! plus the current pending tail.
+//!
+//! `WordStream` applies the same rule a word at a time, keeping timings.
+
+use crate::words::Word;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diff {
+    /// Text committed since the previous diff, to append as-is
+    pub commit: String,
+    /// Uncommitted tail of the current hypothesis
+    pub pending: String,
+}
+
+#[derive(Default)]
+pub struct Dictation {
+    /// Committed so far in the current utterance
+    committed: String,
+    /// Last hypothesis, compared against the next one
+    previous: String,
+    /// Pending tail of the last diff sent
+    pending: String,
+    /// An earlier utterance committed text, so the next commit starts with
+    /// a space
+    needs_space: bool,
+}
+
+impl Dictation {
+    /// Takes a partial hypothesis for the utterance in progress.
+    pub fn update(&mut self, hypothesis: &str) -> Option<Diff> {
+        let hypothesis = hypothesis.trim();
+        let stable = common_prefix(&self.previous, hypothesis);
+        let newly_stable =
+            if stable.len() > self.committed.len() && stable.starts_with(&self.committed) {
+                stable[self.committed.len()..].to_string()
+            } else {
+                String::new()
+            };
+        self.previous = hypothesis.to_string();
+
+        let commit = self.commit(&newly_stable);
+        let pending = tail_after(hypothesis, &self.committed).to_string();
+        if commit.is_empty() && pending == self.pending {
+            return None;
+        }
+        self.pending = pending.clone();
+        Some(Diff { commit, pending })
+    }
+
+    /// Commits whatever of the final transcript is not committed yet and
+    /// resets for the next utterance.
+    pub fn finish(&mut self, text: &str) -> Option<Diff> {
+        let rest = tail_after(text.trim(), &self.committed).to_string();
+        let commit = self.commit(&rest);
+        let had_pending = !std::mem::take(&mut self.pending).is_empty();
+
+        self.needs_space |= !self.committed.is_empty();
+        self.committed.clear();
+        self.previous.clear();
+
+        (!commit.is_empty() || had_pending).then(|| Diff {
+            commit,
+            pending: String::new(),
+        })
+    }
+
+    fn commit(&mut self, text: &str) -> String {
+        if text.is_empty() {
+            return String::new();
+        }
+        self.committed.push_str(text);
+        if std::mem::take(&mut self.needs_space) {
+            format!(" {}", text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Word-at-a-time counterpart of `Dictation` (`capture --word-events`). A
+/// word is sent once two consecutive hypotheses agree on it and on every
+/// word before it; the final transcript sends whatever is left.
+#[derive(Default)]
+pub struct WordStream {
+    /// Words of the last hypothesis
+    previous: Vec<String>,
+    /// Words of the current utterance already sent
+    sent: usize,
+}
+
+impl WordStream {
+    /// Takes the words of a partial hypothesis and returns those that just
+    /// settled.
+    pub fn update(&mut self, words: Vec<Word>) -> Vec<Word> {
+        let stable = self
+            .previous
+            .iter()
+            .zip(&words)
+            .take_while(|(previous, word)| **previous == word.text)
+            .count();
+        self.previous = words.iter().map(|w| w.text.clone()).collect();
+
+        if stable <= self.sent {
+            return Vec::new();
+        }
+        let settled = words.into_iter().take(stable).skip(self.sent).collect();
+        self.sent = stable;
+        settled
+    }
+
+    /// Returns the final transcript's words not sent yet and resets for the
+    /// next utterance.
+    pub fn finish(&mut self, words: Vec<Word>) -> Vec<Word> {
+        let sent = std::mem::take(&mut self.sent);
+        self.previous.clear();
+        words.into_iter().skip(sent).collect()
+    }
+}
+
+/// Longest common prefix, ending on a character boundary.
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()));
+    &a[..len]
+}
+
+/// What `text` holds beyond `committed`. When the model has since revised
+/// committed text, everything after the point where they diverge.
+fn tail_after<'a>(text: &'a str, committed: &str) -> &'a str {
+    let shared = common_prefix(text, committed).len();
+    &text[shared..]
+}
@@ -0,0 +1,323 @@
+//! `doctor`: the environment facts support asks for first, as one JSON
+//! report.
+//!
+//! Covers the OS and architecture (including Rosetta), physical and
+//! available memory, whether the machine offers Metal and Core ML, the
+//! health of every downloaded Parakeet model, and microphone permission.
+//! Metal and Core ML are reported as what the machine offers; whether the
+//! engine uses them depends on how ONNX Runtime was built.
+//! Anything that would stop transcription is listed under `problems`, and
+//! the command fails after printing the report when there are any.
+
+use crate::{model, SCHEMA_VERSION};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Memory below which long recordings are likely to be refused or swap
+const LOW_MEMORY_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Serialize)]
+struct Report {
+    schema_version: u32,
+    os: Os,
+    memory: Memory,
+    acceleration: Acceleration,
+    models: Vec<ModelHealth>,
+    microphone: Microphone,
+    problems: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Os {
+    name: &'static str,
+    version: Option<String>,
+    arch: &'static str,
+    /// Running an x86_64 build under Rosetta on Apple silicon
+    translated: bool,
+    cpus: usize,
+}
+
+#[derive(Serialize)]
+struct Memory {
+    total_bytes: Option<u64>,
+    /// Free memory the system can hand out without paging
+    available_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Acceleration {
+    metal_device: Option<String>,
+    coreml: bool,
+}
+
+#[derive(Serialize)]
+struct ModelHealth {
+    path: PathBuf,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+enum Microphone {
+    NotDetermined,
+    Restricted,
+    Denied,
+    Authorized,
+    /// Not a macOS build, where there is no permission to ask for
+    Unknown,
+}
+
+/// Checks the environment, `model` and every Parakeet model under the
+/// app's model directory.
+pub fn run(model: Option<&Path>) -> Result<()> {
+    let mut models: Vec<ModelHealth> = model.into_iter().map(check_model).collect();
+    for dir in downloaded_models() {
+        if model != Some(dir.as_path()) {
+            models.push(check_model(&dir));
+        }
+    }
+
+    let mut report = Report {
+        schema_version: SCHEMA_VERSION,
+        os: Os {
+            name: std::env::consts::OS,
+            version: os_version(),
+            arch: std::env::consts::ARCH,
+            translated: sysctl_u64("sysctl.proc_translated") == Some(1),
+            cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        },
+        memory: Memory {
+            total_bytes: total_memory(),
+            available_bytes: available_memory(),
+        },
+        acceleration: acceleration(),
+        models,
+        microphone: microphone(),
+        problems: Vec::new(),
+    };
+    report.problems = problems(&report);
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.problems.is_empty() {
+        bail!("{} problem(s) found", report.problems.len());
+    }
+    Ok(())
+}
+
+fn problems(report: &Report) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if report.os.translated {
+        problems.push(
+            "Running under Rosetta; install the Apple silicon build for full speed".to_string(),
+        );
+    }
+    if let Some(available) = report
+        .memory
+        .available_bytes
+        .filter(|&b| b < LOW_MEMORY_BYTES)
+    {
+        problems.push(format!(
+            "Only {} MB of memory is available; long recordings may be slow or refused",
+            available / (1024 * 1024)
+        ));
+    }
+    if report.models.is_empty() {
+        problems.push("No Parakeet model is downloaded".to_string());
+    }
+    for health in &report.models {
+        if let Some(error) = &health.error {
+            problems.push(error.clone());
+        }
+    }
+    match report.microphone {
+        Microphone::Denied => problems.push(
+            "Microphone access is denied; allow it in System Settings > Privacy & Security > Microphone"
+                .to_string(),
+        ),
+        Microphone::Restricted => {
+            problems.push("Microphone access is restricted by a device policy".to_string())
+        }
+        _ => {}
+    }
+
+    problems
+}
+
+fn check_model(dir: &Path) -> ModelHealth {
+    let error = model::validate(dir).err().map(|e| format!("{:#}", e));
+    ModelHealth {
+        path: dir.to_path_buf(),
+        ok: error.is_none(),
+        error,
+    }
+}
+
+/// `parakeet*` directories under the app's model directory, which follows
+/// `WHISPER_MAC_DATA_DIR` like the app does.
+fn downloaded_models() -> Vec<PathBuf> {
+    let data_dir = match std::env::var_os("WHISPER_MAC_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join("Library/Application Support/WhisperMac"),
+            None => return Vec::new(),
+        },
+    };
+    let Ok(entries) = fs::read_dir(data_dir.join("models")) else {
+        return Vec::new();
+    };
+
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("parakeet"))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+#[cfg(target_os = "macos")]
+fn sysctl_u64(name: &str) -> Option<u64> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let mut value = [0u8; 8];
+    let mut len = value.len();
+    let status = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    // Integer sysctls are either 4 or 8 bytes wide.
+    match (status, len) {
+        (0, 4) => Some(u32::from_ne_bytes([value[0], value[1], value[2], value[3]]) as u64),
+        (0, 8) => Some(u64::from_ne_bytes(value)),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn sysctl_u64(_name: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "macos")]
+fn os_version() -> Option<String> {
+    let name = std::ffi::CString::new("kern.osproductversion").ok()?;
+    let mut value = [0u8; 64];
+    let mut len = value.len();
+    let status = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    let version = std::ffi::CStr::from_bytes_until_nul(&value[..len]).ok()?;
+    Some(version.to_string_lossy().into_owned())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn os_version() -> Option<String> {
+    let release = fs::read_to_string("/etc/os-release").ok()?;
+    release
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        .map(|name| name.trim_matches('"').to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn total_memory() -> Option<u64> {
+    sysctl_u64("hw.memsize")
+}
+
+#[cfg(target_os = "macos")]
+fn available_memory() -> Option<u64> {
+    let free = sysctl_u64("vm.page_free_count")?;
+    let speculative = sysctl_u64("vm.page_speculative_count").unwrap_or(0);
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+    Some((free + speculative) * page_size)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn total_memory() -> Option<u64> {
+    meminfo("MemTotal:")
+}
+
+#[cfg(not(target_os = "macos"))]
+fn available_memory() -> Option<u64> {
+    meminfo("MemAvailable:")
+}
+
+#[cfg(not(target_os = "macos"))]
+fn meminfo(key: &str) -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let kb: u64 = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix(key))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn pk_microphone_authorization() -> std::ffi::c_int;
+    fn pk_metal_device(name: *mut std::ffi::c_char, name_len: usize) -> std::ffi::c_int;
+    fn pk_coreml_available() -> std::ffi::c_int;
+}
+
+#[cfg(target_os = "macos")]
+fn acceleration() -> Acceleration {
+    let mut name = [0 as std::ffi::c_char; 128];
+    let metal_device =
+        (unsafe { pk_metal_device(name.as_mut_ptr(), name.len()) } != 0).then(|| {
+            unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        });
+    Acceleration {
+        metal_device,
+        coreml: unsafe { pk_coreml_available() } != 0,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn acceleration() -> Acceleration {
+    Acceleration {
+        metal_device: None,
+        coreml: false,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn microphone() -> Microphone {
+    match unsafe { pk_microphone_authorization() } {
+        0 => Microphone::NotDetermined,
+        1 => Microphone::Restricted,
+        2 => Microphone::Denied,
+        3 => Microphone::Authorized,
+        _ => Microphone::Unknown,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn microphone() -> Microphone {
+    Microphone::Unknown
+}
@@ -0,0 +1,465 @@
+//! Conversion of captured audio to the 16 kHz mono the engine expects, plus
+//! the level measurements taken on every captured frame.
+//!
+//! For a short dictation clip inference takes only tens of milliseconds, so
+//! this per-sample work shows up in end-to-end latency. The hot loops
+//! (downmixing, the resampler's anti-aliasing filter, 16-bit PCM to float
+//! conversion and the level measurements) have NEON versions on Apple
+//! Silicon; other targets use plain loops.
+
+use std::f64::consts::PI;
+
+#[cfg(target_arch = "aarch64")]
+pub use neon::{pcm16_to_f32, peak, sum_squares};
+#[cfg(not(target_arch = "aarch64"))]
+pub use scalar::{pcm16_to_f32, peak, sum_squares};
+
+#[cfg(target_arch = "aarch64")]
+use neon::dot;
+#[cfg(not(target_arch = "aarch64"))]
+use scalar::dot;
+
+#[cfg(target_arch = "aarch64")]
+use neon::downmix_stereo;
+#[cfg(not(target_arch = "aarch64"))]
+use scalar::downmix_stereo;
+
+/// Averages interleaved frames down to one channel.
+pub fn downmix(interleaved: &[f32], channels: usize) -> Vec<f32> {
+    match channels {
+        0 | 1 => interleaved.to_vec(),
+        2 => downmix_stereo(interleaved),
+        _ => {
+            let scale = 1.0 / channels as f32;
+            interleaved
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() * scale)
+                .collect()
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    // NEON is part of the aarch64 baseline, so the intrinsics need no
+    // runtime feature check.
+
+    pub fn downmix_stereo(interleaved: &[f32]) -> Vec<f32> {
+        let frames = interleaved.len() / 2;
+        let blocks = frames / 4;
+        let mut output = Vec::with_capacity(frames);
+
+        unsafe {
+            let half = vdupq_n_f32(0.5);
+            for i in 0..blocks {
+                let frame = vld2q_f32(interleaved.as_ptr().add(i * 8));
+                let mono = vmulq_f32(vaddq_f32(frame.0, frame.1), half);
+                vst1q_f32(output.as_mut_ptr().add(i * 4), mono);
+            }
+            output.set_len(blocks * 4);
+        }
+
+        output.extend(
+            interleaved[blocks * 8..frames * 2]
+                .chunks_exact(2)
+                .map(|frame| (frame[0] + frame[1]) * 0.5),
+        );
+        output
+    }
+
+    pub fn sum_squares(samples: &[f32]) -> f32 {
+        let blocks = samples.chunks_exact(4);
+        let rest: f32 = blocks.remainder().iter().map(|s| s * s).sum();
+
+        unsafe {
+            let mut acc = vdupq_n_f32(0.0);
+            for block in blocks {
+                let v = vld1q_f32(block.as_ptr());
+                acc = vfmaq_f32(acc, v, v);
+            }
+            vaddvq_f32(acc) + rest
+        }
+    }
+
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let blocks = len / 4;
+        let rest: f32 = a[blocks * 4..len]
+            .iter()
+            .zip(&b[blocks * 4..len])
+            .map(|(x, y)| x * y)
+            .sum();
+
+        unsafe {
+            let mut acc = vdupq_n_f32(0.0);
+            for i in 0..blocks {
+                let x = vld1q_f32(a.as_ptr().add(i * 4));
+                let y = vld1q_f32(b.as_ptr().add(i * 4));
+                acc = vfmaq_f32(acc, x, y);
+            }
+            vaddvq_f32(acc) + rest
+        }
+    }
+
+    /// Little-endian 16-bit PCM as samples in [-1, 1).
+    pub fn pcm16_to_f32(bytes: &[u8]) -> Vec<f32> {
+        let count = bytes.len() / 2;
+        let blocks = count / 8;
+        let mut output = Vec::with_capacity(count);
+
+        unsafe {
+            let scale = vdupq_n_f32(1.0 / 32_768.0);
+            for i in 0..blocks {
+                // Byte loads have no alignment requirement; the bytes are
+                // already in the lanes' little-endian order.
+                let pcm = vreinterpretq_s16_u8(vld1q_u8(bytes.as_ptr().add(i * 16)));
+                let low = vcvtq_f32_s32(vmovl_s16(vget_low_s16(pcm)));
+                let high = vcvtq_f32_s32(vmovl_high_s16(pcm));
+                vst1q_f32(output.as_mut_ptr().add(i * 8), vmulq_f32(low, scale));
+                vst1q_f32(output.as_mut_ptr().add(i * 8 + 4), vmulq_f32(high, scale));
+            }
+            output.set_len(blocks * 8);
+        }
+
+        output.extend(
+            bytes[blocks * 16..count * 2]
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0),
+        );
+        output
+    }
+
+    /// Largest absolute sample, 0 for no samples.
+    pub fn peak(samples: &[f32]) -> f32 {
+        let blocks = samples.chunks_exact(4);
+        let rest = blocks
+            .remainder()
+            .iter()
+            .fold(0.0f32, |peak, s| peak.max(s.abs()));
+
+        unsafe {
+            let mut acc = vdupq_n_f32(0.0);
+            for block in blocks {
+                acc = vmaxq_f32(acc, vabsq_f32(vld1q_f32(block.as_ptr())));
+            }
+            vmaxvq_f32(acc).max(rest)
+        }
+    }
+}
+
+// Also built for tests on Apple Silicon, as the reference for the NEON
+// versions.
+#[cfg(any(not(target_arch = "aarch64"), test))]
+mod scalar {
+    pub fn downmix_stereo(interleaved: &[f32]) -> Vec<f32> {
+        interleaved
+            .chunks_exact(2)
+            .map(|frame| (frame[0] + frame[1]) * 0.5)
+            .collect()
+    }
+
+    pub fn sum_squares(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum()
+    }
+
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    /// Little-endian 16-bit PCM as samples in [-1, 1).
+    pub fn pcm16_to_f32(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0)
+            .collect()
+    }
+
+    /// Largest absolute sample, 0 for no samples.
+    pub fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+}
+
+/// Taps of the low-pass filter run ahead of downsampling
+const LOWPASS_TAPS: usize = 32;
+
+/// Streaming windowed-sinc low-pass filter with its cutoff just under the
+/// output rate's Nyquist frequency, so that noise and music above it do not
+/// fold down into the speech band when the rate drops. It delays the
+/// signal by about 16 input samples (a third of a millisecond at 48 kHz).
+struct Lowpass {
+    taps: Vec<f32>,
+    /// The last `LOWPASS_TAPS - 1` input samples of the previous block
+    history: Vec<f32>,
+}
+
+impl Lowpass {
+    /// `step` is input samples per output sample.
+    fn new(step: f64) -> Self {
+        // In cycles per input sample, a little under the output's Nyquist.
+        let cutoff = 0.45 / step;
+        let center = (LOWPASS_TAPS - 1) as f64 / 2.0;
+        let taps: Vec<f64> = (0..LOWPASS_TAPS)
+            .map(|i| {
+                // Never zero: an even number of taps has no center tap.
+                let x = i as f64 - center;
+                let sinc = (2.0 * PI * cutoff * x).sin() / (PI * x);
+                let hamming = 0.54 - 0.46 * (2.0 * PI * i as f64 / (LOWPASS_TAPS - 1) as f64).cos();
+                sinc * hamming
+            })
+            .collect();
+        // Unity gain at DC. The taps are symmetric, so the filter needs no
+        // reversal for the dot products below.
+        let sum: f64 = taps.iter().sum();
+
+        Self {
+            taps: taps.iter().map(|t| (t / sum) as f32).collect(),
+            history: vec![0.0; LOWPASS_TAPS - 1],
+        }
+    }
+
+    fn filter(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut buffer = std::mem::take(&mut self.history);
+        buffer.extend_from_slice(input);
+        let output = buffer
+            .windows(LOWPASS_TAPS)
+            .map(|window| dot(window, &self.taps))
+            .collect();
+        self.history = buffer.split_off(buffer.len() - (LOWPASS_TAPS - 1));
+        output
+    }
+}
+
+/// Streaming resampler: a low-pass filter when the rate drops, then linear
+/// interpolation. Capture devices run at 44.1 or 48 kHz and speech sits
+/// well below the new Nyquist, so linear is enough for recognition once
+/// everything above it is filtered out.
+pub struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output sample. Position 0 is the last sample
+    /// of the previous block and position k is `input[k - 1]`.
+    position: f64,
+    previous: f32,
+    lowpass: Option<Lowpass>,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        let step = from_rate as f64 / to_rate as f64;
+        Self {
+            step,
+            position: 1.0,
+            previous: 0.0,
+            lowpass: (step > 1.0).then(|| Lowpass::new(step)),
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.step == 1.0 || input.is_empty() {
+            return input.to_vec();
+        }
+        let filtered;
+        let input = match &mut self.lowpass {
+            Some(lowpass) => {
+                filtered = lowpass.filter(input);
+                filtered.as_slice()
+            }
+            None => input,
+        };
+        if self.step.fract() == 0.0 && self.position.fract() == 0.0 {
+            return self.decimate(input);
+        }
+
+        let previous = self.previous;
+        let at = |k: usize| if k == 0 { previous } else { input[k - 1] };
+        let len = input.len() as f64;
+        let mut output = Vec::with_capacity((len / self.step) as usize + 1);
+
+        while self.position < len {
+            let k = self.position as usize;
+            let frac = (self.position - k as f64) as f32;
+            let (a, b) = (at(k), at(k + 1));
+            output.push(a + (b - a) * frac);
+            self.position += self.step;
+        }
+
+        self.position -= len;
+        self.previous = input[input.len() - 1];
+        output
+    }
+
+    /// Whole-number ratios such as 48 kHz to 16 kHz land every output
+    /// sample exactly on an input sample, so no interpolation is needed.
+    fn decimate(&mut self, input: &[f32]) -> Vec<f32> {
+        let step = self.step as usize;
+        let start = self.position as usize;
+        // Position 0 is the previous block's last sample.
+        let mut output = Vec::with_capacity(input.len() / step + 1);
+        if start == 0 {
+            output.push(self.previous);
+        }
+        let first = if start == 0 { step } else { start };
+        // The last sample sits at position `len`, which belongs to the
+        // next block.
+        output.extend(
+            input[..input.len() - 1]
+                .iter()
+                .skip(first - 1)
+                .step_by(step)
+                .copied(),
+        );
+
+        let next = start + output.len() * step;
+        self.position = (next - input.len()) as f64;
+        self.previous = input[input.len() - 1];
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lengths around every multiple of the 4- and 8-lane block sizes up
+    /// to a few blocks, plus long ones that end mid-block.
+    const LENS: [usize; 23] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 11, 12, 13, 15, 16, 17, 23, 31, 33, 63, 65, 1001, 4099,
+    ];
+
+    fn signal(len: usize, mut seed: u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    /// The NEON versions add up the `terms` products in a different order,
+    /// so the sums may differ by rounding. Either order is within about
+    /// `terms` epsilons of `magnitude`, the sum of the products' sizes, of
+    /// the exact sum.
+    fn assert_close(actual: f32, expected: f32, magnitude: f32, terms: usize) {
+        let tolerance = terms as f32 * f32::EPSILON * magnitude;
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} differs from {} by more than {}",
+            actual,
+            expected,
+            tolerance
+        );
+    }
+
+    #[test]
+    fn downmix_stereo_matches_scalar() {
+        for len in LENS {
+            // Odd lengths leave half a frame, which both versions drop.
+            let interleaved = signal(len, 1);
+            assert_eq!(
+                downmix_stereo(&interleaved),
+                scalar::downmix_stereo(&interleaved),
+                "{} samples",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn pcm16_to_f32_matches_scalar() {
+        for len in LENS {
+            // Odd lengths leave half a sample, which both versions drop.
+            let mut seed = 7u32;
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (seed >> 24) as u8
+                })
+                .collect();
+            assert_eq!(
+                pcm16_to_f32(&bytes),
+                scalar::pcm16_to_f32(&bytes),
+                "{} bytes",
+                len
+            );
+        }
+        let extremes = [0x00, 0x80, 0xff, 0x7f, 0xff, 0xff, 0x01, 0x00, 0x00, 0x00];
+        assert_eq!(
+            pcm16_to_f32(&extremes),
+            [
+                -1.0,
+                32_767.0 / 32_768.0,
+                -1.0 / 32_768.0,
+                1.0 / 32_768.0,
+                0.0
+            ]
+        );
+    }
+
+    #[test]
+    fn peak_matches_scalar() {
+        for len in LENS {
+            let mut samples = signal(len, 3);
+            assert_eq!(peak(&samples), scalar::peak(&samples), "{} samples", len);
+            // The loudest sample in the tail past the last whole block.
+            if let Some(last) = samples.last_mut() {
+                *last = -2.0;
+                assert_eq!(peak(&samples), 2.0, "{} samples", len);
+            }
+        }
+    }
+
+    #[test]
+    fn sum_squares_matches_scalar() {
+        for len in LENS {
+            let samples = signal(len, 5);
+            let expected = scalar::sum_squares(&samples);
+            assert_close(sum_squares(&samples), expected, expected, len);
+        }
+    }
+
+    #[test]
+    fn dot_matches_scalar() {
+        for len in LENS {
+            let (a, b) = (signal(len, 9), signal(len + 3, 11));
+            let magnitude: f32 = a.iter().zip(&b).map(|(x, y)| (x * y).abs()).sum();
+            assert_close(dot(&a, &b), scalar::dot(&a, &b), magnitude, len);
+            assert_close(dot(&b, &a), scalar::dot(&b, &a), magnitude, len);
+        }
+    }
+
+    #[test]
+    fn lowpass_matches_scalar() {
+        let input = signal(4099, 13);
+        for step in [2.0, 3.0, 44_100.0 / 16_000.0] {
+            let mut lowpass = Lowpass::new(step);
+            let mut padded = vec![0.0; LOWPASS_TAPS - 1];
+            padded.extend_from_slice(&input);
+            let expected: Vec<f32> = padded
+                .windows(LOWPASS_TAPS)
+                .map(|window| scalar::dot(window, &lowpass.taps))
+                .collect();
+            // The input is within [-1, 1].
+            let magnitude: f32 = lowpass.taps.iter().map(|t| t.abs()).sum();
+
+            // Blocks of uneven length, so the history carries across them.
+            let mut filtered = Vec::new();
+            let mut rest = input.as_slice();
+            for len in LENS.iter().cycle().skip(1) {
+                let (block, tail) = rest.split_at((*len).min(rest.len()));
+                filtered.extend(lowpass.filter(block));
+                rest = tail;
+                if rest.is_empty() {
+                    break;
+                }
+            }
+
+            assert_eq!(filtered.len(), expected.len());
+            for (actual, expected) in filtered.into_iter().zip(expected) {
+                assert_close(actual, expected, magnitude, LOWPASS_TAPS);
+            }
+        }
+    }
+}
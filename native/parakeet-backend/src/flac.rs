@@ -0,0 +1,380 @@
+//! A small FLAC encoder for 16-bit mono audio (`--save-audio` to `.flac`).
+//!
+//! Every block is coded with whichever of FLAC's fixed polynomial
+//! predictors (orders 0 to 4) leaves the smallest residual, Rice coded
+//! with one parameter per block, or stored verbatim if that is smaller.
+//! There is no LPC and no stereo decorrelation, so files come out somewhat
+//! larger than `flac -5` would make them, but speech still comes out at
+//! half to two thirds of its WAV size. Frames are written as each block
+//! fills, so a run that is killed leaves a playable file; only its length
+//! in STREAMINFO is then missing.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+const BLOCK_SIZE: usize = 4096;
+/// FLAC's block size code for 4096 samples
+const BLOCK_SIZE_CODE: u8 = 0b1100;
+/// Largest Rice parameter that fits the 4-bit field without escaping
+const MAX_RICE_PARAMETER: u32 = 14;
+/// Byte offset of STREAMINFO's 64 bits of rate, channels, depth and length
+const STREAMINFO_SAMPLES_OFFSET: u64 = 18;
+
+pub struct Encoder<W: Write + Seek> {
+    out: W,
+    sample_rate: u32,
+    pending: Vec<i32>,
+    frames: u64,
+    samples: u64,
+}
+
+impl<W: Write + Seek> Encoder<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<Self> {
+        out.write_all(b"fLaC")?;
+        // Last metadata block, type 0 (STREAMINFO), 34 bytes long.
+        out.write_all(&[0x80, 0, 0, 34])?;
+        let mut info = BitWriter::default();
+        info.write(BLOCK_SIZE as u64, 16);
+        info.write(BLOCK_SIZE as u64, 16);
+        // Minimum and maximum frame sizes: unknown.
+        info.write(0, 24);
+        info.write(0, 24);
+        info.write(stream_info_tail(sample_rate, 0), 64);
+        // No MD5 signature.
+        info.write(0, 64);
+        info.write(0, 64);
+        out.write_all(&info.into_bytes())?;
+
+        Ok(Self {
+            out,
+            sample_rate,
+            pending: Vec::with_capacity(BLOCK_SIZE),
+            frames: 0,
+            samples: 0,
+        })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            self.pending
+                .push((sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i32);
+            if self.pending.len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Writes the last, partial block and the stream's length.
+    pub fn finish(mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+        self.out.seek(SeekFrom::Start(STREAMINFO_SAMPLES_OFFSET))?;
+        self.out
+            .write_all(&stream_info_tail(self.sample_rate, self.samples).to_be_bytes())?;
+        self.out.flush()
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let block = std::mem::take(&mut self.pending);
+        let mut frame = BitWriter::default();
+
+        // Sync code, fixed block size strategy.
+        frame.write(0xFFF8, 16);
+        let partial = block.len() != BLOCK_SIZE;
+        frame.write(if partial { 0b0111 } else { BLOCK_SIZE_CODE } as u64, 4);
+        frame.write(sample_rate_code(self.sample_rate) as u64, 4);
+        // Mono, 16 bits per sample, reserved bit.
+        frame.write(0b0000, 4);
+        frame.write(0b100, 3);
+        frame.write(0, 1);
+        for byte in utf8_number(self.frames) {
+            frame.write(byte as u64, 8);
+        }
+        if partial {
+            frame.write(block.len() as u64 - 1, 16);
+        }
+        if sample_rate_code(self.sample_rate) == 0b1101 {
+            frame.write(self.sample_rate as u64, 16);
+        }
+        let header_crc = crc8(frame.bytes());
+        frame.write(header_crc as u64, 8);
+
+        write_subframe(&mut frame, &block);
+        frame.align();
+        let frame_crc = crc16(frame.bytes());
+        frame.write(frame_crc as u64, 16);
+
+        self.out.write_all(&frame.into_bytes())?;
+        self.frames += 1;
+        self.samples += block.len() as u64;
+        self.pending = block;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// STREAMINFO's packed sample rate, channel count, bit depth and length.
+fn stream_info_tail(sample_rate: u32, samples: u64) -> u64 {
+    (sample_rate as u64) << 44 | 15 << 36 | (samples & ((1 << 36) - 1))
+}
+
+fn sample_rate_code(sample_rate: u32) -> u8 {
+    match sample_rate {
+        8_000 => 0b0100,
+        16_000 => 0b0101,
+        22_050 => 0b0110,
+        24_000 => 0b0111,
+        32_000 => 0b1000,
+        44_100 => 0b1001,
+        48_000 => 0b1010,
+        96_000 => 0b1011,
+        // Given in Hz after the header.
+        _ => 0b1101,
+    }
+}
+
+fn write_subframe(frame: &mut BitWriter, block: &[i32]) {
+    let verbatim_bits = 16 * block.len() as u64;
+    let best = (0..=4usize)
+        .filter(|&order| order < block.len())
+        .map(|order| {
+            let residual = fixed_residual(block, order);
+            let parameter = rice_parameter(&residual);
+            let bits = 16 * order as u64 + 10 + rice_bits(&residual, parameter);
+            (bits, order, residual, parameter)
+        })
+        .min_by_key(|candidate| candidate.0);
+
+    match best {
+        Some((bits, order, residual, parameter)) if bits < verbatim_bits => {
+            // Zero bit, type 001xxx (fixed, order xxx), no wasted bits.
+            frame.write(0, 1);
+            frame.write(0b001000 | order as u64, 6);
+            frame.write(0, 1);
+            for &sample in &block[..order] {
+                frame.write_signed(sample, 16);
+            }
+            // Rice coding with a 4-bit parameter, a single partition.
+            frame.write(0b00, 2);
+            frame.write(0, 4);
+            frame.write(parameter as u64, 4);
+            for &r in &residual {
+                frame.write_rice(zigzag(r), parameter);
+            }
+        }
+        _ => {
+            frame.write(0, 1);
+            frame.write(0b000001, 6);
+            frame.write(0, 1);
+            for &sample in block {
+                frame.write_signed(sample, 16);
+            }
+        }
+    }
+}
+
+/// Residual of the fixed predictor of `order`, after its warm-up samples.
+fn fixed_residual(block: &[i32], order: usize) -> Vec<i32> {
+    (order..block.len())
+        .map(|i| {
+            let s = |k: usize| block[i - k];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// The Rice parameter that best fits `residual`'s mean magnitude.
+fn rice_parameter(residual: &[i32]) -> u32 {
+    if residual.is_empty() {
+        return 0;
+    }
+    let mean = residual.iter().map(|&r| zigzag(r) as u64).sum::<u64>() / residual.len() as u64;
+    let mut parameter = 0;
+    while parameter < MAX_RICE_PARAMETER && (1u64 << (parameter + 1)) <= mean {
+        parameter += 1;
+    }
+    parameter
+}
+
+fn rice_bits(residual: &[i32], parameter: u32) -> u64 {
+    residual
+        .iter()
+        .map(|&r| (zigzag(r) >> parameter) as u64 + 1 + parameter as u64)
+        .sum()
+}
+
+/// A frame number in FLAC's UTF-8-like variable length coding.
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let mut continuation = Vec::new();
+    let mut rest = value;
+    // Each leading-byte form holds 7 - n bits with n continuation bytes.
+    while rest >= 1 << (6 - continuation.len()) {
+        continuation.push(0x80 | (rest & 0x3F) as u8);
+        rest >>= 6;
+    }
+    let n = continuation.len();
+    let marker = !(0xFFu8 >> (n + 1));
+    let mut bytes = vec![marker | rest as u8];
+    bytes.extend(continuation.into_iter().rev());
+    bytes
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Big-endian bit packing, MSB first as FLAC wants.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits used in the last byte, 0 when it is full
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.push_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    fn write_rice(&mut self, value: u32, parameter: u32) {
+        for _ in 0..value >> parameter {
+            self.push_bit(false);
+        }
+        self.push_bit(true);
+        self.write(value as u64 & ((1 << parameter) - 1), parameter);
+    }
+
+    fn push_bit(&mut self, bit: bool) {
+        if self.used == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().expect("a byte was just pushed") |= 0x80 >> self.used;
+        }
+        self.used = (self.used + 1) % 8;
+    }
+
+    /// Pads with zero bits to the next byte boundary.
+    fn align(&mut self) {
+        self.used = 0;
+    }
+
+    /// The bytes written so far; only meaningful when aligned.
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Silence, a tone, noise and clipping, so every subframe type and
+    /// several Rice parameters turn up.
+    fn signal(len: usize) -> Vec<f32> {
+        let mut seed = 1u32;
+        (0..len)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let noise = (seed >> 8) as f32 / (1 << 23) as f32 - 1.0;
+                let tone = (i as f32 * 0.05).sin();
+                match (i / BLOCK_SIZE) % 4 {
+                    0 => 0.0,
+                    1 => 0.5 * tone + 0.01 * noise,
+                    2 => noise,
+                    _ => 1.5 * tone,
+                }
+            })
+            .collect()
+    }
+
+    fn round_trip(samples: &[f32], sample_rate: u32) {
+        let mut out = Cursor::new(Vec::new());
+        let mut encoder = Encoder::new(&mut out, sample_rate).unwrap();
+        // Uneven writes, so blocks fill across calls.
+        for chunk in samples.chunks(1000) {
+            encoder.write(chunk).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let mut reader = claxon::FlacReader::new(Cursor::new(out.into_inner())).unwrap();
+        let info = reader.streaminfo();
+        assert_eq!(info.sample_rate, sample_rate);
+        assert_eq!(info.channels, 1);
+        assert_eq!(info.bits_per_sample, 16);
+        assert_eq!(info.samples, Some(samples.len() as u64));
+
+        let decoded: Vec<i32> = reader.samples().map(Result::unwrap).collect();
+        let expected: Vec<i32> = samples
+            .iter()
+            .map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i32)
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn decodes_to_the_same_samples() {
+        // Past frame 127, where frame numbers take two bytes, and ending on
+        // a partial block.
+        round_trip(&signal(BLOCK_SIZE * 130 + 1234), 16_000);
+    }
+
+    #[test]
+    fn decodes_an_uncommon_sample_rate() {
+        round_trip(&signal(BLOCK_SIZE * 4 + 7), 11_025);
+    }
+
+    #[test]
+    fn decodes_a_block_shorter_than_the_predictor() {
+        round_trip(&signal(3), 16_000);
+    }
+}
@@ -0,0 +1,167 @@
+//! `--output`: the formats a CLI transcript can be written in.
+//!
+//! Each format is a `Formatter` in a `Registry` keyed by the name `--output`
+//! takes. JSON, MessagePack, CBOR and plain text are built in; every
+//! `NAME.hbs` file in the formats directory adds a format `NAME` rendered
+//! from that Handlebars template, so a niche format is a file rather than a
+//! change to the backend. Templates see the transcript as it is written as
+//! JSON (`text`, `segments` with `start`, `end` and `text`, `cues`,
+//! `chapters` and so on, times in the `--timestamp-format`), can include
+//! each other as partials, and are rendered without HTML escaping.
+
+use anyhow::{bail, Context, Result};
+use handlebars::Handlebars;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const TEMPLATE_EXTENSION: &str = "hbs";
+
+pub trait Formatter {
+    /// Writes one transcript, given as its JSON value.
+    fn write(&self, transcript: &Value, out: &mut dyn Write) -> Result<()>;
+}
+
+struct Json;
+
+impl Formatter for Json {
+    fn write(&self, transcript: &Value, out: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer(&mut *out, transcript)?;
+        out.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+struct MessagePack;
+
+impl Formatter for MessagePack {
+    fn write(&self, transcript: &Value, out: &mut dyn Write) -> Result<()> {
+        out.write_all(&rmp_serde::to_vec_named(transcript)?)?;
+        Ok(())
+    }
+}
+
+struct Cbor;
+
+impl Formatter for Cbor {
+    fn write(&self, transcript: &Value, out: &mut dyn Write) -> Result<()> {
+        ciborium::into_writer(transcript, out)
+            .map_err(|e| anyhow::anyhow!("Failed to encode CBOR: {}", e))
+    }
+}
+
+struct Text;
+
+impl Formatter for Text {
+    fn write(&self, transcript: &Value, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "{}", transcript["text"].as_str().unwrap_or_default())?;
+        Ok(())
+    }
+}
+
+struct Template {
+    name: String,
+    /// Shared by every template of the directory, for partials
+    templates: Rc<Handlebars<'static>>,
+}
+
+impl Formatter for Template {
+    fn write(&self, transcript: &Value, out: &mut dyn Write) -> Result<()> {
+        let rendered = self
+            .templates
+            .render(&self.name, transcript)
+            .with_context(|| format!("Failed to render the {} template", self.name))?;
+        out.write_all(rendered.as_bytes())?;
+        Ok(())
+    }
+}
+
+pub struct Registry {
+    formatters: BTreeMap<String, Box<dyn Formatter>>,
+}
+
+impl Registry {
+    fn builtin() -> Self {
+        let mut formatters: BTreeMap<String, Box<dyn Formatter>> = BTreeMap::new();
+        formatters.insert("json".to_string(), Box::new(Json));
+        formatters.insert("msgpack".to_string(), Box::new(MessagePack));
+        formatters.insert("cbor".to_string(), Box::new(Cbor));
+        formatters.insert("text".to_string(), Box::new(Text));
+        Self { formatters }
+    }
+
+    /// The built-in formats and the templates in `dir`, which may not
+    /// exist.
+    pub fn load(dir: Option<&Path>) -> Result<Self> {
+        let mut registry = Self::builtin();
+        let Some(dir) = dir else {
+            return Ok(registry);
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(registry),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+        };
+
+        let mut templates = Handlebars::new();
+        templates.register_escape_fn(handlebars::no_escape);
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(TEMPLATE_EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if registry.formatters.contains_key(name) {
+                bail!(
+                    "{} would replace the built-in {} format; rename it",
+                    path.display(),
+                    name
+                );
+            }
+            let source = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            templates
+                .register_template_string(name, source)
+                .with_context(|| format!("Invalid template {}", path.display()))?;
+            names.push(name.to_string());
+        }
+
+        let templates = Rc::new(templates);
+        for name in names {
+            let template = Template {
+                name: name.clone(),
+                templates: Rc::clone(&templates),
+            };
+            registry.formatters.insert(name, Box::new(template));
+        }
+        Ok(registry)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn Formatter> {
+        match self.formatters.get(name) {
+            Some(formatter) => Ok(formatter.as_ref()),
+            None => bail!(
+                "Unknown output format '{}'; available: {}",
+                name,
+                self.formatters
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// `~/Library/Application Support/WhisperMac/formats`, when there is a
+/// home directory.
+pub fn default_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join("Library/Application Support/WhisperMac/formats"))
+}
@@ -0,0 +1,117 @@
+//! Message framing for the stdio server.
+//!
+//! `newline` is the original protocol: one JSON message per line.
+//! `length-prefixed` wraps every message in a 4-byte big-endian length, which
+//! lets a `transcribe` command be followed by a raw audio frame (f32le
+//! samples) instead of a path, with no base64 or line-splitting involved.
+
+use std::io::{self, BufRead, Write};
+
+// Guards against a corrupted header making us allocate gigabytes.
+const MAX_FRAME_BYTES: usize = 512 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Framing {
+    /// One JSON message per line
+    Newline,
+    /// 4-byte big-endian length followed by the payload
+    LengthPrefixed,
+}
+
+pub struct FrameReader<R> {
+    inner: R,
+    framing: Framing,
+}
+
+impl<R: BufRead> FrameReader<R> {
+    pub fn new(inner: R, framing: Framing) -> Self {
+        Self { inner, framing }
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Returns the next message, or `None` once the peer closes the stream.
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.framing {
+            Framing::Newline => loop {
+                let mut line = Vec::new();
+                if self.inner.read_until(b'\n', &mut line)? == 0 {
+                    return Ok(None);
+                }
+
+                let line = line.trim_ascii();
+                if !line.is_empty() {
+                    return Ok(Some(line.to_vec()));
+                }
+            },
+            Framing::LengthPrefixed => {
+                let mut header = [0u8; 4];
+                match self.inner.read_exact(&mut header) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(e) => return Err(e),
+                }
+
+                let len = u32::from_be_bytes(header) as usize;
+                if len > MAX_FRAME_BYTES {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Frame of {} bytes exceeds the {} byte limit",
+                            len, MAX_FRAME_BYTES
+                        ),
+                    ));
+                }
+
+                let mut payload = vec![0u8; len];
+                self.inner.read_exact(&mut payload)?;
+                Ok(Some(payload))
+            }
+        }
+    }
+}
+
+pub struct FrameWriter<W> {
+    inner: W,
+    framing: Framing,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(inner: W, framing: Framing) -> Self {
+        Self { inner, framing }
+    }
+
+    pub fn write_frame(&mut self, payload: &[u8]) -> io::Result<()> {
+        match self.framing {
+            Framing::Newline => {
+                self.inner.write_all(payload)?;
+                self.inner.write_all(b"\n")?;
+            }
+            Framing::LengthPrefixed => {
+                let len = u32::try_from(payload.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "Frame too large to send")
+                })?;
+                self.inner.write_all(&len.to_be_bytes())?;
+                self.inner.write_all(payload)?;
+            }
+        }
+        self.inner.flush()
+    }
+}
+
+/// Decodes an audio frame of little-endian f32 samples.
+pub fn decode_samples(frame: &[u8]) -> io::Result<Vec<f32>> {
+    if !frame.len().is_multiple_of(4) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Audio frame must contain whole f32le samples",
+        ));
+    }
+
+    Ok(frame
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
@@ -0,0 +1,307 @@
+//! gRPC service (`serve --grpc`), built with `--features grpc`.
+//!
+//! The contract lives in `proto/parakeet.proto`. Inference is blocking, so
+//! every engine call is pushed onto the blocking thread pool and serialized
+//! on a single loaded engine. Every call passes `access` first: the token
+//! is checked when a call or stream opens, the rate limit on every
+//! transcription. Audio only ever arrives as samples: a network client has
+//! no business naming files on this machine. The input limits, memory
+//! budget and retry policy are the same as the stdio server's.
+
+use crate::access::{Access, Denied};
+use crate::audio::{self, InputLimits};
+use crate::incremental::SAMPLE_RATE;
+use crate::retry::RetryPolicy;
+use crate::{framing, memory, model};
+use anyhow::{Context, Result};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use transcribe_rs::{engines::parakeet::ParakeetEngine, TranscriptionEngine, TranscriptionResult};
+
+pub mod proto {
+    tonic::include_proto!("parakeet.v1");
+}
+
+use proto::parakeet_server::{Parakeet, ParakeetServer};
+use proto::{
+    streaming_recognize_request, transcribe_request, Segment, StreamingRecognizeRequest,
+    StreamingRecognizeResponse, TranscribeRequest, TranscriptionOutput,
+};
+
+// Whole recordings are sent as a single message, so lift tonic's 4 MB default.
+const MAX_MESSAGE_BYTES: usize = 256 * 1024 * 1024;
+
+/// What a streamed utterance may buffer when no input limit is set: as much
+/// as one unary call could carry.
+const MAX_STREAM_SAMPLES: usize = MAX_MESSAGE_BYTES / 4;
+
+/// The guardrails every transcription runs under.
+#[derive(Clone, Copy, Debug)]
+pub struct Guards {
+    pub limits: InputLimits,
+    pub retry: RetryPolicy,
+    pub memory_budget: Option<memory::Budget>,
+}
+
+impl Guards {
+    /// Refuses a buffer of `samples` over the input limits.
+    fn check_len(&self, samples: usize) -> Result<(), Status> {
+        if samples > MAX_STREAM_SAMPLES {
+            return Err(Status::out_of_range(format!(
+                "Utterance is over {} samples; mark utterance ends more often",
+                MAX_STREAM_SAMPLES
+            )));
+        }
+        self.limits
+            .check_samples(samples, SAMPLE_RATE)
+            .map_err(|e| Status::out_of_range(e.to_string()))
+    }
+}
+
+struct ParakeetService {
+    engine: Arc<Mutex<ParakeetEngine>>,
+    access: Arc<Access>,
+    guards: Guards,
+}
+
+impl ParakeetService {
+    /// Authenticates a call, returning the client's address for rate
+    /// limiting.
+    fn admit<T>(&self, request: &Request<T>) -> Result<Option<IpAddr>, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        self.access.authenticate(token).map_err(denied)?;
+        Ok(request.remote_addr().map(|addr| addr.ip()))
+    }
+}
+
+fn denied(denied: Denied) -> Status {
+    match denied {
+        Denied::Unauthenticated => Status::unauthenticated("Missing or invalid bearer token"),
+        Denied::RateLimited { retry_after } => Status::resource_exhausted(format!(
+            "Rate limit exceeded; retry in {:.1}s",
+            retry_after.as_secs_f64()
+        )),
+    }
+}
+
+#[tonic::async_trait]
+impl Parakeet for ParakeetService {
+    async fn transcribe(
+        &self,
+        request: Request<TranscribeRequest>,
+    ) -> Result<Response<TranscriptionOutput>, Status> {
+        let client = self.admit(&request)?;
+        self.access.take(client).map_err(denied)?;
+        let samples = match request.into_inner().audio {
+            Some(transcribe_request::Audio::Pcm(bytes)) => decode_pcm(&bytes)?,
+            None => return Err(Status::invalid_argument("Request has no audio")),
+        };
+        self.guards.check_len(samples.len())?;
+
+        let output = run_blocking(self.engine.clone(), self.guards, samples).await?;
+        Ok(Response::new(output))
+    }
+
+    type StreamingRecognizeStream = ReceiverStream<Result<StreamingRecognizeResponse, Status>>;
+
+    async fn streaming_recognize(
+        &self,
+        request: Request<Streaming<StreamingRecognizeRequest>>,
+    ) -> Result<Response<Self::StreamingRecognizeStream>, Status> {
+        let client = self.admit(&request)?;
+        let mut inbound = request.into_inner();
+        let engine = self.engine.clone();
+        let access = self.access.clone();
+        let guards = self.guards;
+        let (tx, rx) = mpsc::channel(8);
+
+        tokio::spawn(async move {
+            let mut buffer: Vec<f32> = Vec::new();
+
+            loop {
+                let message = match inbound.message().await {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        return;
+                    }
+                };
+
+                match message.request {
+                    Some(streaming_recognize_request::Request::Audio(bytes)) => {
+                        let decoded = decode_pcm(&bytes).and_then(|samples| {
+                            guards.check_len(buffer.len() + samples.len())?;
+                            Ok(samples)
+                        });
+                        match decoded {
+                            Ok(samples) => buffer.extend(samples),
+                            Err(status) => {
+                                let _ = tx.send(Err(status)).await;
+                                return;
+                            }
+                        }
+                    }
+                    Some(streaming_recognize_request::Request::EndUtterance(true)) => {
+                        if !flush(&engine, &access, guards, client, &mut buffer, &tx).await {
+                            return;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            flush(&engine, &access, guards, client, &mut buffer, &tx).await;
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+pub fn run(listen: &str, model: Option<&Path>, access: Access, guards: Guards) -> Result<()> {
+    let addr: SocketAddr = listen
+        .parse()
+        .with_context(|| format!("Invalid listen address: {}", listen))?;
+    let model = model.context("Model path required in gRPC mode")?;
+    model::validate(model)?;
+    if !addr.ip().is_loopback() && !access.requires_token() {
+        log::warn!(
+            "gRPC server on {} accepts any client on the network; pass --auth-token-file",
+            addr
+        );
+    }
+
+    let mut engine = ParakeetEngine::new();
+    guards
+        .retry
+        .run("Model load", || engine.load_model(model))
+        .map_err(|e| anyhow::anyhow!("Failed to load model: {}", e))?;
+
+    let service = ParakeetServer::new(ParakeetService {
+        engine: Arc::new(Mutex::new(engine)),
+        access: Arc::new(access),
+        guards,
+    })
+    .max_decoding_message_size(MAX_MESSAGE_BYTES)
+    .max_encoding_message_size(MAX_MESSAGE_BYTES);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async move {
+        log::info!("gRPC server listening on {}", addr);
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve(addr)
+            .await
+    })?;
+
+    Ok(())
+}
+
+/// Transcribes the buffered utterance, if any. Returns false once the client
+/// has gone away or run into its rate limit, and the stream should stop.
+async fn flush(
+    engine: &Arc<Mutex<ParakeetEngine>>,
+    access: &Access,
+    guards: Guards,
+    client: Option<IpAddr>,
+    buffer: &mut Vec<f32>,
+    tx: &mpsc::Sender<Result<StreamingRecognizeResponse, Status>>,
+) -> bool {
+    if buffer.is_empty() {
+        return true;
+    }
+    if let Err(e) = access.take(client) {
+        let _ = tx.send(Err(denied(e))).await;
+        return false;
+    }
+
+    let samples = std::mem::take(buffer);
+    let response = run_blocking(engine.clone(), guards, samples)
+        .await
+        .map(|output| StreamingRecognizeResponse {
+            output: Some(output),
+        });
+
+    tx.send(response).await.is_ok()
+}
+
+async fn run_blocking(
+    engine: Arc<Mutex<ParakeetEngine>>,
+    guards: Guards,
+    samples: Vec<f32>,
+) -> Result<TranscriptionOutput, Status> {
+    tokio::task::spawn_blocking(move || transcribe(&engine, guards, samples))
+        .await
+        .map_err(|e| Status::internal(format!("Transcription task failed: {}", e)))?
+}
+
+fn decode_pcm(bytes: &[u8]) -> Result<Vec<f32>, Status> {
+    framing::decode_samples(bytes).map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+fn transcribe(
+    engine: &Mutex<ParakeetEngine>,
+    guards: Guards,
+    samples: Vec<f32>,
+) -> Result<TranscriptionOutput, Status> {
+    let start_time = Instant::now();
+    if let Some(budget) = guards.memory_budget {
+        budget
+            .check()
+            .map_err(|e| Status::resource_exhausted(e.to_string()))?;
+    }
+    if audio::is_silent(&samples, audio::SILENCE_PEAK) {
+        let result = TranscriptionResult {
+            text: String::new(),
+            segments: Some(Vec::new()),
+        };
+        return Ok(to_proto(result, 0));
+    }
+
+    let mut engine = engine
+        .lock()
+        .map_err(|_| Status::internal("Parakeet engine lock poisoned"))?;
+    let result = if guards.retry.is_disabled() {
+        engine.transcribe_samples(samples, None)
+    } else {
+        guards.retry.run("Transcription", || {
+            engine.transcribe_samples(samples.clone(), None)
+        })
+    }
+    .map_err(|e| Status::internal(format!("Transcription failed: {}", e)))?;
+
+    Ok(to_proto(result, start_time.elapsed().as_millis() as u64))
+}
+
+fn to_proto(result: TranscriptionResult, processing_time_ms: u64) -> TranscriptionOutput {
+    let segments = result
+        .segments
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| Segment {
+            start: s.start as f64,
+            end: s.end as f64,
+            text: s.text,
+        })
+        .collect();
+
+    TranscriptionOutput {
+        no_speech: result.text.trim().is_empty(),
+        text: result.text,
+        segments,
+        processing_time_ms,
+        peak_rss_bytes: memory::peak_rss_bytes(),
+    }
+}
@@ -0,0 +1,305 @@
+//! Chunked transcription with streamed output (`--chunk-secs`).
+//!
+//! Long recordings are read from disk one chunk at a time and every chunk's
+//! segments are written out as soon as it is decoded, so neither the samples
+//! nor the segment list grow with the length of the input. Only the plain
+//! transcript text is kept until the end, which is small by comparison.
+//!
+//! A chunk ends at the longest pause in its last stretch (see
+//! `stream::pause_cut`) rather than at a fixed sample count, so words are
+//! not cut in half; the audio after the cut opens the next chunk. Plain CLI
+//! runs whose output is nothing but segments and text take this path too,
+//! with `DEFAULT_CHUNK_SECS`.
+
+use crate::stream;
+use crate::timestamps::TimestampFormat;
+use crate::{memory, Segment, TranscriptionStatus, SCHEMA_VERSION};
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+/// Sample rate the engine expects.
+pub const SAMPLE_RATE: u32 = 16_000;
+
+/// Chunk length for CLI runs that stream without `--chunk-secs`
+pub const DEFAULT_CHUNK_SECS: f64 = 120.0;
+
+/// How far back from a full chunk's end to look for a pause to cut at
+const MAX_CUT_SEARCH_SECS: f64 = 10.0;
+
+/// One chunk of audio and where it starts in the recording.
+pub struct Chunk {
+    pub offset_secs: f64,
+    pub samples: Vec<f32>,
+}
+
+/// Whether `path` is a WAV file `ChunkReader` can read.
+pub fn can_chunk(path: &Path) -> bool {
+    hound::WavReader::open(path).is_ok_and(|reader| {
+        let spec = reader.spec();
+        spec.channels == 1 && spec.sample_rate == SAMPLE_RATE
+    })
+}
+
+/// Reads a 16 kHz mono WAV file in chunks of at most `chunk_len` samples,
+/// cut at pauses.
+pub struct ChunkReader {
+    reader: hound::WavReader<BufReader<File>>,
+    chunk_len: usize,
+    position: usize,
+    /// Samples read past the last cut, which open the next chunk
+    carry: Vec<f32>,
+}
+
+impl ChunkReader {
+    pub fn open(path: &Path, chunk_secs: f64) -> Result<Self> {
+        let reader = hound::WavReader::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let spec = reader.spec();
+        if spec.channels != 1 || spec.sample_rate != SAMPLE_RATE {
+            bail!(
+                "{} is {} Hz with {} channel(s); chunked mode needs {} Hz mono",
+                path.display(),
+                spec.sample_rate,
+                spec.channels,
+                SAMPLE_RATE
+            );
+        }
+
+        let chunk_len = (chunk_secs * SAMPLE_RATE as f64).round() as usize;
+        if chunk_len == 0 {
+            bail!("Chunk length must be positive");
+        }
+
+        Ok(Self {
+            reader,
+            chunk_len,
+            position: 0,
+            carry: Vec::new(),
+        })
+    }
+
+    pub fn chunk_len(&self) -> usize {
+        self.chunk_len
+    }
+
+    /// Samples handed out in chunks so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Skips to `position` samples into the file.
+    pub fn seek(&mut self, position: usize) -> Result<()> {
+        let target = u32::try_from(position).context("Resume offset out of range")?;
+        self.reader.seek(target)?;
+        self.position = position;
+        self.carry.clear();
+        Ok(())
+    }
+
+    /// Returns the next chunk, or `None` at the end of the file.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        let offset_secs = self.position as f64 / SAMPLE_RATE as f64;
+        let wanted = self.chunk_len - self.carry.len();
+        let mut samples = std::mem::take(&mut self.carry);
+        let read = self.read(wanted, &mut samples)?;
+
+        if samples.is_empty() {
+            return Ok(None);
+        }
+        if read == wanted {
+            let search =
+                (self.chunk_len / 4).min((MAX_CUT_SEARCH_SECS * SAMPLE_RATE as f64) as usize);
+            let cut = stream::pause_cut(&samples, samples.len() - search);
+            self.carry = samples.split_off(cut);
+        }
+        self.position += samples.len();
+        Ok(Some(Chunk {
+            offset_secs,
+            samples,
+        }))
+    }
+
+    /// Appends up to `count` samples from the file, returning how many
+    /// there were.
+    fn read(&mut self, count: usize, samples: &mut Vec<f32>) -> Result<usize> {
+        let spec = self.reader.spec();
+        let before = samples.len();
+        match spec.sample_format {
+            hound::SampleFormat::Float => {
+                for sample in self.reader.samples::<f32>().take(count) {
+                    samples.push(sample?);
+                }
+            }
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                for sample in self.reader.samples::<i32>().take(count) {
+                    samples.push(sample? as f32 / scale);
+                }
+            }
+        }
+        Ok(samples.len() - before)
+    }
+}
+
+/// Writes the same document as `TranscriptionOutput`, one segment at a time.
+/// Field order differs (`segments` comes before `text`), which JSON readers
+/// do not care about.
+pub struct JsonStream<W: Write> {
+    out: W,
+    timestamps: TimestampFormat,
+    text: String,
+    first: bool,
+}
+
+impl<W: Write> JsonStream<W> {
+    pub fn begin(mut out: W, timestamps: TimestampFormat) -> io::Result<Self> {
+        write!(
+            out,
+            "{{\"schema_version\":{},\"segments\":[",
+            SCHEMA_VERSION
+        )?;
+        Ok(Self {
+            out,
+            timestamps,
+            text: String::new(),
+            first: true,
+        })
+    }
+
+    pub fn push(&mut self, segments: &[Segment], text: &str) -> io::Result<()> {
+        for segment in segments {
+            if !self.first {
+                self.out.write_all(b",")?;
+            }
+            self.first = false;
+            serde_json::to_writer(&mut self.out, &self.timestamps.to_value(segment)?)?;
+        }
+        self.out.flush()?;
+
+        let text = text.trim();
+        if !text.is_empty() {
+            if !self.text.is_empty() {
+                self.text.push(' ');
+            }
+            self.text.push_str(text);
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self, processing_time_ms: u64) -> io::Result<W> {
+        let status = if self.text.is_empty() {
+            TranscriptionStatus::NoSpeech
+        } else {
+            TranscriptionStatus::Ok
+        };
+
+        self.out.write_all(b"],\"status\":")?;
+        serde_json::to_writer(&mut self.out, &status)?;
+        self.out.write_all(b",\"text\":")?;
+        serde_json::to_writer(&mut self.out, &self.text)?;
+        writeln!(
+            self.out,
+            ",\"processing_time_ms\":{},\"peak_rss_bytes\":{}}}",
+            processing_time_ms,
+            memory::peak_rss_bytes()
+        )?;
+        Ok(self.out)
+    }
+}
+
+/// Plain-text counterpart of `JsonStream`: each chunk's text as it arrives.
+pub struct TextStream<W: Write> {
+    out: W,
+    first: bool,
+}
+
+impl<W: Write> TextStream<W> {
+    pub fn begin(out: W) -> Self {
+        Self { out, first: true }
+    }
+
+    pub fn push(&mut self, text: &str) -> io::Result<()> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+        if !self.first {
+            self.out.write_all(b" ")?;
+        }
+        self.first = false;
+        self.out.write_all(text.as_bytes())?;
+        self.out.flush()
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(b"\n")?;
+        Ok(self.out)
+    }
+}
+
+/// The streamed output for one `--output` format.
+pub enum ResultStream<W: Write> {
+    Json(JsonStream<W>),
+    Text(TextStream<W>),
+}
+
+impl<W: Write> ResultStream<W> {
+    pub fn begin(out: W, format: &str, timestamps: TimestampFormat) -> Result<Self> {
+        Ok(match format {
+            "json" => ResultStream::Json(JsonStream::begin(out, timestamps)?),
+            "text" => ResultStream::Text(TextStream::begin(out)),
+            other => bail!("--chunk-secs supports json and text output, not {}", other),
+        })
+    }
+
+    pub fn push(&mut self, segments: &[Segment], text: &str) -> io::Result<()> {
+        match self {
+            ResultStream::Json(stream) => stream.push(segments, text),
+            ResultStream::Text(stream) => stream.push(text),
+        }
+    }
+
+    pub fn finish(self, processing_time_ms: u64) -> io::Result<W> {
+        match self {
+            ResultStream::Json(stream) => stream.finish(processing_time_ms),
+            ResultStream::Text(stream) => stream.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav;
+
+    #[test]
+    fn short_file_is_one_chunk_of_the_whole_recording() {
+        let path = std::env::temp_dir().join(format!("incremental-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..SAMPLE_RATE * 3 + 123 {
+            let phase = i as f32 * 440.0 / SAMPLE_RATE as f32 * std::f32::consts::TAU;
+            writer
+                .write_sample((phase.sin() * 12_000.0) as i16)
+                .unwrap();
+        }
+        writer.finalize().unwrap();
+
+        assert!(can_chunk(&path));
+        let mut reader = ChunkReader::open(&path, DEFAULT_CHUNK_SECS).unwrap();
+        let chunk = reader.next_chunk().unwrap().unwrap();
+        let whole = wav::read(&path).unwrap();
+        assert!(reader.next_chunk().unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(chunk.offset_secs, 0.0);
+        assert_eq!(chunk.samples, whole);
+    }
+}
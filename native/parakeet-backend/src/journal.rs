@@ -0,0 +1,68 @@
+//! Append-only JSONL journal of completed transcriptions (`--journal`).
+
+use crate::{TranscribeOptions, TranscriptionOutput, SCHEMA_VERSION};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Journal {
+    path: PathBuf,
+}
+
+/// One completed transcription.
+pub struct Entry<'a> {
+    /// Input file, if the audio came from one
+    pub source: Option<&'a Path>,
+    pub source_sha256: &'a str,
+    pub model: Option<&'a Path>,
+    pub options: &'a TranscribeOptions,
+    pub result: &'a TranscriptionOutput,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    schema_version: u32,
+    recorded_at_ms: u64,
+    source: Option<&'a Path>,
+    source_sha256: &'a str,
+    model: Option<&'a Path>,
+    options: &'a TranscribeOptions,
+    result: &'a TranscriptionOutput,
+}
+
+impl Journal {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn append(&self, entry: Entry<'_>) -> Result<()> {
+        let record = Record {
+            schema_version: SCHEMA_VERSION,
+            recorded_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            source: entry.source,
+            source_sha256: entry.source_sha256,
+            model: entry.model,
+            options: entry.options,
+            result: entry.result,
+        };
+
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        // One write per record on an O_APPEND descriptor keeps lines intact
+        // even when several backend processes share the journal.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open journal {}", self.path.display()))?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
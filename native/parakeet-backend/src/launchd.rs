@@ -0,0 +1,166 @@
+//! launchd socket activation.
+//!
+//! launchd owns the listening socket declared in the job plist (see
+//! `launchd/com.whispermac.parakeet-backend.plist`) and starts us on the
+//! first connection. Each client speaks the stdio protocol over the socket,
+//! all of them sharing one worker pool.
+//! With `--idle-exit` we quit once no client has been connected for that
+//! long, leaving launchd to start us again on demand.
+
+use crate::framing::Framing;
+use crate::pool::Pool;
+use crate::serve_connection;
+use anyhow::{bail, Context, Result};
+use std::ffi::{c_char, c_int, CString};
+use std::io::BufReader;
+use std::os::fd::FromRawFd;
+use std::os::unix::net::UnixListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+extern "C" {
+    fn launch_activate_socket(name: *const c_char, fds: *mut *mut c_int, cnt: *mut usize) -> c_int;
+}
+
+/// Tracks connected clients so the idle watchdog knows when we are unused.
+struct Activity {
+    connections: AtomicUsize,
+    last_active: Mutex<Instant>,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            connections: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn connect(&self) -> ConnectionGuard<'_> {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard(self)
+    }
+
+    fn idle_for(&self) -> Option<Duration> {
+        if self.connections.load(Ordering::SeqCst) > 0 {
+            return None;
+        }
+        let last_active = self
+            .last_active
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        Some(last_active.elapsed())
+    }
+}
+
+struct ConnectionGuard<'a>(&'a Activity);
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        *self
+            .0
+            .last_active
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+        self.0.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn run(pool: Pool, name: &str, idle_exit: Option<Duration>, framing: Framing) -> Result<()> {
+    let listeners = activate_sockets(name)?;
+    let pool = Arc::new(pool);
+    let activity = Arc::new(Activity::new());
+
+    if let Some(timeout) = idle_exit {
+        let activity = activity.clone();
+        thread::spawn(move || loop {
+            thread::sleep(IDLE_POLL_INTERVAL.min(timeout));
+            if activity.idle_for().is_some_and(|idle| idle >= timeout) {
+                log::info!(
+                    "No clients for {:?}, exiting until launchd restarts us",
+                    timeout
+                );
+                std::process::exit(0);
+            }
+        });
+    }
+
+    // A single Sockets entry can map to several descriptors (e.g. one per
+    // address family), so each gets its own accept loop.
+    let handles: Vec<_> = listeners
+        .into_iter()
+        .map(|listener| {
+            let pool = pool.clone();
+            let activity = activity.clone();
+            thread::spawn(move || accept_loop(listener, pool, activity, framing))
+        })
+        .collect();
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
+
+fn activate_sockets(name: &str) -> Result<Vec<UnixListener>> {
+    let c_name = CString::new(name).context("Socket name contains a NUL byte")?;
+    let mut fds: *mut c_int = std::ptr::null_mut();
+    let mut count: usize = 0;
+
+    let err = unsafe { launch_activate_socket(c_name.as_ptr(), &mut fds, &mut count) };
+    if err != 0 {
+        return Err(std::io::Error::from_raw_os_error(err)).with_context(|| {
+            format!(
+                "launch_activate_socket({}) failed; is the job loaded with a matching Sockets entry?",
+                name
+            )
+        });
+    }
+
+    let listeners = unsafe {
+        let listeners = std::slice::from_raw_parts(fds, count)
+            .iter()
+            .map(|&fd| UnixListener::from_raw_fd(fd))
+            .collect::<Vec<_>>();
+        libc::free(fds.cast());
+        listeners
+    };
+
+    if listeners.is_empty() {
+        bail!("launchd returned no sockets for {}", name);
+    }
+    Ok(listeners)
+}
+
+fn accept_loop(listener: UnixListener, pool: Arc<Pool>, activity: Arc<Activity>, framing: Framing) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let pool = pool.clone();
+        let activity = activity.clone();
+        thread::spawn(move || {
+            let _guard = activity.connect();
+            let result = stream
+                .try_clone()
+                .context("Failed to clone client socket")
+                .and_then(|reader| {
+                    serve_connection(&pool, BufReader::new(reader), stream, framing)
+                });
+
+            if let Err(e) = result {
+                log::warn!("Client connection ended with error: {:#}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,524 @@
+//! `--locale`: regional number, currency, date and percent formatting.
+//!
+//! Parakeet writes numbers the way its mostly American training text does:
+//! "1,234.56", "€20", "3/5/2024". Dictated into a German or French
+//! document that is wrong, so the transcript is rewritten with the
+//! locale's decimal and thousands separators, currency placement, date
+//! order and percent spacing. Units need no rules of their own: "2.5 km"
+//! becomes "2,5 km" through the decimal separator.
+//!
+//! Only unambiguous forms are touched. Plain integers such as years stay as
+//! they are, runs like version numbers and IP addresses ("1.2.3") are left
+//! alone, a currency amount followed by a word ("$5 million") keeps its
+//! symbol where it is, and dates with a two-digit year are not turned year
+//! first.
+
+use anyhow::{bail, Result};
+
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹'];
+const NBSP: char = '\u{a0}';
+const NARROW_NBSP: char = '\u{202f}';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Currency {
+    /// "€1,234.56"
+    Before,
+    /// "€ 1.234,56"
+    BeforeSpaced,
+    /// "1.234,56 €"
+    After,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DateOrder {
+    Mdy,
+    Dmy,
+    Ymd,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Locale {
+    decimal: char,
+    group: char,
+    currency: Currency,
+    date_order: DateOrder,
+    date_separator: char,
+    /// What goes between a number and "%", if anything
+    percent_space: Option<char>,
+}
+
+impl Locale {
+    const US: Locale = Locale {
+        decimal: '.',
+        group: ',',
+        currency: Currency::Before,
+        date_order: DateOrder::Mdy,
+        date_separator: '/',
+        percent_space: None,
+    };
+
+    /// Parses a tag like `de-DE`, `fr_CH` or `en-GB`; the region is
+    /// optional.
+    pub fn parse(tag: &str) -> Result<Self> {
+        let lower = tag.trim().replace('_', "-").to_ascii_lowercase();
+        let mut parts = lower.split('-');
+        let language = parts.next().unwrap_or_default();
+        // Skip a script subtag such as the "hant" of zh-Hant-TW.
+        let region = parts.find(|p| p.len() == 2).unwrap_or_default();
+
+        let continental = Locale {
+            decimal: ',',
+            group: '.',
+            currency: Currency::After,
+            date_order: DateOrder::Dmy,
+            date_separator: '.',
+            percent_space: Some(NBSP),
+        };
+        let nordic = Locale {
+            group: NBSP,
+            ..continental
+        };
+
+        let locale = match (language, region) {
+            ("en", "" | "us") => Self::US,
+            ("en", "ca") => Locale {
+                date_order: DateOrder::Ymd,
+                date_separator: '-',
+                ..Self::US
+            },
+            ("en", _) => Locale {
+                date_order: DateOrder::Dmy,
+                ..Self::US
+            },
+            ("de", "ch") => Locale {
+                decimal: '.',
+                group: '’',
+                currency: Currency::BeforeSpaced,
+                ..continental
+            },
+            ("de", _) => continental,
+            ("fr", "ch") => Locale {
+                decimal: '.',
+                group: NARROW_NBSP,
+                date_separator: '.',
+                ..continental
+            },
+            ("fr", _) => Locale {
+                group: NARROW_NBSP,
+                date_separator: '/',
+                percent_space: Some(NARROW_NBSP),
+                ..continental
+            },
+            ("es" | "it" | "pt" | "tr" | "id", _) => Locale {
+                currency: if region == "br" {
+                    Currency::BeforeSpaced
+                } else {
+                    Currency::After
+                },
+                date_separator: '/',
+                percent_space: None,
+                ..continental
+            },
+            ("nl", _) => Locale {
+                currency: Currency::BeforeSpaced,
+                date_separator: '-',
+                percent_space: None,
+                ..continental
+            },
+            ("da", _) => continental,
+            ("sv", _) => Locale {
+                date_order: DateOrder::Ymd,
+                date_separator: '-',
+                ..nordic
+            },
+            ("nb" | "no" | "nn" | "fi" | "pl" | "cs" | "sk" | "ru" | "uk", _) => nordic,
+            ("hu", _) => Locale {
+                date_order: DateOrder::Ymd,
+                ..nordic
+            },
+            ("ja" | "zh" | "ko", _) => Locale {
+                date_order: DateOrder::Ymd,
+                ..Self::US
+            },
+            _ => bail!(
+                "Unsupported locale '{}'; expected a tag like en-US, de-DE or fr-FR",
+                tag
+            ),
+        };
+        Ok(locale)
+    }
+
+    /// Rewrites the numbers, amounts, dates and percentages in `text`.
+    pub fn format(&self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::with_capacity(text.len());
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let starts_token = i == 0 || !chars[i - 1].is_alphanumeric();
+
+            if starts_token && CURRENCY_SYMBOLS.contains(&c) {
+                if let Some((end, number)) = scan_number(&chars, i + 1) {
+                    self.push_amount(&mut out, c, &number, &chars, end);
+                    i = end;
+                    continue;
+                }
+            }
+
+            // Inside a word such as "v1.2" or "mp3": copy the rest of it.
+            if c.is_alphanumeric() && !(starts_token && c.is_ascii_digit()) {
+                let end = run_end(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+
+            if c.is_ascii_digit() {
+                let date = scan_date(&chars, i).filter(|(_, date)| self.can_write(date));
+                if let Some((end, date)) = date {
+                    self.push_date(&mut out, date);
+                    i = end;
+                    continue;
+                }
+                if let Some((end, number)) = scan_number(&chars, i) {
+                    self.push_number(&mut out, &number);
+                    if chars.get(end) == Some(&'%') {
+                        out.extend(self.percent_space);
+                    }
+                    i = end;
+                    continue;
+                }
+                // Something like "1.2.3": copy it whole so no part of it
+                // is read as a number.
+                let end = run_end(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+
+            out.push(c);
+            i += 1;
+        }
+
+        out
+    }
+
+    fn push_number(&self, out: &mut String, number: &Number) {
+        if number.grouped {
+            let len = number.integer.len();
+            for (i, digit) in number.integer.chars().enumerate() {
+                if i > 0 && (len - i) % 3 == 0 {
+                    out.push(self.group);
+                }
+                out.push(digit);
+            }
+        } else {
+            out.push_str(&number.integer);
+        }
+        if let Some(fraction) = &number.fraction {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+    }
+
+    fn push_amount(
+        &self,
+        out: &mut String,
+        symbol: char,
+        number: &Number,
+        chars: &[char],
+        end: usize,
+    ) {
+        let followed_by_word =
+            chars.get(end) == Some(&' ') && chars.get(end + 1).is_some_and(|c| c.is_alphabetic());
+        let placement = if followed_by_word {
+            Currency::Before
+        } else {
+            self.currency
+        };
+
+        match placement {
+            Currency::Before => {
+                out.push(symbol);
+                self.push_number(out, number);
+            }
+            Currency::BeforeSpaced => {
+                out.push(symbol);
+                out.push(NBSP);
+                self.push_number(out, number);
+            }
+            Currency::After => {
+                self.push_number(out, number);
+                out.push(NBSP);
+                out.push(symbol);
+            }
+        }
+    }
+
+    /// Year-first dates need the full year: "24-03-05" could be read in
+    /// any order.
+    fn can_write(&self, date: &Date) -> bool {
+        self.date_order != DateOrder::Ymd || date.year.len() == 4
+    }
+
+    fn push_date(&self, out: &mut String, date: Date) {
+        let sep = self.date_separator;
+        let formatted = match self.date_order {
+            DateOrder::Mdy => format!("{}{sep}{}{sep}{}", date.month, date.day, date.year),
+            DateOrder::Dmy => format!("{}{sep}{}{sep}{}", date.day, date.month, date.year),
+            DateOrder::Ymd => format!("{}{sep}{:0>2}{sep}{:0>2}", date.year, date.month, date.day),
+        };
+        out.push_str(&formatted);
+    }
+}
+
+/// A number as written in US style, without its separators.
+struct Number {
+    integer: String,
+    /// Whether it was written with thousands separators
+    grouped: bool,
+    fraction: Option<String>,
+}
+
+/// Parses "1234", "1,234", "1,234.56" or "3.5" at `start`, returning where
+/// it ends. Fails for anything that runs on into more separators and
+/// digits, such as "1.2.3" or "1,2".
+fn scan_number(chars: &[char], start: usize) -> Option<(usize, Number)> {
+    let digits_end = |from: usize| {
+        (from..chars.len())
+            .find(|&i| !chars[i].is_ascii_digit())
+            .unwrap_or(chars.len())
+    };
+
+    let mut i = digits_end(start);
+    if i == start {
+        return None;
+    }
+    let leading = i - start;
+    let mut integer: String = chars[start..i].iter().collect();
+    let mut grouped = false;
+
+    while leading <= 3 && chars.get(i) == Some(&',') {
+        let group_end = digits_end(i + 1);
+        if group_end - (i + 1) != 3 {
+            break;
+        }
+        integer.extend(&chars[i + 1..group_end]);
+        grouped = true;
+        i = group_end;
+    }
+
+    let mut fraction = None;
+    if chars.get(i) == Some(&'.') {
+        let fraction_end = digits_end(i + 1);
+        if fraction_end > i + 1 {
+            fraction = Some(chars[i + 1..fraction_end].iter().collect());
+            i = fraction_end;
+        }
+    }
+
+    let runs_on = matches!(chars.get(i), Some('.' | ','))
+        && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+    if runs_on || chars.get(i).is_some_and(|c| c.is_alphanumeric()) {
+        return None;
+    }
+
+    Some((
+        i,
+        Number {
+            integer,
+            grouped,
+            fraction,
+        },
+    ))
+}
+
+struct Date {
+    month: String,
+    day: String,
+    year: String,
+}
+
+/// Parses a US "M/D/YYYY" or "M/D/YY" date at `start`.
+fn scan_date(chars: &[char], start: usize) -> Option<(usize, Date)> {
+    let mut fields = Vec::with_capacity(3);
+    let mut i = start;
+
+    for field in 0..3 {
+        let end = (i..chars.len())
+            .find(|&j| !chars[j].is_ascii_digit())
+            .unwrap_or(chars.len());
+        let len = end - i;
+        let valid = if field < 2 {
+            (1..=2).contains(&len)
+        } else {
+            len == 2 || len == 4
+        };
+        if !valid {
+            return None;
+        }
+        fields.push(chars[i..end].iter().collect::<String>());
+        i = end;
+        if field < 2 {
+            if chars.get(i) != Some(&'/') {
+                return None;
+            }
+            i += 1;
+        }
+    }
+
+    if matches!(chars.get(i), Some(c) if c.is_alphanumeric() || *c == '/') {
+        return None;
+    }
+    let month: u32 = fields[0].parse().ok()?;
+    let day: u32 = fields[1].parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let year = fields.pop()?;
+    let day = fields.pop()?;
+    let month = fields.pop()?;
+    Some((i, Date { month, day, year }))
+}
+
+/// End of the word at `start`, counting ".", "," and "/" between digits
+/// as part of it.
+fn run_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        let separator_between_digits = matches!(chars[i], '.' | ',' | '/')
+            && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+        if !(chars[i].is_alphanumeric() || separator_between_digits) {
+            break;
+        }
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AMOUNTS: &str = "1,234.56 km, 2.5% on 3/5/2024 for $1,234.56.";
+    const SHORT_DATE: &str = "$5 million by 3/5/24";
+
+    fn format(tag: &str, text: &str) -> String {
+        Locale::parse(tag).unwrap().format(text)
+    }
+
+    #[test]
+    fn formats_each_locale() {
+        let cases = [
+            (
+                "en-US",
+                "1,234.56 km, 2.5% on 3/5/2024 for $1,234.56.",
+                "$5 million by 3/5/24",
+            ),
+            (
+                "en-CA",
+                "1,234.56 km, 2.5% on 2024-03-05 for $1,234.56.",
+                "$5 million by 3/5/24",
+            ),
+            (
+                "en-GB",
+                "1,234.56 km, 2.5% on 5/3/2024 for $1,234.56.",
+                "$5 million by 5/3/24",
+            ),
+            (
+                "de-DE",
+                "1.234,56 km, 2,5\u{a0}% on 5.3.2024 for 1.234,56\u{a0}$.",
+                "$5 million by 5.3.24",
+            ),
+            (
+                "de-CH",
+                "1’234.56 km, 2.5\u{a0}% on 5.3.2024 for $\u{a0}1’234.56.",
+                "$5 million by 5.3.24",
+            ),
+            (
+                "fr-FR",
+                "1\u{202f}234,56 km, 2,5\u{202f}% on 5/3/2024 for 1\u{202f}234,56\u{a0}$.",
+                "$5 million by 5/3/24",
+            ),
+            (
+                "fr-CH",
+                "1\u{202f}234.56 km, 2.5\u{a0}% on 5.3.2024 for 1\u{202f}234.56\u{a0}$.",
+                "$5 million by 5.3.24",
+            ),
+            (
+                "es-ES",
+                "1.234,56 km, 2,5% on 5/3/2024 for 1.234,56\u{a0}$.",
+                "$5 million by 5/3/24",
+            ),
+            (
+                "pt-BR",
+                "1.234,56 km, 2,5% on 5/3/2024 for $\u{a0}1.234,56.",
+                "$5 million by 5/3/24",
+            ),
+            (
+                "nl-NL",
+                "1.234,56 km, 2,5% on 5-3-2024 for $\u{a0}1.234,56.",
+                "$5 million by 5-3-24",
+            ),
+            (
+                "da-DK",
+                "1.234,56 km, 2,5\u{a0}% on 5.3.2024 for 1.234,56\u{a0}$.",
+                "$5 million by 5.3.24",
+            ),
+            (
+                "sv-SE",
+                "1\u{a0}234,56 km, 2,5\u{a0}% on 2024-03-05 for 1\u{a0}234,56\u{a0}$.",
+                "$5 million by 3/5/24",
+            ),
+            (
+                "nb-NO",
+                "1\u{a0}234,56 km, 2,5\u{a0}% on 5.3.2024 for 1\u{a0}234,56\u{a0}$.",
+                "$5 million by 5.3.24",
+            ),
+            (
+                "hu-HU",
+                "1\u{a0}234,56 km, 2,5\u{a0}% on 2024.03.05 for 1\u{a0}234,56\u{a0}$.",
+                "$5 million by 3/5/24",
+            ),
+            (
+                "ja-JP",
+                "1,234.56 km, 2.5% on 2024/03/05 for $1,234.56.",
+                "$5 million by 3/5/24",
+            ),
+        ];
+
+        for (tag, amounts, short_date) in cases {
+            assert_eq!(format(tag, AMOUNTS), amounts, "{}", tag);
+            assert_eq!(format(tag, SHORT_DATE), short_date, "{}", tag);
+        }
+    }
+
+    #[test]
+    fn leaves_ambiguous_forms_alone() {
+        let text = "Version 1.2.3 of mp3 v1.5 shipped in 2024 to 10.0.0.1, 1,2 or 12/31.";
+        assert_eq!(format("de-DE", text), text);
+    }
+
+    #[test]
+    fn keeps_ungrouped_numbers_ungrouped() {
+        assert_eq!(format("de-DE", "1234.5 and 12,345"), "1234,5 and 12.345");
+    }
+
+    #[test]
+    fn rejects_impossible_dates() {
+        assert_eq!(
+            format("en-GB", "13/5/2024 and 3/32/2024"),
+            "13/5/2024 and 3/32/2024"
+        );
+    }
+
+    #[test]
+    fn parses_tag_variants() {
+        assert_eq!(format("fr_ch", "2.5%"), "2.5\u{a0}%");
+        assert_eq!(format("zh-Hant-TW", "3/5/2024"), "2024/03/05");
+        assert_eq!(format("de", "2.5"), "2,5");
+        assert!(Locale::parse("xx-YY").is_err());
+    }
+}
@@ -1,63 +1,741 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use checkpoint::Checkpoint;
+use clap::{Parser, Subcommand, ValueEnum};
+use compress::Compression;
+use framing::{FrameReader, FrameWriter, Framing};
+use journal::Journal;
+use pool::Pool;
+use retry::RetryPolicy;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::{self, BufRead, Write};
-use std::path::PathBuf;
-use transcribe_rs::{engines::parakeet::ParakeetEngine, TranscriptionEngine};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use timestamps::TimestampFormat;
+use transcribe_rs::engines::parakeet::{
+    ParakeetEngine, ParakeetInferenceParams, TimestampGranularity,
+};
+use transcribe_rs::{TranscriptionEngine, TranscriptionResult};
+
+#[cfg(feature = "grpc")]
+mod access;
+mod alternatives;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod audio;
+mod batch;
+mod cache;
+mod captions;
+mod capture;
+mod chapters;
+mod checkpoint;
+mod compress;
+mod cues;
+mod dictation;
+mod doctor;
+mod dsp;
+mod fingerprint;
+mod flac;
+mod formats;
+mod framing;
+mod incremental;
+mod journal;
+#[cfg(target_os = "macos")]
+mod launchd;
+mod locale;
+mod memory;
+mod model;
+mod overlap;
+mod plan;
+mod pool;
+mod post_exec;
+mod power;
+mod recording;
+mod retry;
+mod sanity;
+mod search;
+mod second_pass;
+mod session;
+mod shm;
+mod speakers;
+mod sqlite;
+mod stats;
+mod stream;
+#[cfg(target_os = "macos")]
+mod system_audio;
+mod timestamps;
+mod voiceprint;
+mod wav;
+mod words;
+#[cfg(target_os = "macos")]
+mod xpc;
+
+/// Version of every JSON document we emit. Bump on any change that could
+/// break an existing consumer (removed or retyped fields).
+const SCHEMA_VERSION: u32 = 1;
+
+/// Version of the server protocol: the commands, their fields and the
+/// response envelope. Bump whenever an older app could no longer talk to
+/// this backend; `hello` refuses clients that expect another version.
+const PROTOCOL_VERSION: u32 = 1;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
     /// Run in server mode
     #[arg(short, long)]
     server: bool,
 
+    /// Message framing for the stdio server
+    #[arg(long, value_enum, default_value_t = Framing::Newline, global = true)]
+    framing: Framing,
+
+    /// Run as an XPC service (macOS); implied when launched from the bundled .xpc
+    #[arg(long)]
+    xpc: bool,
+
     /// Path to the audio file (CLI mode)
     #[arg(short, long)]
     file: Option<PathBuf>,
 
-    /// Path to the model directory or file (CLI mode)
-    #[arg(short, long)]
+    /// Path to the model directory (CLI, gRPC, capture and batch modes)
+    #[arg(short, long, global = true)]
     model: Option<PathBuf>,
 
-    /// Output format (json or text) (CLI mode)
+    /// Output format: json, msgpack, cbor, text or the name of a template
+    /// in --formats-dir (CLI mode)
     #[arg(short, long, default_value = "json")]
     output: String,
+
+    /// Handlebars templates, each NAME.hbs adding an --output format NAME
+    /// [default: ~/Library/Application Support/WhisperMac/formats]
+    #[arg(long, value_name = "DIR")]
+    formats_dir: Option<PathBuf>,
+
+    /// Transcribe in chunks of this many seconds, cut at pauses, writing
+    /// segments as each chunk finishes (CLI mode, json or text output).
+    /// Plain runs on 16 kHz mono WAV stream in 120-second chunks anyway;
+    /// shorter files make one chunk and the same output as a single decode
+    #[arg(long, value_name = "SECS")]
+    chunk_secs: Option<f64>,
+
+    /// Checkpoint finished chunks here so a crashed run loses at most one
+    /// chunk (CLI mode, with --chunk-secs)
+    #[arg(long, value_name = "DIR", requires = "chunk_secs")]
+    checkpoint_dir: Option<PathBuf>,
+
+    /// Continue from this input's checkpoint instead of starting over
+    #[arg(long, requires = "checkpoint_dir")]
+    resume: bool,
+
+    /// Write the output to this file instead of stdout (CLI mode)
+    #[arg(long, value_name = "PATH")]
+    out_file: Option<PathBuf>,
+
+    /// Compress the output (CLI mode)
+    #[arg(long, value_enum)]
+    compress: Option<Compression>,
+
+    /// Also append the transcript to this SQLite database (CLI mode)
+    #[arg(long, value_name = "PATH")]
+    out_sqlite: Option<PathBuf>,
+
+    /// Tag questions, hesitations and shouting in the output (CLI mode)
+    #[arg(long, conflicts_with = "chunk_secs")]
+    cues: bool,
+
+    /// Format numbers, amounts, dates and percentages for this locale,
+    /// e.g. de-DE or fr-FR (CLI mode)
+    #[arg(long, value_name = "TAG", conflicts_with = "chunk_secs")]
+    locale: Option<String>,
+
+    /// Group the transcript into topical chapters (CLI mode)
+    #[arg(long, conflicts_with = "chunk_secs")]
+    chapters: bool,
+
+    /// Flag segments where more than one person is speaking (CLI mode)
+    #[arg(long, conflicts_with = "chunk_secs")]
+    overlap: bool,
+
+    /// Re-decode the spans Parakeet is unsure of with this model: a Parakeet
+    /// model directory or a Whisper GGML file (CLI mode)
+    #[arg(long, value_name = "MODEL", conflicts_with = "chunk_secs")]
+    second_pass: Option<PathBuf>,
+
+    /// Confidence below which a span goes to the second-pass model
+    #[arg(
+        long,
+        value_name = "0-1",
+        default_value_t = 0.8,
+        requires = "second_pass"
+    )]
+    second_pass_threshold: f32,
+
+    /// List the words Parakeet is unsure of with what else it might have
+    /// heard, by decoding each stretch of speech four more times (CLI mode)
+    #[arg(long, conflicts_with = "chunk_secs")]
+    word_alternatives: bool,
+
+    /// Pipe the finished transcript's JSON into this shell command and
+    /// attach what it prints as `post_processing_output` (CLI mode)
+    #[arg(long, value_name = "CMD", conflicts_with = "chunk_secs")]
+    post_exec: Option<String>,
+
+    /// Stop once resident memory exceeds this many megabytes: CLI runs fail
+    /// before their next chunk, the server runs transcriptions one at a time
+    /// and refuses them if that is not enough
+    #[arg(long, value_name = "MB", global = true)]
+    max_memory_mb: Option<u64>,
+
+    /// Refuse inputs longer than this, e.g. 90m or 2h
+    #[arg(long, value_name = "DURATION", value_parser = audio::parse_duration, global = true)]
+    max_input_duration: Option<f64>,
+
+    /// Refuse input files larger than this many bytes
+    #[arg(long, value_name = "BYTES", global = true)]
+    max_input_bytes: Option<u64>,
+
+    /// Retry failed model loads and transcriptions this many times
+    #[arg(long, default_value_t = 0, global = true)]
+    retries: u32,
+
+    /// Wait before the first retry, doubling after each one up to 30 s
+    #[arg(long, value_name = "MS", default_value_t = 500, global = true)]
+    retry_backoff_ms: u64,
+
+    /// Append every completed transcription to this JSONL journal
+    #[arg(long, value_name = "PATH", global = true)]
+    journal: Option<PathBuf>,
+
+    /// Validate the model and inputs and print the resolved job as JSON
+    /// without transcribing anything (CLI and batch modes)
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Also write times as ms, hh:mm:ss.mmm or samples at 16 kHz, in a
+    /// `_ms`, `_clock` or `_samples` field next to each time in seconds
+    #[arg(long, value_enum, default_value_t = TimestampFormat::Seconds, global = true)]
+    timestamp_format: TimestampFormat,
+
+    /// When to ease off to save power: auto economizes on battery and when
+    /// the Mac runs hot (CLI and batch modes)
+    #[arg(long, value_enum, default_value_t = power::Policy::Auto, global = true)]
+    power_policy: power::Policy,
+
+    /// Lighter model directory to load instead of --model while economizing
+    /// on power (CLI and batch modes)
+    #[arg(long, value_name = "DIR", global = true)]
+    battery_model: Option<PathBuf>,
+
+    /// Flag (or, with suppress, remove) repetition loops and segments with
+    /// more text than their audio could hold (CLI and batch modes)
+    #[arg(long, value_enum, default_value_t = sanity::Mode::Flag, global = true)]
+    sanity_check: sanity::Mode,
+
+    /// Keep transcripts here and answer repeats of the same audio, model
+    /// and decoding options from it (CLI and server modes)
+    #[arg(long, value_name = "DIR", global = true, conflicts_with = "chunk_secs")]
+    cache_dir: Option<PathBuf>,
+
+    /// Enrolled speaker voiceprints [default: ~/Library/Application Support/WhisperMac/speakers]
+    #[arg(long, value_name = "DIR", global = true)]
+    speaker_dir: Option<PathBuf>,
 }
 
-#[derive(Serialize)]
+impl Args {
+    fn input_limits(&self) -> audio::InputLimits {
+        audio::InputLimits {
+            max_duration_secs: self.max_input_duration,
+            max_bytes: self.max_input_bytes,
+        }
+    }
+
+    /// `--chunk-secs`, or `DEFAULT_CHUNK_SECS` when nothing asked for needs
+    /// the whole result at once, so even a plain run of a long recording
+    /// streams its segments instead of holding them all.
+    fn streamed_chunk_secs(&self, file: &Path) -> Option<f64> {
+        if self.chunk_secs.is_some() {
+            return self.chunk_secs;
+        }
+        let whole_result = self.cues
+            || self.locale.is_some()
+            || self.chapters
+            || self.overlap
+            || self.second_pass.is_some()
+            || self.word_alternatives
+            || self.post_exec.is_some()
+            || self.cache_dir.is_some()
+            || self.out_sqlite.is_some()
+            || self.journal.is_some();
+        let streamable = matches!(self.output.as_str(), "json" | "text");
+        (streamable && !whole_result && incremental::can_chunk(file))
+            .then_some(incremental::DEFAULT_CHUNK_SECS)
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            initial_backoff: Duration::from_millis(self.retry_backoff_ms),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Run as a long-lived service (stdio protocol unless --grpc)
+    Serve {
+        /// Serve the gRPC API from proto/parakeet.proto
+        #[arg(long)]
+        grpc: bool,
+
+        /// Listen address for the gRPC server
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        listen: String,
+
+        /// File holding a token that gRPC clients must send as
+        /// `authorization: Bearer <token>`
+        #[arg(long, value_name = "FILE", requires = "grpc")]
+        auth_token_file: Option<PathBuf>,
+
+        /// Transcriptions a minute each gRPC client address may request;
+        /// unlimited when left out
+        #[arg(long, value_name = "N", requires = "grpc", value_parser = clap::value_parser!(u32).range(1..))]
+        rate_limit: Option<u32>,
+
+        /// Accept clients on a socket inherited from launchd (key under Sockets in the job plist)
+        #[arg(long, value_name = "NAME")]
+        launchd_socket: Option<String>,
+
+        /// With --launchd-socket, exit after this many minutes without a connected client
+        #[arg(long, value_name = "MINUTES", requires = "launchd_socket")]
+        idle_exit: Option<u64>,
+
+        /// Engines to keep loaded, so this many requests can run in parallel
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        workers: u32,
+    },
+
+    /// Transcribe live audio, printing one JSON event per line
+    Capture {
+        /// Audio to capture; `mic,system` transcribes both, labelling each event
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "mic")]
+        source: Vec<capture::Source>,
+
+        /// Stop after this long, e.g. 90s or 1h; runs until interrupted otherwise
+        #[arg(long, value_name = "DURATION", value_parser = audio::parse_duration)]
+        duration: Option<f64>,
+
+        /// Frames louder than this (dBFS) count as speech
+        #[arg(long, value_name = "DB", default_value_t = -45.0, allow_negative_numbers = true)]
+        vad_threshold_db: f32,
+
+        /// Silence that ends an utterance
+        #[arg(long, value_name = "MS", default_value_t = 600)]
+        min_silence_ms: u32,
+
+        /// Longest utterance before it is cut regardless of pauses
+        #[arg(long, value_name = "SECS", default_value_t = 30.0)]
+        max_utterance_secs: f32,
+
+        /// Also emit input level events this many times a second
+        #[arg(long, value_name = "HZ")]
+        level_hz: Option<f32>,
+
+        /// Emit dictation diffs (committed text plus a pending tail) while
+        /// speech is still in progress
+        #[arg(long)]
+        dictation: bool,
+
+        /// Emit each word with its timing as soon as partial decodes agree
+        /// on it, ahead of the utterance's transcript
+        #[arg(long)]
+        word_events: bool,
+
+        /// Cluster each source's utterances by speaker, tag transcripts with
+        /// their cluster and, when capture ends, emit a voiceprint vector per
+        /// cluster for matching speakers across recordings
+        #[arg(long)]
+        speaker_embeddings: bool,
+
+        /// Experimental: name the speaker of each transcript from the
+        /// enrolled voiceprints (see `speakers enroll`). Voiceprints also
+        /// capture the microphone, so names are only trustworthy between
+        /// speakers enrolled in the setup being captured
+        #[arg(long)]
+        identify_speakers: bool,
+
+        /// Lowest voiceprint similarity (up to 1) that names a speaker
+        #[arg(long, value_name = "SIMILARITY", default_value_t = 0.9)]
+        speaker_threshold: f32,
+
+        /// How much new speech triggers another partial decode with
+        /// --dictation or --word-events
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        partial_interval_ms: u64,
+
+        /// Append each transcript to this .srt or .vtt file as it finishes,
+        /// for tools that read live captions from a file
+        #[arg(long, value_name = "PATH")]
+        live_captions: Option<PathBuf>,
+
+        /// When capture ends, emit talk time, word counts, words per minute
+        /// and interruptions for each speaker
+        #[arg(long)]
+        stats: bool,
+
+        /// Record the 16 kHz mono audio each source fed the recognizer to
+        /// this .wav or .flac file; with several sources, each gets its own
+        /// file named after it, e.g. call.mic.wav
+        #[arg(long, value_name = "PATH")]
+        save_audio: Option<PathBuf>,
+    },
+
+    /// Transcribe many short 16 kHz mono WAV files, packing them into few
+    /// engine calls; prints one JSON line per file
+    Batch {
+        /// Files to transcribe
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Most audio to pack into one engine call
+        #[arg(long, value_name = "SECS", default_value_t = 60.0)]
+        pack_secs: f64,
+
+        /// Silence inserted between packed clips
+        #[arg(long, value_name = "SECS", default_value_t = 1.0)]
+        gap_secs: f64,
+
+        /// The files are consecutive parts of one recording, in order:
+        /// print a single transcript on the whole recording's timeline
+        #[arg(long, conflicts_with_all = ["pack_secs", "gap_secs"])]
+        concat: bool,
+    },
+
+    /// Find segments of past transcripts kept by --journal or --out-sqlite,
+    /// printing the best matches as JSON lines
+    Search {
+        /// Words that must all appear; "..." for a phrase, OR between words,
+        /// word* for a prefix
+        query: String,
+
+        /// Journal or --out-sqlite database to search; repeat for several
+        #[arg(long = "in", value_name = "FILE", required = true)]
+        sources: Vec<PathBuf>,
+
+        /// Search index, updated from the sources on every search
+        /// [default: ~/Library/Application Support/WhisperMac/search.sqlite]
+        #[arg(long, value_name = "FILE")]
+        index: Option<PathBuf>,
+
+        /// Most matches to print
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Manage enrolled speakers, whose names label capture transcripts
+    /// (experimental)
+    Speakers {
+        #[command(subcommand)]
+        action: SpeakersAction,
+    },
+
+    /// Check the OS, memory, acceleration, downloaded models and
+    /// microphone permission, printing a JSON report for support
+    Doctor,
+
+    /// Print the JSON Schema of our outputs
+    Schema {
+        /// Output type to describe; prints all of them when omitted
+        #[arg(value_enum)]
+        kind: Option<SchemaKind>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SpeakersAction {
+    /// Store a speaker's voiceprint from a 16 kHz mono WAV of them talking
+    Enroll { name: String, sample: PathBuf },
+    /// List enrolled speakers
+    List,
+    /// Forget an enrolled speaker
+    Remove { name: String },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SchemaKind {
+    /// CLI result and the `data` of a server `transcribe` response
+    Transcription,
+    /// Server protocol response
+    Response,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 struct TranscriptionOutput {
+    schema_version: u32,
+    status: TranscriptionStatus,
     text: String,
     segments: Vec<Segment>,
-    processing_time_ms: u128,
+    processing_time_ms: u64,
+    /// Highest resident memory of the backend process so far
+    peak_rss_bytes: u64,
+    /// Questions, hesitations and shouting, when asked for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cues: Vec<cues::Cue>,
+    /// Topical chapters, when asked for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chapters: Vec<chapters::Chapter>,
+    /// Spans re-decoded by the `--second-pass` model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<second_pass::Revision>,
+    /// Words Parakeet is unsure of, with what else it might have heard,
+    /// when asked for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    alternatives: Vec<alternatives::UncertainWord>,
+    /// What `--post-exec` printed for this transcript
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    post_processing_output: Option<String>,
+    /// Answered from `--cache-dir` without decoding
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    cached: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum TranscriptionStatus {
+    Ok,
+    /// The input was empty or silent, or nothing intelligible was decoded
+    NoSpeech,
+    /// The session was paused, so the audio was dropped without decoding
+    Paused,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 struct Segment {
     start: f64,
     end: f64,
     text: String,
+    /// More than one person was speaking, so the text is less reliable
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    overlap: bool,
+    /// Looks like a decoding artifact rather than speech
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    suspect: Option<sanity::Suspect>,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "command", rename_all = "snake_case")]
 enum Command {
+    /// Handshake, sent first: answered with what this backend supports, or
+    /// an error when the client speaks another protocol version
+    Hello {
+        protocol_version: u32,
+    },
     LoadModel {
         path: String,
     },
     Transcribe {
-        path: String,
+        /// WAV file to transcribe
+        path: Option<String>,
+        /// POSIX shared memory object holding f32 samples, instead of `path`
+        shm: Option<String>,
+        /// Byte length of the samples in `shm`
+        len: Option<usize>,
+        /// Samples from the audio frame following the command (length-prefixed framing)
+        #[serde(skip)]
+        samples: Option<Vec<f32>>,
+        /// Carry context over from earlier utterances sent with the same id
+        session_id: Option<String>,
         options: Option<TranscribeOptions>,
     },
+    /// Drops a session's carried-over context
+    EndSession {
+        session_id: String,
+    },
+    /// Drops audio sent to the session until `resume`, keeping its context
+    Pause {
+        session_id: String,
+    },
+    Resume {
+        session_id: String,
+    },
     Ping,
 }
 
-#[derive(Deserialize, Debug)]
+impl Command {
+    /// Every command's `name`, as advertised by `hello`
+    const NAMES: &'static [&'static str] = &[
+        "hello",
+        "load_model",
+        "transcribe",
+        "end_session",
+        "pause",
+        "resume",
+        "ping",
+    ];
+
+    /// The session whose state the command reads or changes.
+    fn session_id(&self) -> Option<&str> {
+        match self {
+            Command::Transcribe { session_id, .. } => session_id.as_deref(),
+            Command::EndSession { session_id }
+            | Command::Pause { session_id }
+            | Command::Resume { session_id } => Some(session_id),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Hello { .. } => "hello",
+            Command::LoadModel { .. } => "load_model",
+            Command::Transcribe { .. } => "transcribe",
+            Command::EndSession { .. } => "end_session",
+            Command::Pause { .. } => "pause",
+            Command::Resume { .. } => "resume",
+            Command::Ping => "ping",
+        }
+    }
+}
+
+/// Per-request overrides sent with `transcribe`. Anything left out keeps the
+/// engine's behaviour, so one warm process can serve dictation and
+/// captioning clients side by side.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
 struct TranscribeOptions {
-    // Add future options here if needed
+    /// Only "auto" is accepted: Parakeet picks the language itself and has
+    /// no way to be told which one to expect
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    /// Whether the response carries segments or just the text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<ResponseFormat>,
+    /// Timestamp granularity of the returned segments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    granularity: Option<Granularity>,
+    /// Peak level in dBFS below which the audio is reported as no speech
+    /// without running the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    silence_threshold_db: Option<f32>,
+    /// Spellings to enforce on matching words of the transcript, e.g.
+    /// "Kubernetes" or "WhisperMac"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    vocabulary: Vec<String>,
+    /// Tag questions, hesitations and shouting in the transcript
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    cues: bool,
+    /// Formats numbers, amounts, dates and percentages for this locale,
+    /// e.g. "de-DE"; US style when left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locale: Option<String>,
+    /// Group the transcript into topical chapters
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    chapters: bool,
+    /// Flag segments where more than one person is speaking
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    overlap: bool,
+    /// Another format to add next to every time in seconds, as with
+    /// --timestamp-format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp_format: Option<TimestampFormat>,
+    /// Flag or remove repetition loops and implausibly dense segments;
+    /// flagged when left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sanity_check: Option<sanity::Mode>,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ResponseFormat {
+    /// The full `TranscriptionOutput`
+    Json,
+    /// `TranscriptionOutput` with the segments left out
+    Text,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Granularity {
+    Token,
+    Word,
+    Segment,
+}
+
+impl TranscribeOptions {
+    fn validate(&self) -> Result<()> {
+        if let Some(language) = self.language.as_deref().filter(|l| *l != "auto") {
+            anyhow::bail!(
+                "Unsupported language '{}': Parakeet detects the language itself, only 'auto' is accepted",
+                language
+            );
+        }
+        if let Some(tag) = &self.locale {
+            locale::Locale::parse(tag)?;
+        }
+        Ok(())
+    }
+
+    fn inference_params(&self) -> Option<ParakeetInferenceParams> {
+        let granularity = match self.granularity? {
+            Granularity::Token => TimestampGranularity::Token,
+            Granularity::Word => TimestampGranularity::Word,
+            Granularity::Segment => TimestampGranularity::Segment,
+        };
+        Some(ParakeetInferenceParams {
+            timestamp_granularity: granularity,
+        })
+    }
+
+    fn silence_peak(&self) -> f32 {
+        self.silence_threshold_db
+            .map_or(audio::SILENCE_PEAK, |db| 10f32.powf(db / 20.0))
+    }
+
+    /// Applies the overrides that act on the finished transcript.
+    /// `samples` is the audio it was decoded from, needed for `cues` and
+    /// `overlap`.
+    fn apply(&self, output: &mut TranscriptionOutput, samples: Option<&[f32]>) {
+        sanity::check(output, self.sanity_check.unwrap_or_default());
+        if !self.vocabulary.is_empty() {
+            output.text = words::apply_vocabulary(&output.text, &self.vocabulary);
+            for segment in &mut output.segments {
+                segment.text = words::apply_vocabulary(&segment.text, &self.vocabulary);
+            }
+        }
+        // Validated already, so a bad tag can't get this far.
+        if let Some(locale) = self
+            .locale
+            .as_deref()
+            .and_then(|t| locale::Locale::parse(t).ok())
+        {
+            output.text = locale.format(&output.text);
+            for segment in &mut output.segments {
+                segment.text = locale.format(&segment.text);
+            }
+        }
+        if let Some(samples) = samples.filter(|_| self.cues) {
+            output.cues = cues::analyze(samples, &output.segments);
+        }
+        if let Some(samples) = samples.filter(|_| self.overlap) {
+            overlap::flag(samples, &mut output.segments);
+        }
+        if self.chapters {
+            output.chapters = chapters::detect(&output.segments);
+        }
+        if self.format == Some(ResponseFormat::Text) {
+            output.segments.clear();
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
 #[serde(tag = "status", rename_all = "snake_case")]
 enum Response {
     Ok {
@@ -69,80 +747,875 @@ enum Response {
     },
 }
 
+/// A response as written to the wire, stamped with the schema version and
+/// the id of the request it answers.
+#[derive(Serialize, JsonSchema)]
+struct ResponseEnvelope {
+    schema_version: u32,
+    /// Echoes the request's `request_id`, or the id generated for it
+    request_id: String,
+    #[serde(flatten)]
+    response: Response,
+}
+
+fn encode_response(request_id: &str, response: Response) -> serde_json::Result<Vec<u8>> {
+    if let Response::Error { message } = &response {
+        log::warn!("Request {} failed: {}", request_id, message);
+    }
+
+    serde_json::to_vec(&ResponseEnvelope {
+        schema_version: SCHEMA_VERSION,
+        request_id: request_id.to_string(),
+        response,
+    })
+}
+
+/// The `request_id` a client tagged its message with, or a fresh one for
+/// clients that don't. Read separately from the command so that even a
+/// message that fails to parse is answered under the client's id.
+fn request_id(message: &[u8]) -> String {
+    #[derive(Deserialize)]
+    struct Tagged {
+        request_id: Option<String>,
+    }
+
+    serde_json::from_slice::<Tagged>(message)
+        .ok()
+        .and_then(|tagged| tagged.request_id)
+        .unwrap_or_else(new_request_id)
+}
+
+/// `data` of the `hello` response.
+#[derive(Serialize)]
+struct Handshake {
+    protocol_version: u32,
+    schema_version: u32,
+    backend_version: &'static str,
+    commands: &'static [&'static str],
+    engines: Vec<EngineInfo>,
+}
+
+#[derive(Serialize)]
+struct EngineInfo {
+    name: &'static str,
+    /// Model directory loaded with `load_model`, if any
+    model: Option<PathBuf>,
+    workers: usize,
+}
+
+fn handshake(client_version: u32, model: Option<PathBuf>, workers: usize) -> Response {
+    if client_version != PROTOCOL_VERSION {
+        return Response::Error {
+            message: format!(
+                "Protocol version mismatch: the app speaks version {} but parakeet-backend {} speaks version {}; reinstall so both match",
+                client_version,
+                env!("CARGO_PKG_VERSION"),
+                PROTOCOL_VERSION
+            ),
+        };
+    }
+
+    let handshake = Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        schema_version: SCHEMA_VERSION,
+        backend_version: env!("CARGO_PKG_VERSION"),
+        commands: Command::NAMES,
+        engines: vec![EngineInfo {
+            name: "parakeet",
+            model,
+            workers,
+        }],
+    };
+    match serde_json::to_value(handshake) {
+        Ok(data) => Response::Ok { data: Some(data) },
+        Err(e) => Response::Error {
+            message: e.to_string(),
+        },
+    }
+}
+
+fn new_request_id() -> String {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    format!("backend-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// The loaded engine plus the state that must survive between commands.
+struct Backend {
+    engine: ParakeetEngine,
+    model_path: Option<PathBuf>,
+    journal: Option<Journal>,
+    cache: Option<cache::Cache>,
+    memory_budget: Option<memory::Budget>,
+    retry: RetryPolicy,
+    limits: audio::InputLimits,
+    sessions: HashMap<String, session::Session>,
+    /// Workers serving alongside this one, itself included, as `hello`
+    /// reports them
+    workers: usize,
+}
+
+impl Backend {
+    fn new() -> Self {
+        Self {
+            engine: ParakeetEngine::new(),
+            model_path: None,
+            journal: None,
+            cache: None,
+            memory_budget: None,
+            retry: RetryPolicy::NONE,
+            limits: audio::InputLimits::default(),
+            sessions: HashMap::new(),
+            workers: 1,
+        }
+    }
+
+    fn from_args(args: &Args) -> Self {
+        Self {
+            journal: args.journal.clone().map(Journal::new),
+            cache: args.cache_dir.clone().map(cache::Cache::new),
+            memory_budget: args.max_memory_mb.map(memory::Budget::from_mb),
+            retry: args.retry_policy(),
+            limits: args.input_limits(),
+            ..Self::new()
+        }
+    }
+
+    fn pool(args: &Args, workers: u32) -> Pool {
+        Pool::new((0..workers).map(|_| Self {
+            workers: workers as usize,
+            ..Self::from_args(args)
+        }))
+    }
+}
+
+/// Audio for one transcription, once the command's source is resolved.
+enum AudioInput {
+    File(PathBuf),
+    Samples(Vec<f32>),
+}
+
+impl AudioInput {
+    fn path(&self) -> Option<&Path> {
+        match self {
+            AudioInput::File(path) => Some(path),
+            AudioInput::Samples(_) => None,
+        }
+    }
+
+    fn transcribe(
+        self,
+        engine: &mut ParakeetEngine,
+        retry: RetryPolicy,
+    ) -> retry::EngineResult<TranscriptionResult> {
+        self.transcribe_with(engine, retry, &TranscribeOptions::default())
+    }
+
+    fn transcribe_with(
+        self,
+        engine: &mut ParakeetEngine,
+        retry: RetryPolicy,
+        options: &TranscribeOptions,
+    ) -> retry::EngineResult<TranscriptionResult> {
+        let peak = options.silence_peak();
+        let no_speech = || TranscriptionResult {
+            text: String::new(),
+            segments: Some(Vec::new()),
+        };
+
+        match self {
+            AudioInput::File(path) => match wav::Wav::open(&path) {
+                // Each attempt decodes straight from the mapping, so the
+                // engine's buffer is the only copy of the samples.
+                Ok(wav) if wav.is_engine_format() => {
+                    if wav.is_silent(peak) {
+                        return Ok(no_speech());
+                    }
+                    retry.run("Transcription", || {
+                        engine.transcribe_samples(wav.to_vec(), options.inference_params())
+                    })
+                }
+                _ => {
+                    if audio::file_is_silent(&path, peak) {
+                        return Ok(no_speech());
+                    }
+                    retry.run("Transcription", || {
+                        engine.transcribe_file(&path, options.inference_params())
+                    })
+                }
+            },
+            AudioInput::Samples(samples) if audio::is_silent(&samples, peak) => Ok(no_speech()),
+            // Retrying needs a copy of the samples per attempt, so skip that
+            // when retries are off.
+            AudioInput::Samples(samples) if retry.is_disabled() => {
+                engine.transcribe_samples(samples, options.inference_params())
+            }
+            AudioInput::Samples(samples) => retry.run("Transcription", || {
+                engine.transcribe_samples(samples.clone(), options.inference_params())
+            }),
+        }
+    }
+
+    fn into_samples(self) -> Result<Vec<f32>> {
+        match self {
+            AudioInput::File(path) => wav::read(&path),
+            AudioInput::Samples(samples) => Ok(samples),
+        }
+    }
+
+    fn check_limits(&self, limits: &audio::InputLimits) -> Result<()> {
+        match self {
+            AudioInput::File(path) => limits.check_file(path),
+            AudioInput::Samples(samples) => {
+                limits.check_samples(samples.len(), incremental::SAMPLE_RATE)
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> io::Result<String> {
+        match self {
+            AudioInput::File(path) => fingerprint::hash_file(path),
+            AudioInput::Samples(samples) => Ok(fingerprint::hash_samples(samples)),
+        }
+    }
+}
+
 fn main() -> Result<()> {
     env_logger::init();
     let args = Args::parse();
 
-    if args.server {
-        run_server()
-    } else {
-        run_cli(args)
+    if args.dry_run && !matches!(args.mode, None | Some(Mode::Batch { .. })) {
+        anyhow::bail!("--dry-run only applies to CLI and batch modes");
+    }
+
+    match args.mode {
+        Some(Mode::Serve {
+            grpc: true,
+            ref listen,
+            ref auth_token_file,
+            rate_limit,
+            ..
+        }) => run_grpc(listen, &args, auth_token_file.as_deref(), rate_limit),
+        Some(Mode::Serve {
+            launchd_socket: Some(ref name),
+            idle_exit,
+            workers,
+            ..
+        }) => run_launchd(
+            Backend::pool(&args, workers),
+            name,
+            idle_exit.map(|m| Duration::from_secs(m * 60)),
+            args.framing,
+        ),
+        Some(Mode::Serve { workers, .. }) => {
+            run_server(Backend::pool(&args, workers), args.framing)
+        }
+        Some(Mode::Capture {
+            ref source,
+            duration,
+            vad_threshold_db,
+            min_silence_ms,
+            max_utterance_secs,
+            level_hz,
+            dictation,
+            word_events,
+            speaker_embeddings,
+            identify_speakers,
+            speaker_threshold,
+            partial_interval_ms,
+            ref live_captions,
+            stats,
+            ref save_audio,
+        }) => {
+            let speakers = if identify_speakers {
+                let dir = speaker_dir(&args)?;
+                Some(speakers::Identifier::new(
+                    speakers::load(&dir)?,
+                    speaker_threshold,
+                ))
+            } else {
+                None
+            };
+            let options = capture::Options {
+                vad: stream::VadConfig {
+                    threshold_db: vad_threshold_db,
+                    min_silence_ms,
+                    max_utterance_secs,
+                    ..Default::default()
+                },
+                level_hz,
+                partial_interval: (dictation || word_events)
+                    .then(|| Duration::from_millis(partial_interval_ms)),
+                dictation,
+                word_events,
+                speaker_embeddings,
+                speakers,
+                duration: duration.map(Duration::from_secs_f64),
+                live_captions: live_captions.clone(),
+                stats,
+                timestamps: args.timestamp_format,
+                save_audio: save_audio.clone(),
+            };
+            run_capture(&args, source, &options)
+        }
+        Some(Mode::Batch {
+            ref files,
+            pack_secs,
+            gap_secs,
+            concat,
+        }) => {
+            let options = batch::Options {
+                pack_secs,
+                gap_secs,
+                timestamps: args.timestamp_format,
+                sanity_check: args.sanity_check,
+                concat,
+            };
+            if args.dry_run {
+                plan::batch(&args, files, &options)
+            } else {
+                run_batch(&args, files, &options)
+            }
+        }
+        Some(Mode::Search {
+            ref query,
+            ref sources,
+            ref index,
+            limit,
+        }) => {
+            let index = match index {
+                Some(index) => index.clone(),
+                None => search::default_index()?,
+            };
+            search::run(&index, sources, query, limit, args.timestamp_format)
+        }
+        Some(Mode::Speakers { ref action }) => run_speakers(&args, action),
+        Some(Mode::Doctor) => doctor::run(args.model.as_deref()),
+        Some(Mode::Schema { kind }) => print_schema(kind),
+        None if args.xpc || launched_as_xpc_service() => run_xpc(&args),
+        None if args.server => run_server(Backend::pool(&args, 1), args.framing),
+        None if args.dry_run => plan::cli(&args),
+        None => run_cli(args),
     }
 }
 
-fn run_server() -> Result<()> {
+fn run_capture(args: &Args, sources: &[capture::Source], options: &capture::Options) -> Result<()> {
+    let model = args
+        .model
+        .as_deref()
+        .context("Model path required in capture mode")?;
+    model::validate(model)?;
+
+    let retry = args.retry_policy();
     let mut engine = ParakeetEngine::new();
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
+    retry
+        .run("Model load", || engine.load_model(model))
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    capture::run(&mut engine, retry, sources, options)
+}
+
+fn run_batch(args: &Args, files: &[PathBuf], options: &batch::Options) -> Result<()> {
+    let model = args
+        .model
+        .as_deref()
+        .context("Model path required in batch mode")?;
+    let economize = power::govern(args.power_policy);
+    let model = match &args.battery_model {
+        Some(lighter) if economize => lighter,
+        _ => model,
+    };
+    model::validate(model)?;
+
+    let retry = args.retry_policy();
+    let mut engine = ParakeetEngine::new();
+    retry
+        .run("Model load", || engine.load_model(model))
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    batch::run(&mut engine, retry, files, options, io::stdout().lock())
+}
+
+fn speaker_dir(args: &Args) -> Result<PathBuf> {
+    match &args.speaker_dir {
+        Some(dir) => Ok(dir.clone()),
+        None => speakers::default_dir(),
+    }
+}
+
+fn run_speakers(args: &Args, action: &SpeakersAction) -> Result<()> {
+    let dir = speaker_dir(args)?;
+    match action {
+        SpeakersAction::Enroll { name, sample } => {
+            speakers::enroll(&dir, name, sample)?;
+            eprintln!("Enrolled {}", name);
+        }
+        SpeakersAction::List => {
+            for profile in speakers::load(&dir)? {
+                println!("{}", profile.name);
+            }
+        }
+        SpeakersAction::Remove { name } => speakers::remove(&dir, name)?,
+    }
+    Ok(())
+}
+
+fn print_schema(kind: Option<SchemaKind>) -> Result<()> {
+    let transcription = || schemars::schema_for!(TranscriptionOutput);
+    let response = || schemars::schema_for!(ResponseEnvelope);
+
+    let schema = match kind {
+        Some(SchemaKind::Transcription) => serde_json::to_value(transcription())?,
+        Some(SchemaKind::Response) => serde_json::to_value(response())?,
+        None => serde_json::json!({
+            "schema_version": SCHEMA_VERSION,
+            "transcription": transcription(),
+            "response": response(),
+        }),
+    };
+
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+#[cfg(feature = "grpc")]
+fn run_grpc(
+    listen: &str,
+    args: &Args,
+    auth_token_file: Option<&Path>,
+    rate_limit: Option<u32>,
+) -> Result<()> {
+    let access = access::Access::new(auth_token_file, rate_limit)?;
+    let guards = grpc::Guards {
+        limits: args.input_limits(),
+        retry: args.retry_policy(),
+        memory_budget: args.max_memory_mb.map(memory::Budget::from_mb),
+    };
+    grpc::run(listen, args.model.as_deref(), access, guards)
+}
+
+#[cfg(not(feature = "grpc"))]
+fn run_grpc(
+    _listen: &str,
+    _args: &Args,
+    _auth_token_file: Option<&Path>,
+    _rate_limit: Option<u32>,
+) -> Result<()> {
+    anyhow::bail!("gRPC support is not compiled in; rebuild with --features grpc")
+}
+
+#[cfg(target_os = "macos")]
+fn launched_as_xpc_service() -> bool {
+    xpc::launched_as_service()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn launched_as_xpc_service() -> bool {
+    false
+}
+
+#[cfg(target_os = "macos")]
+fn run_launchd(
+    pool: Pool,
+    name: &str,
+    idle_exit: Option<Duration>,
+    framing: Framing,
+) -> Result<()> {
+    launchd::run(pool, name, idle_exit, framing)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn run_launchd(
+    _pool: Pool,
+    _name: &str,
+    _idle_exit: Option<Duration>,
+    _framing: Framing,
+) -> Result<()> {
+    anyhow::bail!("launchd socket activation is only available on macOS")
+}
+
+#[cfg(target_os = "macos")]
+fn run_xpc(args: &Args) -> Result<()> {
+    xpc::run(Backend::from_args(args))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn run_xpc(_args: &Args) -> Result<()> {
+    anyhow::bail!("XPC service mode is only available on macOS")
+}
+
+fn run_server(pool: Pool, framing: Framing) -> Result<()> {
+    serve_connection(&pool, io::stdin().lock(), io::stdout(), framing)
+}
+
+/// Runs the command protocol over one client connection. The pool is
+/// shared so that socket clients reuse whatever model is already loaded.
+///
+/// With a single worker requests are answered one at a time, in order.
+/// With more, each request runs on its own thread and responses go out as
+/// they finish, matched up by `request_id`.
+fn serve_connection<R: BufRead, W: Write + Send>(
+    pool: &Pool,
+    input: R,
+    output: W,
+    framing: Framing,
+) -> Result<()> {
+    let mut reader = FrameReader::new(input, framing);
+    let writer = Mutex::new(FrameWriter::new(output, framing));
+    let writer = &writer;
 
     // Signal ready
-    writeln!(stdout, "PARAKEET_SERVER_READY")?;
-    stdout.flush()?;
+    writer
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .write_frame(b"PARAKEET_SERVER_READY")?;
+
+    // Requests without a session go through a queue as deep as the pool,
+    // drained by one thread per engine, so a burst of them waits for an
+    // engine (and reading stops once the queue is full) rather than each
+    // taking a thread of its own.
+    let (free_queue, free_commands) = mpsc::sync_channel::<(String, Command)>(pool.len());
+    let free_commands = &Mutex::new(free_commands);
 
-    for line in stdin.lock().lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            continue;
+    thread::scope(|scope| {
+        // Moved in, so the free threads stop once reading does.
+        let free_queue = free_queue;
+        if pool.len() > 1 {
+            for _ in 0..pool.len() {
+                scope.spawn(move || loop {
+                    let next = free_commands
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .recv();
+                    match next {
+                        Ok((request_id, command)) => respond(pool, writer, request_id, command),
+                        Err(_) => break,
+                    }
+                });
+            }
         }
 
-        let response = match serde_json::from_str::<Command>(&line) {
-            Ok(command) => process_command(&mut engine, command),
-            Err(e) => Response::Error {
-                message: format!("Invalid JSON: {}", e),
-            },
-        };
+        // Commands of one session go through a queue drained by a thread of
+        // its own, so they run in the order they arrived while requests
+        // without a session run alongside them.
+        let mut sessions: HashMap<String, mpsc::Sender<(String, Command)>> = HashMap::new();
+
+        while let Some(frame) = reader.read_frame()? {
+            let request_id = request_id(&frame);
+            let command = match serde_json::from_slice::<Command>(&frame) {
+                Ok(mut command) => match read_audio_frame(&mut reader, &mut command) {
+                    Ok(true) => Ok(command),
+                    Ok(false) => break,
+                    Err(e) => Err(Response::Error {
+                        message: format!("Invalid audio frame: {}", e),
+                    }),
+                },
+                Err(e) => Err(Response::Error {
+                    message: format!("Invalid JSON: {}", e),
+                }),
+            };
 
-        writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
-        stdout.flush()?;
+            match command {
+                Ok(command) if pool.len() > 1 => match command.session_id().map(str::to_string) {
+                    Some(session_id) => {
+                        let ends = matches!(command, Command::EndSession { .. });
+                        let queue = sessions.entry(session_id.clone()).or_insert_with(|| {
+                            let (queue, commands) = mpsc::channel();
+                            scope.spawn(move || {
+                                for (request_id, command) in commands {
+                                    respond(pool, writer, request_id, command);
+                                }
+                            });
+                            queue
+                        });
+                        // The receiving thread only stops once this sender
+                        // is dropped, so the send cannot fail.
+                        let _ = queue.send((request_id, command));
+                        if ends {
+                            sessions.remove(&session_id);
+                        }
+                    }
+                    None => {
+                        // The free threads only stop once this sender is
+                        // dropped, so the send cannot fail.
+                        let _ = free_queue.send((request_id, command));
+                    }
+                },
+                Ok(command) => {
+                    let response = pool.process(&request_id, command);
+                    send_response(writer, &request_id, response)?;
+                }
+                Err(response) => send_response(writer, &request_id, response)?,
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Runs a command on the pool and sends its response, logging rather than
+/// failing when the client is gone.
+fn respond<W: Write>(
+    pool: &Pool,
+    writer: &Mutex<FrameWriter<W>>,
+    request_id: String,
+    command: Command,
+) {
+    let response = pool.process(&request_id, command);
+    if let Err(e) = send_response(writer, &request_id, response) {
+        log::warn!("Failed to send response to {}: {:#}", request_id, e);
     }
+}
 
+fn send_response<W: Write>(
+    writer: &Mutex<FrameWriter<W>>,
+    request_id: &str,
+    response: Response,
+) -> Result<()> {
+    let frame = encode_response(request_id, response)?;
+    writer
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .write_frame(&frame)?;
     Ok(())
 }
 
-fn process_command(engine: &mut ParakeetEngine, command: Command) -> Response {
+/// With length-prefixed framing, a `transcribe` command without `path` or
+/// `shm` is followed by a frame of raw samples. Returns false if the stream
+/// ended before that frame arrived.
+fn read_audio_frame<R: BufRead>(
+    reader: &mut FrameReader<R>,
+    command: &mut Command,
+) -> io::Result<bool> {
+    if reader.framing() != Framing::LengthPrefixed {
+        return Ok(true);
+    }
+
+    if let Command::Transcribe {
+        path: None,
+        shm: None,
+        samples,
+        ..
+    } = command
+    {
+        match reader.read_frame()? {
+            Some(frame) => *samples = Some(framing::decode_samples(&frame)?),
+            None => return Ok(false),
+        }
+    }
+
+    Ok(true)
+}
+
+fn process_command(backend: &mut Backend, request_id: &str, command: Command) -> Response {
+    log::debug!("Request {}: {}", request_id, command.name());
+    let engine = &mut backend.engine;
     match command {
         Command::Ping => Response::Ok { data: None },
+        Command::Hello { protocol_version } => handshake(
+            protocol_version,
+            backend.model_path.clone(),
+            backend.workers,
+        ),
+        Command::EndSession { session_id } => {
+            backend.sessions.remove(&session_id);
+            Response::Ok { data: None }
+        }
+        // Pausing a session that has not been used yet opens it paused.
+        Command::Pause { session_id } => {
+            backend
+                .sessions
+                .entry(session_id)
+                .or_default()
+                .set_paused(true);
+            Response::Ok { data: None }
+        }
+        Command::Resume { session_id } => {
+            if let Some(session) = backend.sessions.get_mut(&session_id) {
+                session.set_paused(false);
+            }
+            Response::Ok { data: None }
+        }
         Command::LoadModel { path } => {
-            match engine.load_model(&PathBuf::from(path)) {
-                Ok(_) => Response::Ok { data: None },
+            let path = PathBuf::from(path);
+            if backend.model_path.as_ref() == Some(&path) {
+                return Response::Ok { data: None };
+            }
+
+            if let Err(e) = model::validate(&path) {
+                return Response::Error {
+                    message: format!("{:#}", e),
+                };
+            }
+
+            match backend.retry.run("Model load", || engine.load_model(&path)) {
+                Ok(_) => {
+                    backend.model_path = Some(path);
+                    Response::Ok { data: None }
+                }
                 Err(e) => Response::Error {
                     message: format!("Failed to load model: {}", e),
                 },
             }
         }
-        Command::Transcribe { path, options: _ } => {
+        Command::Transcribe {
+            path,
+            shm,
+            len,
+            samples,
+            session_id,
+            options,
+        } => {
+            if let Some(Err(e)) = backend.memory_budget.map(|budget| budget.check()) {
+                return Response::Error {
+                    message: e.to_string(),
+                };
+            }
+
+            let options = options.unwrap_or_default();
+            if let Err(e) = options.validate() {
+                return Response::Error {
+                    message: e.to_string(),
+                };
+            }
+
             let start_time = std::time::Instant::now();
-            match engine.transcribe_file(&PathBuf::from(path), None) {
-                Ok(result) => {
-                    let duration = start_time.elapsed();
-                     let segments: Vec<Segment> = result
-                        .segments
-                        .unwrap_or_default()
-                        .into_iter()
-                        .map(|s| Segment {
-                            start: s.start as f64,
-                            end: s.end as f64,
-                            text: s.text,
-                        })
-                        .collect();
-
-                    let output = TranscriptionOutput {
-                        text: result.text,
-                        segments,
-                        processing_time_ms: duration.as_millis(),
+            let paused = session_id
+                .as_ref()
+                .and_then(|id| backend.sessions.get(id))
+                .is_some_and(session::Session::is_paused);
+            if paused {
+                let mut output = to_output(
+                    TranscriptionResult {
+                        text: String::new(),
+                        segments: Some(Vec::new()),
+                    },
+                    start_time.elapsed(),
+                );
+                output.status = TranscriptionStatus::Paused;
+                return match serde_json::to_value(&output) {
+                    Ok(val) => Response::Ok { data: Some(val) },
+                    Err(e) => Response::Error {
+                        message: e.to_string(),
+                    },
+                };
+            }
+
+            let audio = match (path, shm, samples) {
+                (Some(path), None, None) => AudioInput::File(PathBuf::from(path)),
+                (None, None, Some(samples)) => AudioInput::Samples(samples),
+                (None, Some(name), None) => match shm::read_samples(&name, len) {
+                    Ok(samples) => AudioInput::Samples(samples),
+                    Err(e) => {
+                        return Response::Error {
+                            message: format!("Failed to read shared memory: {:#}", e),
+                        }
+                    }
+                },
+                _ => {
+                    return Response::Error {
+                        message: "transcribe requires exactly one audio source: 'path', 'shm' or an audio frame".to_string(),
+                    }
+                }
+            };
+
+            if let Err(e) = audio.check_limits(&backend.limits) {
+                return Response::Error {
+                    message: e.to_string(),
+                };
+            }
+
+            // Hash before the samples are handed to the engine.
+            let fingerprint =
+                (backend.journal.is_some() || backend.cache.is_some()).then(|| audio.fingerprint());
+            let source = audio.path().map(Path::to_path_buf);
+            // Carried-over session context changes the transcript, so
+            // session requests bypass the cache.
+            let cache_key = match (&backend.cache, &fingerprint, &backend.model_path) {
+                (Some(_), Some(Ok(hash)), Some(model)) if session_id.is_none() => {
+                    Some(cache::key(hash, model, &options))
+                }
+                _ => None,
+            };
+
+            // The cue and overlap passes need the audio as decoded, without
+            // session context.
+            let (audio, analysis_samples) = if options.cues || options.overlap {
+                match audio.into_samples() {
+                    Ok(samples) => (AudioInput::Samples(samples.clone()), Some(samples)),
+                    Err(e) => {
+                        return Response::Error {
+                            message: format!("{:#}", e),
+                        }
+                    }
+                }
+            } else {
+                (audio, None)
+            };
+
+            let (audio, context) = match session_id {
+                Some(id) => {
+                    let samples = match audio.into_samples() {
+                        Ok(samples) => samples,
+                        Err(e) => {
+                            return Response::Error {
+                                message: format!("{:#}", e),
+                            }
+                        }
                     };
+                    let session = backend.sessions.entry(id.clone()).or_default();
+                    let (samples, carry) = session.with_context(&samples);
+                    (AudioInput::Samples(samples), Some((id, carry)))
+                }
+                None => (audio, None),
+            };
 
-                    match serde_json::to_value(output) {
+            let cached = cache_key
+                .as_deref()
+                .and_then(|key| backend.cache.as_ref()?.get(key));
+            let result = match cached {
+                Some(mut output) => {
+                    output.processing_time_ms = start_time.elapsed().as_millis() as u64;
+                    Ok(output)
+                }
+                None => audio
+                    .transcribe_with(engine, backend.retry, &options)
+                    .map(|result| {
+                        let output = to_output(result, start_time.elapsed());
+                        if let (Some(cache), Some(key)) = (&backend.cache, &cache_key) {
+                            if let Err(e) = cache.put(key, &output) {
+                                log::warn!("Failed to cache transcript: {:#}", e);
+                            }
+                        }
+                        output
+                    }),
+            };
+
+            match result {
+                Ok(mut output) => {
+                    if let Some((id, carry)) = context {
+                        if let Some(session) = backend.sessions.get_mut(&id) {
+                            session.finish(&mut output, carry);
+                        }
+                    }
+                    options.apply(&mut output, analysis_samples.as_deref());
+
+                    if let (Some(journal), Some(fingerprint)) = (&backend.journal, fingerprint) {
+                        let appended = fingerprint.map_err(anyhow::Error::from).and_then(|hash| {
+                            journal.append(journal::Entry {
+                                source: source.as_deref(),
+                                source_sha256: &hash,
+                                model: backend.model_path.as_deref(),
+                                options: &options,
+                                result: &output,
+                            })
+                        });
+                        if let Err(e) = appended {
+                            log::warn!("Failed to append to journal: {:#}", e);
+                        }
+                    }
+
+                    let timestamps = options.timestamp_format.unwrap_or_default();
+                    match timestamps.to_value(&output) {
                         Ok(val) => Response::Ok { data: Some(val) },
                         Err(e) => Response::Error { message: e.to_string() }
                     }
@@ -156,43 +1629,414 @@ fn process_command(engine: &mut ParakeetEngine, command: Command) -> Response {
 }
 
 fn run_cli(args: Args) -> Result<()> {
+    let retry = args.retry_policy();
+    let limits = args.input_limits();
+    let chunk_secs = args
+        .file
+        .as_deref()
+        .and_then(|file| args.streamed_chunk_secs(file));
     let file = args.file.context("File path required in CLI mode")?;
     let model = args.model.context("Model path required in CLI mode")?;
-    
-    let start_time = std::time::Instant::now();
-    let mut engine = ParakeetEngine::new();
+    let economize = power::govern(args.power_policy);
+    let model = match args.battery_model {
+        Some(lighter) if economize => lighter,
+        _ => model,
+    };
 
-    engine
-        .load_model(&model)
-        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    limits.check_file(&file)?;
 
-    let result = engine
-        .transcribe_file(&file, None)
-        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-    let duration = start_time.elapsed();
-
-    if args.output == "json" {
-        let segments: Vec<Segment> = result
-            .segments
-            .unwrap_or_default()
-            .into_iter()
-            .map(|s| Segment {
-                start: s.start as f64,
-                end: s.end as f64,
-                text: s.text,
-            })
-            .collect();
-
-        let output = TranscriptionOutput {
-            text: result.text,
-            segments,
-            processing_time_ms: duration.as_millis(),
+    let options = TranscribeOptions {
+        cues: args.cues,
+        locale: args.locale.clone(),
+        chapters: args.chapters,
+        overlap: args.overlap,
+        sanity_check: Some(args.sanity_check),
+        ..Default::default()
+    };
+    options.validate()?;
+    if !(0.0..=1.0).contains(&args.second_pass_threshold) {
+        anyhow::bail!("--second-pass-threshold must be between 0 and 1");
+    }
+
+    let memory_budget = args.max_memory_mb.map(memory::Budget::from_mb);
+
+    model::validate(&model)?;
+
+    let start_time = std::time::Instant::now();
+    let load_engine = || -> Result<ParakeetEngine> {
+        let mut engine = ParakeetEngine::new();
+        retry
+            .run("Model load", || engine.load_model(&model))
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        Ok(engine)
+    };
+
+    if let Some(chunk_secs) = chunk_secs {
+        if args.out_sqlite.is_some() || args.journal.is_some() {
+            anyhow::bail!("--chunk-secs cannot be combined with --out-sqlite or --journal");
+        }
+        let out = open_output(args.out_file.as_deref(), args.compress)?;
+        let job = ChunkedJob {
+            file: &file,
+            chunk_secs,
+            format: &args.output,
+            checkpoint_dir: args.checkpoint_dir.as_deref(),
+            resume: args.resume,
+            retry,
+            timestamps: args.timestamp_format,
+            sanity_check: args.sanity_check,
+            memory_budget,
         };
+        return run_chunked(&mut load_engine()?, job, out, start_time);
+    }
+
+    // Before decoding, so a misspelt format or a broken template fails fast.
+    let formats_dir = args.formats_dir.clone().or_else(formats::default_dir);
+    let formats = formats::Registry::load(formats_dir.as_deref())?;
+    let formatter = formats.get(&args.output)?;
 
-        println!("{}", serde_json::to_string(&output)?);
+    let hash = if args.cache_dir.is_some() || args.journal.is_some() {
+        Some(
+            fingerprint::hash_file(&file)
+                .with_context(|| format!("Failed to hash {}", file.display()))?,
+        )
     } else {
-        println!("{}", result.text);
+        None
+    };
+    let cache = args.cache_dir.clone().map(cache::Cache::new);
+    let cache_key = cache
+        .as_ref()
+        .and(hash.as_deref())
+        .map(|hash| cache::key(hash, &model, &options));
+
+    let samples =
+        if args.cues || args.overlap || args.second_pass.is_some() || args.word_alternatives {
+            Some(wav::read(&file)?)
+        } else {
+            None
+        };
+
+    // A cached transcript needs no model at all, unless a second pass or
+    // word alternatives are asked for: the cache holds Parakeet's own
+    // transcript, and those are applied on top like the other options.
+    let cached = cache_key
+        .as_deref()
+        .and_then(|key| cache.as_ref()?.get(key));
+    let mut engine = None;
+    let mut output = match cached {
+        Some(mut output) => {
+            output.processing_time_ms = start_time.elapsed().as_millis() as u64;
+            output
+        }
+        None => {
+            let engine = engine.insert(load_engine()?);
+            if let Some(budget) = memory_budget {
+                budget.check()?;
+            }
+            let result = AudioInput::File(file.clone())
+                .transcribe(engine, retry)
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            let output = to_output(result, start_time.elapsed());
+            if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+                if let Err(e) = cache.put(key, &output) {
+                    log::warn!("Failed to cache transcript: {:#}", e);
+                }
+            }
+            output
+        }
+    };
+    let mut engine = match engine {
+        None if args.second_pass.is_some() || args.word_alternatives => Some(load_engine()?),
+        engine => engine,
+    };
+    if let (Some(second_model), Some(samples), Some(engine)) =
+        (&args.second_pass, &samples, &mut engine)
+    {
+        let mut second_pass =
+            second_pass::SecondPass::load(second_model, args.second_pass_threshold, retry)?;
+        second_pass.run(engine, samples, &mut output)?;
+        output.processing_time_ms = start_time.elapsed().as_millis() as u64;
+    }
+    if let (true, Some(samples), Some(engine)) = (args.word_alternatives, &samples, &mut engine) {
+        output.alternatives = alternatives::find(engine, samples, &output.segments, retry)?;
+        output.processing_time_ms = start_time.elapsed().as_millis() as u64;
+    }
+
+    options.apply(&mut output, samples.as_deref());
+
+    if let Some(command) = &args.post_exec {
+        output.post_processing_output = Some(post_exec::run(command, &output)?);
+    }
+
+    if let Some(db_path) = &args.out_sqlite {
+        sqlite::append(db_path, &file, &output)?;
+    }
+
+    if let (Some(journal_path), Some(hash)) = (&args.journal, &hash) {
+        Journal::new(journal_path.clone()).append(journal::Entry {
+            source: Some(&file),
+            source_sha256: hash,
+            model: Some(&model),
+            options: &options,
+            result: &output,
+        })?;
+    }
+
+    let mut out = open_output(args.out_file.as_deref(), args.compress)?;
+    let value = args.timestamp_format.to_value(&output)?;
+    formatter.write(&value, &mut out)?;
+
+    out.finish()?;
+    Ok(())
+}
+
+/// Opens the CLI's output: `--out-file` or stdout, compressed if asked.
+fn open_output(
+    path: Option<&Path>,
+    compression: Option<Compression>,
+) -> Result<compress::Output<Box<dyn Write>>> {
+    let sink: Box<dyn Write> = match path {
+        Some(path) => Box::new(io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    Ok(compress::Output::new(sink, compression)?)
+}
+
+/// Settings for a `--chunk-secs` run.
+struct ChunkedJob<'a> {
+    file: &'a Path,
+    chunk_secs: f64,
+    format: &'a str,
+    checkpoint_dir: Option<&'a Path>,
+    resume: bool,
+    retry: RetryPolicy,
+    timestamps: TimestampFormat,
+    sanity_check: sanity::Mode,
+    /// Checked before each chunk; a run over budget stops with its
+    /// checkpoint kept, so `--resume` can finish it later
+    memory_budget: Option<memory::Budget>,
+}
+
+fn run_chunked(
+    engine: &mut ParakeetEngine,
+    job: ChunkedJob<'_>,
+    out: compress::Output<Box<dyn Write>>,
+    start_time: std::time::Instant,
+) -> Result<()> {
+    let ChunkedJob {
+        file,
+        chunk_secs,
+        format,
+        checkpoint_dir,
+        resume,
+        retry,
+        timestamps,
+        sanity_check,
+        memory_budget,
+    } = job;
+    let mut reader = incremental::ChunkReader::open(file, chunk_secs)?;
+    let mut stream = incremental::ResultStream::begin(out, format, timestamps)?;
+
+    let mut checkpoint = match checkpoint_dir {
+        Some(dir) => {
+            let hash = fingerprint::hash_file(file)
+                .with_context(|| format!("Failed to hash {}", file.display()))?;
+
+            // Chunks finished by the earlier run go straight to the output.
+            let resumed = if resume {
+                Checkpoint::resume(dir, &hash, |chunk| {
+                    Ok(stream.push(&chunk.segments, &chunk.text)?)
+                })?
+            } else {
+                None
+            };
+
+            match resumed {
+                Some((checkpoint, position)) => {
+                    log::info!("Resuming {} at sample {}", file.display(), position);
+                    reader.seek(position)?;
+                    Some(checkpoint)
+                }
+                None => Some(Checkpoint::create(dir, file, &hash, reader.chunk_len())?),
+            }
+        }
+        None => None,
+    };
+
+    while let Some(chunk) = reader.next_chunk()? {
+        if let Some(budget) = memory_budget {
+            budget.check()?;
+        }
+        let mut part = transcribe_chunk(engine, chunk, retry)?;
+        sanity::check(&mut part, sanity_check);
+        stream.push(&part.segments, &part.text)?;
+
+        if let Some(checkpoint) = &mut checkpoint {
+            checkpoint.record(&checkpoint::ChunkRecord {
+                end_sample: reader.position(),
+                text: &part.text,
+                segments: &part.segments,
+            })?;
+        }
     }
 
+    let elapsed = start_time.elapsed().as_millis() as u64;
+    stream.finish(elapsed)?.finish()?;
+
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.remove()?;
+    }
     Ok(())
 }
+
+/// Transcribes one chunk with its segment times moved onto the recording's
+/// timeline.
+fn transcribe_chunk(
+    engine: &mut ParakeetEngine,
+    chunk: incremental::Chunk,
+    retry: RetryPolicy,
+) -> Result<TranscriptionOutput> {
+    let result = AudioInput::Samples(chunk.samples)
+        .transcribe(engine, retry)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    Ok(chunk_output(result, chunk.offset_secs))
+}
+
+fn chunk_output(result: TranscriptionResult, offset_secs: f64) -> TranscriptionOutput {
+    let mut part = to_output(result, Duration::ZERO);
+    for segment in &mut part.segments {
+        segment.start += offset_secs;
+        segment.end += offset_secs;
+    }
+    part
+}
+
+fn to_output(result: TranscriptionResult, duration: Duration) -> TranscriptionOutput {
+    let segments: Vec<Segment> = result
+        .segments
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| Segment {
+            start: s.start as f64,
+            end: s.end as f64,
+            text: s.text,
+            overlap: false,
+            suspect: None,
+        })
+        .collect();
+
+    let status = if result.text.trim().is_empty() {
+        TranscriptionStatus::NoSpeech
+    } else {
+        TranscriptionStatus::Ok
+    };
+
+    TranscriptionOutput {
+        schema_version: SCHEMA_VERSION,
+        status,
+        text: result.text.trim().to_string(),
+        segments,
+        processing_time_ms: duration.as_millis() as u64,
+        peak_rss_bytes: memory::peak_rss_bytes(),
+        cues: Vec::new(),
+        chapters: Vec::new(),
+        revisions: Vec::new(),
+        alternatives: Vec::new(),
+        post_processing_output: None,
+        cached: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use transcribe_rs::TranscriptionSegment;
+
+    fn result() -> TranscriptionResult {
+        let segment = |start, end, text: &str| TranscriptionSegment {
+            start,
+            end,
+            text: text.to_string(),
+        };
+        TranscriptionResult {
+            text: " Hello there. How are you?".to_string(),
+            segments: Some(vec![
+                segment(0.32, 0.64, " Hello"),
+                segment(0.64, 0.96, " there."),
+                segment(1.6, 1.76, " How"),
+                segment(1.76, 1.92, " are"),
+                segment(1.92, 2.24, " you?"),
+            ]),
+        }
+    }
+
+    /// What a plain run writes when it decodes the file in one go.
+    fn whole(result: TranscriptionResult, format: &str, timestamps: TimestampFormat) -> Vec<u8> {
+        let mut output = to_output(result, Duration::ZERO);
+        let options = TranscribeOptions {
+            sanity_check: Some(sanity::Mode::Flag),
+            ..Default::default()
+        };
+        options.apply(&mut output, None);
+
+        let mut out = Vec::new();
+        let formats = formats::Registry::load(None).unwrap();
+        let value = timestamps.to_value(&output).unwrap();
+        formats
+            .get(format)
+            .unwrap()
+            .write(&value, &mut out)
+            .unwrap();
+        out
+    }
+
+    /// What a plain run writes when it streams the file as a single chunk.
+    fn streamed(result: TranscriptionResult, format: &str, timestamps: TimestampFormat) -> Vec<u8> {
+        let mut part = chunk_output(result, 0.0);
+        sanity::check(&mut part, sanity::Mode::Flag);
+
+        let mut stream = incremental::ResultStream::begin(Vec::new(), format, timestamps).unwrap();
+        stream.push(&part.segments, &part.text).unwrap();
+        stream.finish(0).unwrap()
+    }
+
+    fn without_run_stats(json: &[u8]) -> serde_json::Value {
+        let mut value: serde_json::Value = serde_json::from_slice(json).unwrap();
+        let fields = value.as_object_mut().unwrap();
+        fields.remove("processing_time_ms").unwrap();
+        fields.remove("peak_rss_bytes").unwrap();
+        value
+    }
+
+    #[test]
+    fn streamed_json_matches_a_single_decode() {
+        for timestamps in [TimestampFormat::Seconds, TimestampFormat::Ms] {
+            assert_eq!(
+                without_run_stats(&streamed(result(), "json", timestamps)),
+                without_run_stats(&whole(result(), "json", timestamps)),
+            );
+        }
+    }
+
+    #[test]
+    fn streamed_text_matches_a_single_decode() {
+        let streamed = streamed(result(), "text", TimestampFormat::Seconds);
+        assert_eq!(streamed, b"Hello there. How are you?\n");
+        assert_eq!(streamed, whole(result(), "text", TimestampFormat::Seconds));
+    }
+
+    #[test]
+    fn streamed_silence_matches_a_single_decode() {
+        let silence = || TranscriptionResult {
+            text: String::new(),
+            segments: Some(Vec::new()),
+        };
+        assert_eq!(
+            without_run_stats(&streamed(silence(), "json", TimestampFormat::Seconds)),
+            without_run_stats(&whole(silence(), "json", TimestampFormat::Seconds)),
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Resident memory measurement and the `--max-memory-mb` budget.
+//!
+//! Inference can't be interrupted, so the budget is checked where work can
+//! stop cleanly: before each transcription the server starts, and between
+//! the chunks of a CLI run. A server over budget first runs transcriptions
+//! one at a time (see `Pool`) and only refuses them if that is not enough.
+
+use std::fmt;
+
+/// Highest resident set size of this process so far, in bytes.
+pub fn peak_rss_bytes() -> u64 {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return 0;
+    }
+
+    let max_rss = usage.ru_maxrss.max(0) as u64;
+    // macOS reports bytes, Linux kilobytes.
+    if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    }
+}
+
+/// Current resident set size of this process, in bytes.
+#[cfg(target_os = "macos")]
+pub fn current_rss_bytes() -> Option<u64> {
+    let mut info: libc::proc_taskinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+    let written = unsafe {
+        libc::proc_pidinfo(
+            libc::getpid(),
+            libc::PROC_PIDTASKINFO,
+            0,
+            (&mut info as *mut libc::proc_taskinfo).cast(),
+            size,
+        )
+    };
+    (written == size).then_some(info.pti_resident_size)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn current_rss_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * page_size.max(0) as u64)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Budget {
+    limit_bytes: u64,
+}
+
+/// Resident memory found over the budget.
+#[derive(Debug)]
+pub struct OverBudget {
+    rss_bytes: u64,
+    limit_bytes: u64,
+}
+
+impl fmt::Display for OverBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Memory use of {} MB exceeds the --max-memory-mb budget of {} MB",
+            self.rss_bytes / (1024 * 1024),
+            self.limit_bytes / (1024 * 1024)
+        )
+    }
+}
+
+impl std::error::Error for OverBudget {}
+
+impl Budget {
+    pub fn from_mb(mb: u64) -> Self {
+        Self {
+            limit_bytes: mb * 1024 * 1024,
+        }
+    }
+
+    pub fn check(&self) -> Result<(), OverBudget> {
+        match current_rss_bytes() {
+            Some(rss_bytes) if rss_bytes > self.limit_bytes => Err(OverBudget {
+                rss_bytes,
+                limit_bytes: self.limit_bytes,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Checks a model directory before handing it to transcribe-rs, whose load
+//! errors don't say which file is at fault.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Files the app downloads into every Parakeet model directory.
+const REQUIRED_FILES: &[(&str, &str)] = &[
+    ("encoder-model.onnx", "encoder"),
+    ("decoder_joint-model.onnx", "decoder/joiner"),
+    ("nemo128.onnx", "mel preprocessor"),
+    ("vocab.txt", "tokenizer vocabulary"),
+];
+
+pub fn validate(dir: &Path) -> Result<()> {
+    let metadata = fs::metadata(dir)
+        .with_context(|| format!("Model path {} does not exist", dir.display()))?;
+    if !metadata.is_dir() {
+        bail!(
+            "Model path {} is a file; pass the directory containing {}",
+            dir.display(),
+            REQUIRED_FILES[0].0
+        );
+    }
+
+    let mut missing = Vec::new();
+    for (name, role) in REQUIRED_FILES {
+        match fs::metadata(dir.join(name)) {
+            Ok(file) if file.len() == 0 => bail!(
+                "{} in {} is empty; the download was probably interrupted, re-download the model",
+                name,
+                dir.display()
+            ),
+            Ok(_) => {}
+            Err(_) => missing.push(format!("{} ({})", name, role)),
+        }
+    }
+
+    match missing.len() {
+        0 => Ok(()),
+        n if n == REQUIRED_FILES.len() => bail!(
+            "{} does not look like a Parakeet model directory (none of {} found)",
+            dir.display(),
+            REQUIRED_FILES
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => bail!(
+            "Model directory {} is missing {}; did the download complete?",
+            dir.display(),
+            missing.join(", ")
+        ),
+    }
+}
@@ -0,0 +1,190 @@
+//! Overlapped speech (`--overlap`): flagging segments where two people
+//! talk at once.
+//!
+//! There is no separation or diarization model in the tree, so this looks
+//! for two pitches in the same frame. Each voiced frame's strongest period
+//! is found by autocorrelation and cancelled with a comb filter, which
+//! removes a single voice almost entirely. When a substantial, still clearly
+//! periodic residual is left at an unrelated period, a second voice was
+//! speaking. Stretches of `WINDOW_FRAMES` where most voiced frames carry a
+//! second pitch are overlap, and every segment they touch is flagged.
+//!
+//! Two voices at nearly the same pitch, or one of them whispering, go
+//! unnoticed; music and tonal background noise under speech can be flagged.
+
+use crate::incremental::SAMPLE_RATE;
+use crate::Segment;
+
+/// The analysis runs on 2:1 decimated audio: speech pitch sits far below
+/// 4 kHz and this halves the cost.
+const RATE: f32 = SAMPLE_RATE as f32 / 2.0;
+/// 40 ms frames every 20 ms
+const FRAME: usize = 320;
+const HOP: usize = 160;
+const MIN_F0: f32 = 70.0;
+const MAX_F0: f32 = 400.0;
+/// Frames quieter than this (sum of squares) are skipped as silence
+const MIN_ENERGY: f32 = 1e-4;
+/// Normalized correlation at the first period for a frame to count as voiced
+const VOICING: f32 = 0.4;
+/// Share of the frame's energy the first voice must leave unexplained
+const RESIDUAL_SHARE: f32 = 0.2;
+/// Normalized correlation of the residual for it to count as a second voice
+const SECOND_VOICING: f32 = 0.6;
+/// How close two periods may come to an integer ratio and still be one voice
+const HARMONIC_TOLERANCE: f32 = 0.06;
+/// 0.3 s of frames, of which `WINDOW_SHARE` of the voiced ones must carry a
+/// second pitch to be overlap
+const WINDOW_FRAMES: usize = 15;
+const WINDOW_SHARE: f32 = 0.6;
+const MIN_VOICED_IN_WINDOW: usize = 6;
+/// How much of a segment must be overlap for it to be flagged, so that
+/// loose segment boundaries don't pull in their neighbours
+const MIN_SEGMENT_OVERLAP_SECS: f64 = 0.15;
+
+/// Sets `overlap` on the segments of 16 kHz mono `samples` during which
+/// more than one voice is heard.
+pub fn flag(samples: &[f32], segments: &mut [Segment]) {
+    let regions = regions(samples);
+    for segment in segments {
+        let overlapped: f64 = regions
+            .iter()
+            .map(|&(start, end)| (end.min(segment.end) - start.max(segment.start)).max(0.0))
+            .sum();
+        segment.overlap = overlapped >= MIN_SEGMENT_OVERLAP_SECS;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    Unvoiced,
+    OneVoice,
+    TwoVoices,
+}
+
+/// Stretches of overlapped speech, in seconds.
+fn regions(samples: &[f32]) -> Vec<(f64, f64)> {
+    let audio: Vec<f32> = samples
+        .chunks_exact(2)
+        .map(|p| (p[0] + p[1]) * 0.5)
+        .collect();
+    let frames = classify(&audio);
+    let first_frame = 2 * (RATE / MIN_F0) as usize;
+    let frame_secs = |i: usize| (first_frame + i * HOP) as f64 / RATE as f64;
+
+    let mut regions: Vec<(f64, f64)> = Vec::new();
+    for (i, window) in frames.windows(WINDOW_FRAMES).enumerate() {
+        let voiced = window.iter().filter(|&&f| f != Frame::Unvoiced).count();
+        let two = window.iter().filter(|&&f| f == Frame::TwoVoices).count();
+        if voiced < MIN_VOICED_IN_WINDOW || (two as f32) < WINDOW_SHARE * voiced as f32 {
+            continue;
+        }
+
+        // The region runs over the two-voice frames, not the whole window.
+        let first = window.iter().position(|&f| f == Frame::TwoVoices);
+        let last = window.iter().rposition(|&f| f == Frame::TwoVoices);
+        let (Some(first), Some(last)) = (first, last) else {
+            continue;
+        };
+        let start = frame_secs(i + first);
+        let end = frame_secs(i + last) + FRAME as f64 / RATE as f64;
+        match regions.last_mut() {
+            Some(region) if start <= region.1 => region.1 = region.1.max(end),
+            _ => regions.push((start, end)),
+        }
+    }
+    regions
+}
+
+fn classify(audio: &[f32]) -> Vec<Frame> {
+    let min_lag = (RATE / MAX_F0) as usize;
+    let max_lag = (RATE / MIN_F0) as usize;
+    let mut frames = Vec::new();
+
+    // A frame's residual reaches back a period and is searched over a
+    // period's worth of lags on either side; see `first_frame` in `regions`.
+    let mut start = 2 * max_lag;
+    while start + FRAME + 2 * max_lag <= audio.len() {
+        frames.push(classify_frame(audio, start, min_lag, max_lag));
+        start += HOP;
+    }
+    frames
+}
+
+fn classify_frame(audio: &[f32], start: usize, min_lag: usize, max_lag: usize) -> Frame {
+    let frame = &audio[start..start + FRAME];
+    if energy(frame) < MIN_ENERGY {
+        return Frame::Unvoiced;
+    }
+
+    let Some((period, correlation)) = best_period(audio, start, min_lag, max_lag) else {
+        return Frame::Unvoiced;
+    };
+    if correlation < VOICING {
+        return Frame::Unvoiced;
+    }
+
+    // Cancel the first voice: x[n] - g·x[n - period], with the gain that
+    // leaves the least behind.
+    let past = &audio[start - period..start - period + FRAME];
+    let gain = dot(frame, past) / energy(past).max(f32::EPSILON);
+    let residual: Vec<f32> = (start - max_lag..start + FRAME + max_lag)
+        .map(|n| audio[n] - gain * audio[n - period])
+        .collect();
+    let left = energy(&residual[max_lag..max_lag + FRAME]);
+    if left < RESIDUAL_SHARE * energy(frame) {
+        return Frame::OneVoice;
+    }
+
+    match best_period(&residual, max_lag, min_lag, max_lag) {
+        Some((second, correlation))
+            if correlation >= SECOND_VOICING && !harmonically_related(period, second) =>
+        {
+            Frame::TwoVoices
+        }
+        _ => Frame::OneVoice,
+    }
+}
+
+/// The lag in `min_lag..=max_lag` at which the frame at `start` best
+/// matches the audio after it, with its normalized correlation.
+fn best_period(
+    audio: &[f32],
+    start: usize,
+    min_lag: usize,
+    max_lag: usize,
+) -> Option<(usize, f32)> {
+    let frame = &audio[start..start + FRAME];
+    let frame_energy = energy(frame);
+
+    let correlations: Vec<f32> = (min_lag..=max_lag)
+        .map(|lag| {
+            let shifted = &audio[start + lag..start + lag + FRAME];
+            dot(frame, shifted) / (frame_energy * energy(shifted)).sqrt().max(f32::EPSILON)
+        })
+        .collect();
+    let best = correlations.iter().copied().fold(f32::MIN, f32::max);
+    // Multiples of the period correlate almost as well; take the peak at
+    // the shortest lag that comes close.
+    let mut lag = correlations.iter().position(|&c| c >= 0.9 * best)?;
+    while lag + 1 < correlations.len() && correlations[lag + 1] > correlations[lag] {
+        lag += 1;
+    }
+    Some((min_lag + lag, correlations[lag]))
+}
+
+/// Whether one period is close to a whole multiple of the other, as the
+/// octaves and harmonics of a single voice are.
+fn harmonically_related(a: usize, b: usize) -> bool {
+    let (short, long) = if a <= b { (a, b) } else { (b, a) };
+    let ratio = long as f32 / short as f32;
+    (ratio - ratio.round()).abs() <= HARMONIC_TOLERANCE * ratio.round()
+}
+
+fn energy(samples: &[f32]) -> f32 {
+    samples.iter().map(|s| s * s).sum()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
@@ -0,0 +1,255 @@
+//! `--dry-run`: everything a run would do, short of running the model.
+//!
+//! The model directory is validated and every input's header is parsed and
+//! checked against the limits and format requirements a real run applies,
+//! then the resolved job is printed as one JSON object. Nothing is loaded
+//! into the engine, so this takes milliseconds even for a batch of long
+//! recordings. When any input would be refused the plan is still printed,
+//! with the reason on that input, and the run exits with an error.
+
+use crate::incremental::SAMPLE_RATE;
+use crate::{batch, formats, locale, model, wav, Args, SCHEMA_VERSION};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct Plan<'a, J> {
+    schema_version: u32,
+    dry_run: bool,
+    mode: &'static str,
+    model: Option<&'a Path>,
+    /// Why the model directory would be refused
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_error: Option<String>,
+    inputs: Vec<Input<'a>>,
+    job: J,
+    limits: Limits,
+    retries: u32,
+    retry_backoff_ms: u64,
+    journal: Option<&'a Path>,
+    cache_dir: Option<&'a Path>,
+    timestamp_format: String,
+    sanity_check: String,
+    power_policy: String,
+    battery_model: Option<&'a Path>,
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    path: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    format: Option<Format>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Format {
+    sample_rate: u32,
+    channels: u16,
+    sample_format: &'static str,
+    duration_secs: f64,
+    #[serde(skip)]
+    frames: usize,
+    /// Whether the audio is already the engine's 16 kHz mono
+    engine_format: bool,
+}
+
+#[derive(Serialize)]
+struct Limits {
+    max_memory_mb: Option<u64>,
+    max_input_duration_secs: Option<f64>,
+    max_input_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct CliJob<'a> {
+    output: &'a str,
+    out_file: Option<&'a Path>,
+    compress: Option<String>,
+    out_sqlite: Option<&'a Path>,
+    chunk_secs: Option<f64>,
+    checkpoint_dir: Option<&'a Path>,
+    resume: bool,
+    cues: bool,
+    locale: Option<&'a str>,
+    chapters: bool,
+    overlap: bool,
+    second_pass: Option<&'a Path>,
+    second_pass_threshold: Option<f32>,
+    word_alternatives: bool,
+    post_exec: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct BatchJob {
+    pack_secs: f64,
+    gap_secs: f64,
+    concat: bool,
+    /// Engine calls the inputs would be packed into, as indices into `inputs`
+    packs: Vec<Vec<usize>>,
+}
+
+/// Plans a CLI run of `--file`.
+pub fn cli(args: &Args) -> Result<()> {
+    let Some(file) = &args.file else {
+        bail!("File path required in CLI mode");
+    };
+    if let Some(tag) = &args.locale {
+        locale::Locale::parse(tag)?;
+    }
+    if args.chunk_secs.is_none() {
+        let formats_dir = args.formats_dir.clone().or_else(formats::default_dir);
+        formats::Registry::load(formats_dir.as_deref())?.get(&args.output)?;
+    }
+    // Chunked runs, the cue, overlap and second passes and word
+    // alternatives read the samples themselves and only take 16 kHz mono.
+    let strict = args.chunk_secs.is_some()
+        || args.cues
+        || args.overlap
+        || args.second_pass.is_some()
+        || args.word_alternatives;
+    let inputs = vec![probe(args, file, strict)];
+
+    let job = CliJob {
+        output: &args.output,
+        out_file: args.out_file.as_deref(),
+        compress: args.compress.map(|c| value_name(&c)),
+        out_sqlite: args.out_sqlite.as_deref(),
+        chunk_secs: args.streamed_chunk_secs(file),
+        checkpoint_dir: args.checkpoint_dir.as_deref(),
+        resume: args.resume,
+        cues: args.cues,
+        locale: args.locale.as_deref(),
+        chapters: args.chapters,
+        overlap: args.overlap,
+        second_pass: args.second_pass.as_deref(),
+        second_pass_threshold: args
+            .second_pass
+            .as_ref()
+            .map(|_| args.second_pass_threshold),
+        word_alternatives: args.word_alternatives,
+        post_exec: args.post_exec.as_deref(),
+    };
+    print(plan(args, "cli", inputs, job))
+}
+
+/// Plans a `batch` run.
+pub fn batch(args: &Args, files: &[PathBuf], options: &batch::Options) -> Result<()> {
+    let inputs: Vec<Input> = files.iter().map(|f| probe(args, f, true)).collect();
+    let lens: Vec<Option<usize>> = inputs
+        .iter()
+        .map(|input| match (&input.format, &input.error) {
+            (Some(format), None) => Some(format.frames),
+            _ => None,
+        })
+        .collect();
+
+    let job = BatchJob {
+        pack_secs: options.pack_secs,
+        gap_secs: options.gap_secs,
+        concat: options.concat,
+        packs: batch::plan_packs(&lens, options),
+    };
+    print(plan(args, "batch", inputs, job))
+}
+
+fn plan<'a, J>(args: &'a Args, mode: &'static str, inputs: Vec<Input<'a>>, job: J) -> Plan<'a, J> {
+    let model_error = match &args.model {
+        Some(model) => model::validate(model).err().map(|e| format!("{:#}", e)),
+        None => Some("Model path required".to_string()),
+    };
+
+    Plan {
+        schema_version: SCHEMA_VERSION,
+        dry_run: true,
+        mode,
+        model: args.model.as_deref(),
+        model_error,
+        inputs,
+        job,
+        limits: Limits {
+            max_memory_mb: args.max_memory_mb,
+            max_input_duration_secs: args.max_input_duration,
+            max_input_bytes: args.max_input_bytes,
+        },
+        retries: args.retries,
+        retry_backoff_ms: args.retry_backoff_ms,
+        journal: args.journal.as_deref(),
+        cache_dir: args.cache_dir.as_deref(),
+        timestamp_format: value_name(&args.timestamp_format),
+        sanity_check: value_name(&args.sanity_check),
+        power_policy: value_name(&args.power_policy),
+        battery_model: args.battery_model.as_deref(),
+    }
+}
+
+/// Reads what a run would learn about `path` before decoding it. With
+/// `strict`, audio other than 16 kHz mono is refused.
+fn probe<'a>(args: &Args, path: &'a Path, strict: bool) -> Input<'a> {
+    let mut input = Input {
+        path,
+        bytes: std::fs::metadata(path).ok().map(|m| m.len()),
+        format: None,
+        error: None,
+    };
+
+    let wav = match wav::Wav::open(path) {
+        Ok(wav) => wav,
+        Err(e) => {
+            input.error = Some(format!("{:#}", e));
+            return input;
+        }
+    };
+    let engine_format = wav.is_engine_format();
+    input.format = Some(Format {
+        sample_rate: wav.sample_rate(),
+        channels: wav.channels(),
+        sample_format: wav.sample_format(),
+        duration_secs: wav.frames() as f64 / wav.sample_rate() as f64,
+        frames: wav.frames(),
+        engine_format,
+    });
+
+    input.error = if let Err(e) = args.input_limits().check_file(path) {
+        Some(format!("{:#}", e))
+    } else if strict && !engine_format {
+        Some(format!(
+            "{} Hz with {} channel(s); expected {} Hz mono",
+            wav.sample_rate(),
+            wav.channels(),
+            SAMPLE_RATE
+        ))
+    } else {
+        None
+    };
+    input
+}
+
+fn print<J: Serialize>(plan: Plan<'_, J>) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&plan)?);
+
+    if let Some(e) = &plan.model_error {
+        bail!("Dry run failed: {}", e);
+    }
+    let refused = plan.inputs.iter().filter(|i| i.error.is_some()).count();
+    if refused > 0 {
+        bail!(
+            "Dry run failed: {} of {} input(s) would be refused",
+            refused,
+            plan.inputs.len()
+        );
+    }
+    Ok(())
+}
+
+/// The name clap accepts for `value` on the command line.
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map_or_else(String::new, |v| v.get_name().to_string())
+}
@@ -0,0 +1,192 @@
+//! `serve --workers N`: several engines behind one server.
+//!
+//! Each worker is a full `Backend` with its own engine, so a long file job
+//! and an interactive dictation request run side by side instead of queueing
+//! behind one engine. `load_model` warms every worker at once. Requests that
+//! carry a `session_id` always go to the same worker, which is the one
+//! holding that session's context; everything else takes whichever worker is
+//! free. The worker lock alone does not order a session's requests, so the
+//! server hands them to the pool one at a time, in the order they arrived.
+//!
+//! Over `--max-memory-mb`, the pool sheds its parallelism before refusing
+//! anything: a transcription that finds memory over budget waits until the
+//! ones already running are done and then runs alone, and only if memory is
+//! still over budget then does `process_command` turn it away.
+
+use crate::memory;
+use crate::{handshake, process_command, Backend, Command, Response};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, TryLockError};
+use std::thread;
+
+pub struct Pool {
+    workers: Vec<Mutex<Backend>>,
+    next: AtomicUsize,
+    /// The model every worker has loaded, kept here so `hello` can answer
+    /// without waiting for a worker
+    model_path: Mutex<Option<PathBuf>>,
+    memory_budget: Option<memory::Budget>,
+    /// Held shared by every running transcription, or exclusively by one
+    /// that runs alone because memory is over budget
+    transcriptions: RwLock<()>,
+}
+
+impl Pool {
+    pub fn new(workers: impl IntoIterator<Item = Backend>) -> Self {
+        let workers: Vec<Backend> = workers.into_iter().collect();
+        // Workers are all built from the same arguments.
+        let memory_budget = workers.first().and_then(|worker| worker.memory_budget);
+        let workers: Vec<_> = workers.into_iter().map(Mutex::new).collect();
+        assert!(
+            !workers.is_empty(),
+            "a worker pool needs at least one worker"
+        );
+        Self {
+            workers,
+            next: AtomicUsize::new(0),
+            model_path: Mutex::new(None),
+            memory_budget,
+            transcriptions: RwLock::new(()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn process(&self, request_id: &str, command: Command) -> Response {
+        match command {
+            Command::LoadModel { path } => self.load_model(request_id, path),
+            Command::Transcribe {
+                session_id: Some(ref id),
+                ..
+            } => {
+                let worker = self.session_worker(id);
+                self.admit(|| {
+                    let mut backend = worker.lock().unwrap_or_else(PoisonError::into_inner);
+                    process_command(&mut backend, request_id, command)
+                })
+            }
+            Command::Transcribe { .. } => {
+                self.admit(|| process_command(&mut self.free_worker(), request_id, command))
+            }
+            Command::EndSession { ref session_id }
+            | Command::Pause { ref session_id }
+            | Command::Resume { ref session_id } => {
+                let mut backend = self
+                    .session_worker(session_id)
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                process_command(&mut backend, request_id, command)
+            }
+            // Answered here so a health check or handshake never waits on
+            // a busy engine.
+            Command::Ping => Response::Ok { data: None },
+            Command::Hello { protocol_version } => {
+                let model = self
+                    .model_path
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone();
+                handshake(protocol_version, model, self.len())
+            }
+            command => process_command(&mut self.free_worker(), request_id, command),
+        }
+    }
+
+    /// Runs a transcription alongside the others, or on its own once memory
+    /// is over budget.
+    fn admit<T>(&self, transcribe: impl FnOnce() -> T) -> T {
+        if self
+            .memory_budget
+            .is_some_and(|budget| budget.check().is_err())
+        {
+            log::warn!("Over the memory budget; running transcriptions one at a time");
+            let _alone = self
+                .transcriptions
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            transcribe()
+        } else {
+            let _shared = self
+                .transcriptions
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            transcribe()
+        }
+    }
+
+    /// Loads the model into every worker in parallel, failing if any of
+    /// them could not load it.
+    fn load_model(&self, request_id: &str, path: String) -> Response {
+        let responses: Vec<Response> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .workers
+                .iter()
+                .map(|worker| {
+                    let path = path.clone();
+                    scope.spawn(move || {
+                        let mut backend = worker.lock().unwrap_or_else(PoisonError::into_inner);
+                        process_command(&mut backend, request_id, Command::LoadModel { path })
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle.join().unwrap_or_else(|_| Response::Error {
+                        message: "Model load panicked".to_string(),
+                    })
+                })
+                .collect()
+        });
+
+        let failed = responses
+            .into_iter()
+            .find(|response| matches!(response, Response::Error { .. }));
+        let mut model_path = self
+            .model_path
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match failed {
+            Some(response) => {
+                // Some workers may have loaded it and others not.
+                *model_path = None;
+                response
+            }
+            None => {
+                *model_path = Some(PathBuf::from(path));
+                Response::Ok { data: None }
+            }
+        }
+    }
+
+    fn session_worker(&self, session_id: &str) -> &Mutex<Backend> {
+        let mut hasher = DefaultHasher::new();
+        session_id.hash(&mut hasher);
+        &self.workers[hasher.finish() as usize % self.workers.len()]
+    }
+
+    /// Takes the first idle worker, or waits on the next one in turn when
+    /// all of them are busy.
+    fn free_worker(&self) -> MutexGuard<'_, Backend> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.workers.len();
+
+        for i in 0..count {
+            match self.workers[(start + i) % count].try_lock() {
+                Ok(backend) => return backend,
+                Err(TryLockError::Poisoned(e)) => return e.into_inner(),
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+
+        self.workers[start % count]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,61 @@
+//! `--post-exec`: handing the finished transcript to another program.
+//!
+//! The command runs through `sh -c`, so it can be a pipeline, with the
+//! transcript's JSON on its stdin. Whatever it prints becomes the result's
+//! `post_processing_output`, which lets a local LLM summarise or tag a
+//! recording in the same run that transcribed it. A command that exits
+//! unsuccessfully fails the run with its stderr, rather than attaching a
+//! half-written answer.
+
+use crate::TranscriptionOutput;
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `command` with `output` as JSON on its stdin and returns its
+/// stdout, without the trailing newline.
+pub fn run(command: &str, output: &TranscriptionOutput) -> Result<String> {
+    let input = serde_json::to_vec(output)?;
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run post-exec command '{}'", command))?;
+
+    // Feed stdin from another thread: a command that writes before it has
+    // read everything would otherwise fill its stdout pipe and deadlock.
+    let mut stdin = child
+        .stdin
+        .take()
+        .context("post-exec stdin was not piped")?;
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let result = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run post-exec command '{}'", command))?;
+    // A command that doesn't read its stdin closes the pipe early; that is
+    // its business, so the broken pipe is not an error here.
+    if let Ok(Err(e)) = writer.join() {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            return Err(e).context("Failed to write the transcript to post-exec");
+        }
+    }
+
+    if !result.status.success() {
+        bail!(
+            "Post-exec command '{}' failed ({}): {}",
+            command,
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+
+    let mut stdout = String::from_utf8(result.stdout)
+        .context("Post-exec command printed something that is not UTF-8")?;
+    stdout.truncate(stdout.trim_end_matches(['\n', '\r']).len());
+    Ok(stdout)
+}
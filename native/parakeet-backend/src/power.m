@@ -0,0 +1,26 @@
+// Power source and thermal probes for `--power-policy`.
+
+#import <Foundation/Foundation.h>
+#import <IOKit/ps/IOPSKeys.h>
+#import <IOKit/ps/IOPowerSources.h>
+
+// 1 when running on battery, 0 on AC power (always, on a desktop Mac), -1
+// when the power source can't be read.
+int pk_on_battery(void) {
+    CFTypeRef info = IOPSCopyPowerSourcesInfo();
+    if (info == NULL) {
+        return -1;
+    }
+    CFStringRef type = IOPSGetProvidingPowerSourceType(info);
+    int on_battery = -1;
+    if (type != NULL) {
+        on_battery = CFEqual(type, CFSTR(kIOPSBatteryPowerValue)) ? 1 : 0;
+    }
+    CFRelease(info);
+    return on_battery;
+}
+
+// NSProcessInfoThermalState: 0 nominal, 1 fair, 2 serious, 3 critical.
+int pk_thermal_state(void) {
+    return (int)[NSProcessInfo processInfo].thermalState;
+}
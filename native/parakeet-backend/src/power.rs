@@ -0,0 +1,135 @@
+//! `--power-policy`: easing off on battery and when the Mac runs hot (CLI
+//! and batch modes).
+//!
+//! transcribe-rs leaves ONNX Runtime's thread pool to itself, so economizing
+//! works on the process instead: it is moved into Darwin's background band,
+//! which keeps its threads on the efficiency cores and throttles its disk
+//! I/O, and `--battery-model` is loaded in place of `--model` when given.
+//! The model is picked once at the start; with `auto` the power source and
+//! thermal state are re-read every `POLL_INTERVAL`, and the priority follows
+//! them while the run goes on. Elsewhere than macOS the state can't be
+//! read, so `auto` never economizes and `saver` only switches the model.
+
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Policy {
+    /// Economize on battery or when the Mac is running hot
+    #[default]
+    Auto,
+    /// Always run at full speed
+    Performance,
+    /// Always economize
+    Saver,
+}
+
+/// `NSProcessInfoThermalState`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+enum Thermal {
+    Nominal,
+    Fair,
+    Serious,
+    Critical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct State {
+    on_battery: Option<bool>,
+    thermal: Option<Thermal>,
+}
+
+impl Policy {
+    fn economize(self, state: State) -> bool {
+        match self {
+            Policy::Performance => false,
+            Policy::Saver => true,
+            Policy::Auto => {
+                state.on_battery == Some(true) || state.thermal >= Some(Thermal::Serious)
+            }
+        }
+    }
+}
+
+/// Applies `policy` now and, for `auto`, from then on. Returns whether the
+/// run starts out economizing, which is when the lighter model is used.
+pub fn govern(policy: Policy) -> bool {
+    let state = state();
+    let economize = policy.economize(state);
+    if economize {
+        log::info!("Economizing on power ({:?} policy, {:?})", policy, state);
+        set_background(true);
+    }
+
+    if policy == Policy::Auto && cfg!(target_os = "macos") {
+        thread::spawn(move || {
+            let mut economizing = economize;
+            loop {
+                thread::sleep(POLL_INTERVAL);
+                let state = state();
+                let economize = policy.economize(state);
+                if economize != economizing {
+                    log::info!(
+                        "{} economizing on power ({:?})",
+                        if economize { "Started" } else { "Stopped" },
+                        state
+                    );
+                    set_background(economize);
+                    economizing = economize;
+                }
+            }
+        });
+    }
+    economize
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn pk_on_battery() -> std::ffi::c_int;
+    fn pk_thermal_state() -> std::ffi::c_int;
+}
+
+#[cfg(target_os = "macos")]
+fn state() -> State {
+    let on_battery = match unsafe { pk_on_battery() } {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
+    };
+    let thermal = match unsafe { pk_thermal_state() } {
+        0 => Some(Thermal::Nominal),
+        1 => Some(Thermal::Fair),
+        2 => Some(Thermal::Serious),
+        3 => Some(Thermal::Critical),
+        _ => None,
+    };
+    State {
+        on_battery,
+        thermal,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn state() -> State {
+    State {
+        on_battery: None,
+        thermal: None,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn set_background(background: bool) {
+    let priority = if background { libc::PRIO_DARWIN_BG } else { 0 };
+    if unsafe { libc::setpriority(libc::PRIO_DARWIN_PROCESS, 0, priority) } != 0 {
+        log::warn!(
+            "Failed to change process priority: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn set_background(_background: bool) {}
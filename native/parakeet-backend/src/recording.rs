@@ -0,0 +1,115 @@
+//! Recording what the recognizer heard (`capture --save-audio`).
+//!
+//! Each source's audio is written exactly as it is handed to the utterance
+//! segmenter: already downmixed and resampled to 16 kHz mono, before the
+//! VAD drops anything. A source that started late is padded with silence
+//! at the front, so positions in the file are times on the capture's
+//! timeline and line up with the events. WAV files are 32-bit float, FLAC
+//! files 16-bit (see `flac`). Both are flushed about once a second, so a
+//! killed capture leaves at most a second unwritten.
+
+use crate::flac;
+use crate::incremental::SAMPLE_RATE;
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+const FLUSH_SAMPLES: usize = SAMPLE_RATE as usize;
+
+enum Writer {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(flac::Encoder<BufWriter<File>>),
+}
+
+pub struct Recorder {
+    writer: Writer,
+    path: PathBuf,
+    unflushed: usize,
+}
+
+impl Recorder {
+    /// Creates a `.wav` or `.flac` file at `path`.
+    pub fn create(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let create = || {
+            File::create(path)
+                .map(BufWriter::new)
+                .with_context(|| format!("Failed to create {}", path.display()))
+        };
+        let writer = match extension.as_deref() {
+            Some("wav") => {
+                let spec = hound::WavSpec {
+                    channels: 1,
+                    sample_rate: SAMPLE_RATE,
+                    bits_per_sample: 32,
+                    sample_format: hound::SampleFormat::Float,
+                };
+                Writer::Wav(hound::WavWriter::new(create()?, spec)?)
+            }
+            Some("flac") => Writer::Flac(flac::Encoder::new(create()?, SAMPLE_RATE)?),
+            _ => bail!(
+                "Can't tell the audio format of {}: use a .wav or .flac file",
+                path.display()
+            ),
+        };
+        Ok(Self {
+            writer,
+            path: path.to_path_buf(),
+            unflushed: 0,
+        })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<()> {
+        match &mut self.writer {
+            Writer::Wav(writer) => {
+                for &sample in samples {
+                    writer.write_sample(sample)?;
+                }
+            }
+            Writer::Flac(encoder) => encoder.write(samples)?,
+        }
+
+        self.unflushed += samples.len();
+        if self.unflushed >= FLUSH_SAMPLES {
+            self.unflushed = 0;
+            match &mut self.writer {
+                // Also brings the header's length up to date.
+                Writer::Wav(writer) => writer.flush()?,
+                Writer::Flac(encoder) => encoder.flush()?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Pads with `secs` of silence.
+    pub fn write_silence(&mut self, secs: f64) -> Result<()> {
+        let len = (secs.max(0.0) * SAMPLE_RATE as f64).round() as usize;
+        self.write(&vec![0.0; len])
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.writer {
+            Writer::Wav(writer) => writer.finalize()?,
+            Writer::Flac(encoder) => encoder.finish()?,
+        }
+        log::info!("Saved audio to {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Where one source's recording goes: `path` itself, or with the source's
+/// name before the extension when several sources are recorded.
+pub fn source_path(path: &Path, source: &str, several: bool) -> PathBuf {
+    if !several {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, source, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, source),
+    };
+    path.with_file_name(name)
+}
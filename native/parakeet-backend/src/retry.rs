@@ -0,0 +1,62 @@
+//! Retry with exponential backoff for engine calls (`--retries`).
+//!
+//! ONNX Runtime occasionally fails where an immediate second attempt
+//! succeeds (execution provider initialization racing another process,
+//! allocation failures under memory pressure), so these are retried a
+//! configurable number of times before the job is failed. transcribe-rs
+//! chooses the execution provider itself, so there is no provider fallback
+//! to offer here.
+
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+pub type EngineResult<T> = Result<T, Box<dyn Error>>;
+
+/// Longest wait between attempts, however many retries are allowed
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub retries: u32,
+    pub initial_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub const NONE: RetryPolicy = RetryPolicy {
+        retries: 0,
+        initial_backoff: Duration::ZERO,
+    };
+
+    pub fn is_disabled(&self) -> bool {
+        self.retries == 0
+    }
+
+    /// Calls `f` until it succeeds or the retries are used up, doubling the
+    /// wait between attempts up to `MAX_BACKOFF`. Returns the last error.
+    pub fn run<T>(&self, what: &str, mut f: impl FnMut() -> EngineResult<T>) -> EngineResult<T> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+
+        loop {
+            match f() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.retries => {
+                    attempt += 1;
+                    log::warn!(
+                        "{} failed ({}), retrying in {:?} ({}/{})",
+                        what,
+                        e,
+                        backoff,
+                        attempt,
+                        self.retries
+                    );
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Catching decoder artifacts before they reach a document
+//! (`--sanity-check`).
+//!
+//! Two failures get through decoding looking like ordinary text: a loop,
+//! where the same word or phrase comes out over and over, and a segment
+//! holding more text than its audio could possibly carry. A loop is
+//! `MIN_WORD_REPEATS` copies of one word or `MIN_PHRASE_REPEATS` of a phrase
+//! in a row, compared without case or punctuation and across segment
+//! boundaries, so word-level segments are covered too. A segment is too
+//! dense when it has more than `MAX_CHARS_PER_SEC` letters per second of
+//! its span, plus `CHAR_SLACK` for a long word on a short word segment.
+//!
+//! `flag` marks such segments `suspect` and leaves the text alone.
+//! `suppress` also keeps only the first copy of a loop, dropping segments
+//! left with no words, and drops too-dense segments entirely; the text is
+//! then rebuilt from what is left.
+
+use crate::{Segment, TranscriptionOutput, TranscriptionStatus};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Longest phrase, in words, looked for as a loop
+const MAX_PHRASE_WORDS: usize = 8;
+/// Copies of one word that make a loop; "no, no, no" is still speech
+const MIN_WORD_REPEATS: usize = 4;
+/// Copies of a phrase of two or more words that make a loop
+const MIN_PHRASE_REPEATS: usize = 3;
+/// Faster than anyone talks, in letters and digits per second
+const MAX_CHARS_PER_SEC: f64 = 25.0;
+const CHAR_SLACK: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// No checks
+    Off,
+    /// Mark suspect segments, keeping their text
+    #[default]
+    Flag,
+    /// Remove repeated copies and too-dense segments
+    Suppress,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Suspect {
+    /// Part of the segment repeats the words before it
+    Repetition,
+    /// More text than the segment's audio could hold
+    ImplausibleLength,
+}
+
+/// One word of the transcript, by position in its segment.
+struct Word {
+    segment: usize,
+    index: usize,
+    key: String,
+}
+
+pub fn check(output: &mut TranscriptionOutput, mode: Mode) {
+    if mode == Mode::Off || output.segments.is_empty() {
+        return;
+    }
+
+    let words: Vec<Word> = output
+        .segments
+        .iter()
+        .enumerate()
+        .flat_map(|(segment, s)| {
+            s.text
+                .split_whitespace()
+                .enumerate()
+                .map(move |(index, word)| Word {
+                    segment,
+                    index,
+                    key: normalize(word),
+                })
+        })
+        .filter(|w| !w.key.is_empty())
+        .collect();
+    let repeated = repeated_words(&words);
+
+    let mut repeated_by_segment = vec![Vec::new(); output.segments.len()];
+    for (word, _) in words.iter().zip(&repeated).filter(|(_, r)| **r) {
+        repeated_by_segment[word.segment].push(word.index);
+    }
+    for (segment, repeated) in output.segments.iter_mut().zip(&repeated_by_segment) {
+        segment.suspect = if too_dense(segment) {
+            Some(Suspect::ImplausibleLength)
+        } else if !repeated.is_empty() {
+            Some(Suspect::Repetition)
+        } else {
+            None
+        };
+    }
+
+    let suspects = output
+        .segments
+        .iter()
+        .filter(|s| s.suspect.is_some())
+        .count();
+    if suspects == 0 {
+        return;
+    }
+    if mode == Mode::Flag {
+        log::warn!("{} segments look like decoding artifacts", suspects);
+        return;
+    }
+
+    log::warn!("Suppressing decoding artifacts in {} segments", suspects);
+    let segments = std::mem::take(&mut output.segments);
+    for (mut segment, repeated) in segments.into_iter().zip(repeated_by_segment) {
+        match segment.suspect {
+            Some(Suspect::ImplausibleLength) => continue,
+            Some(Suspect::Repetition) => {
+                let lead = if segment.text.starts_with(char::is_whitespace) {
+                    " "
+                } else {
+                    ""
+                };
+                let kept: Vec<&str> = segment
+                    .text
+                    .split_whitespace()
+                    .enumerate()
+                    .filter(|(i, _)| !repeated.contains(i))
+                    .map(|(_, word)| word)
+                    .collect();
+                if kept.iter().all(|word| normalize(word).is_empty()) {
+                    continue;
+                }
+                segment.text = format!("{}{}", lead, kept.join(" "));
+            }
+            None => {}
+        }
+        output.segments.push(segment);
+    }
+
+    output.text = output
+        .segments
+        .iter()
+        .map(|s| s.text.as_str())
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if output.text.is_empty() {
+        output.status = TranscriptionStatus::NoSpeech;
+    }
+}
+
+/// Marks every copy of a loop after the first.
+fn repeated_words(words: &[Word]) -> Vec<bool> {
+    let mut repeated = vec![false; words.len()];
+    let mut i = 0;
+    while i < words.len() {
+        // The phrase length whose loop covers the most words from here.
+        let mut best: Option<(usize, usize)> = None;
+        for len in 1..=MAX_PHRASE_WORDS.min(words.len() - i) {
+            let copies = copies_from(words, i, len);
+            let needed = if len == 1 {
+                MIN_WORD_REPEATS
+            } else {
+                MIN_PHRASE_REPEATS
+            };
+            if copies >= needed && copies * len > best.map_or(0, |(l, c)| l * c) {
+                best = Some((len, copies));
+            }
+        }
+
+        match best {
+            Some((len, copies)) => {
+                repeated[i + len..i + len * copies].fill(true);
+                i += len * copies;
+            }
+            None => i += 1,
+        }
+    }
+    repeated
+}
+
+/// How many times the `len` words at `start` occur back to back.
+fn copies_from(words: &[Word], start: usize, len: usize) -> usize {
+    let phrase = &words[start..start + len];
+    let mut copies = 1;
+    while let Some(next) = words.get(start + copies * len..start + (copies + 1) * len) {
+        if !next.iter().zip(phrase).all(|(a, b)| a.key == b.key) {
+            break;
+        }
+        copies += 1;
+    }
+    copies
+}
+
+fn too_dense(segment: &Segment) -> bool {
+    let chars = segment.text.chars().filter(|c| c.is_alphanumeric()).count();
+    let secs = (segment.end - segment.start).max(0.0);
+    chars as f64 > MAX_CHARS_PER_SEC * secs + CHAR_SLACK
+}
+
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
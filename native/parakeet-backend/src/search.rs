@@ -0,0 +1,404 @@
+//! `search`: finding segments across past transcripts.
+//!
+//! Transcripts come from `--journal` files and `--out-sqlite` databases,
+//! told apart by the SQLite header. Their segments are copied into an
+//! index database with an FTS5 table (SQLite's full-text search, Porter
+//! stemmed, so "budgets" finds "budget"), and each search first brings the
+//! index up to date: a journal is read on from the byte offset where the
+//! last search stopped, a database from the last `files` row it had. A
+//! source that has shrunk since was rotated or replaced and is indexed
+//! again from the start. Only the sources named on the command line are
+//! searched, so one index can serve several archives.
+//!
+//! Every word of the query must appear, in any order; `"..."` matches a
+//! phrase, `OR` between words matches either and `word*` any word that
+//! starts with `word`. Matches are printed best first, one JSON line each.
+
+use crate::timestamps::TimestampFormat;
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, OpenFlags, Transaction};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sources (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    position INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS segments (
+    id INTEGER PRIMARY KEY,
+    source_id INTEGER NOT NULL REFERENCES sources(id),
+    file TEXT,
+    recorded_at_ms INTEGER NOT NULL,
+    start_time REAL,
+    end_time REAL,
+    text TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS segments_source ON segments(source_id);
+CREATE VIRTUAL TABLE IF NOT EXISTS segments_fts USING fts5(
+    text, content = 'segments', content_rowid = 'id', tokenize = 'porter unicode61'
+);
+";
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+/// Most words in a snippet
+const SNIPPET_WORDS: i64 = 12;
+
+/// A journal line, as far as the index needs it.
+#[derive(Deserialize)]
+struct Record {
+    recorded_at_ms: u64,
+    source: Option<PathBuf>,
+    result: RecordResult,
+}
+
+#[derive(Deserialize)]
+struct RecordResult {
+    text: String,
+    #[serde(default)]
+    segments: Vec<RecordSegment>,
+}
+
+#[derive(Deserialize)]
+struct RecordSegment {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+/// One segment to index.
+struct Indexed {
+    file: Option<String>,
+    recorded_at_ms: i64,
+    /// Missing when only the transcript's text was kept
+    span: Option<(f64, f64)>,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct Match {
+    file: Option<String>,
+    recorded_at_ms: i64,
+    start: Option<f64>,
+    end: Option<f64>,
+    text: String,
+    /// The text around the match, matched words in [brackets]
+    snippet: String,
+}
+
+/// `~/Library/Application Support/WhisperMac/search.sqlite`
+pub fn default_index() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").context("HOME is not set; pass --index")?;
+    Ok(PathBuf::from(home).join("Library/Application Support/WhisperMac/search.sqlite"))
+}
+
+pub fn run(
+    index: &Path,
+    sources: &[PathBuf],
+    query: &str,
+    limit: usize,
+    timestamps: TimestampFormat,
+) -> Result<()> {
+    let query = fts_query(query);
+    if query.is_empty() {
+        bail!("Nothing to search for");
+    }
+
+    if let Some(dir) = index.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut conn = Connection::open(index)
+        .with_context(|| format!("Failed to open search index {}", index.display()))?;
+    conn.execute_batch(SCHEMA)
+        .context("Failed to create search index schema")?;
+
+    let mut source_ids = Vec::new();
+    for source in sources {
+        let source = std::fs::canonicalize(source)
+            .with_context(|| format!("Transcript source {} does not exist", source.display()))?;
+        source_ids.push(update(&mut conn, &source)?);
+    }
+
+    let id_list = source_ids
+        .iter()
+        .map(i64::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut statement = conn.prepare(&format!(
+        "SELECT s.file, s.recorded_at_ms, s.start_time, s.end_time, s.text,
+                snippet(segments_fts, 0, '[', ']', '…', {})
+         FROM segments_fts JOIN segments s ON s.id = segments_fts.rowid
+         WHERE segments_fts MATCH ?1 AND s.source_id IN ({})
+         ORDER BY rank LIMIT ?2",
+        SNIPPET_WORDS, id_list
+    ))?;
+    let matches = statement
+        .query_map(params![query, limit as i64], |row| {
+            Ok(Match {
+                file: row.get(0)?,
+                recorded_at_ms: row.get(1)?,
+                start: row.get(2)?,
+                end: row.get(3)?,
+                text: row.get(4)?,
+                snippet: row.get(5)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .context("Search failed")?;
+
+    let mut out = io::stdout().lock();
+    for found in &matches {
+        serde_json::to_writer(&mut out, &timestamps.to_value(found)?)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Indexes what `source` gained since the last search. Returns its id.
+fn update(conn: &mut Connection, source: &Path) -> Result<i64> {
+    let path = source.to_string_lossy().into_owned();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT OR IGNORE INTO sources (path, position) VALUES (?1, 0)",
+        params![path],
+    )?;
+    let (id, position): (i64, i64) = tx.query_row(
+        "SELECT id, position FROM sources WHERE path = ?1",
+        params![path],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut header = [0; SQLITE_HEADER.len()];
+    let is_database = File::open(source)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok()
+        && header == *SQLITE_HEADER;
+    let read = if is_database {
+        read_database(source, position)?
+    } else {
+        read_journal(source, position)?
+    };
+    let (segments, new_position) = match read {
+        Some(read) => read,
+        None => {
+            log::info!("{} shrank; indexing it again", source.display());
+            clear(&tx, id)?;
+            if is_database {
+                read_database(source, 0)?
+            } else {
+                read_journal(source, 0)?
+            }
+            .unwrap_or_default()
+        }
+    };
+
+    if !segments.is_empty() {
+        log::info!(
+            "Indexing {} segments from {}",
+            segments.len(),
+            source.display()
+        );
+    }
+    insert(&tx, id, &segments)?;
+    tx.execute(
+        "UPDATE sources SET position = ?1 WHERE id = ?2",
+        params![new_position, id],
+    )?;
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Reads the journal's complete lines from byte `position` on, with the
+/// offset after them; `None` if the journal is now shorter than that.
+fn read_journal(path: &Path, position: i64) -> Result<Option<(Vec<Indexed>, i64)>> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open journal {}", path.display()))?;
+    if file.metadata()?.len() < position as u64 {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(position as u64))?;
+
+    let mut reader = BufReader::new(file);
+    let mut segments = Vec::new();
+    let mut position = position;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line)?;
+        // A line still being written is picked up by the next search.
+        if len == 0 || line.last() != Some(&b'\n') {
+            break;
+        }
+        position += len as i64;
+
+        let record: Record = match serde_json::from_slice(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                log::warn!("Skipping unreadable line in {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let file = record.source.map(|p| p.to_string_lossy().into_owned());
+        let recorded_at_ms = record.recorded_at_ms as i64;
+        if record.result.segments.is_empty() {
+            segments.push(Indexed {
+                file,
+                recorded_at_ms,
+                span: None,
+                text: record.result.text,
+            });
+            continue;
+        }
+        for segment in record.result.segments {
+            segments.push(Indexed {
+                file: file.clone(),
+                recorded_at_ms,
+                span: Some((segment.start, segment.end)),
+                text: segment.text,
+            });
+        }
+    }
+    Ok(Some((segments, position)))
+}
+
+/// Reads the `--out-sqlite` files after row `position`, with the last row
+/// read; `None` if the database no longer has that row.
+fn read_database(path: &Path, position: i64) -> Result<Option<(Vec<Indexed>, i64)>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open SQLite database {}", path.display()))?;
+    let last: Option<i64> = conn
+        .query_row("SELECT MAX(id) FROM files", [], |row| row.get(0))
+        .with_context(|| format!("{} is not an --out-sqlite database", path.display()))?;
+    let last = last.unwrap_or(0);
+    if last < position {
+        return Ok(None);
+    }
+
+    // Files stored without segments are indexed by their whole text, as
+    // journal entries are.
+    let mut statement = conn.prepare(
+        "SELECT f.path, f.transcribed_at, s.start_time, s.end_time, COALESCE(s.text, f.text)
+         FROM files f LEFT JOIN segments s ON s.file_id = f.id
+         WHERE f.id > ?1 AND f.id <= ?2
+         ORDER BY f.id, s.start_time",
+    )?;
+    let segments = statement
+        .query_map(params![position, last], |row| {
+            let start: Option<f64> = row.get(2)?;
+            let end: Option<f64> = row.get(3)?;
+            Ok(Indexed {
+                file: row.get(0)?,
+                recorded_at_ms: row.get::<_, i64>(1)? * 1000,
+                span: start.zip(end),
+                text: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(Some((segments, last)))
+}
+
+fn insert(tx: &Transaction<'_>, source_id: i64, segments: &[Indexed]) -> Result<()> {
+    let mut insert_segment = tx.prepare(
+        "INSERT INTO segments (source_id, file, recorded_at_ms, start_time, end_time, text)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    let mut insert_text = tx.prepare("INSERT INTO segments_fts (rowid, text) VALUES (?1, ?2)")?;
+    for segment in segments {
+        let text = segment.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        insert_segment.execute(params![
+            source_id,
+            segment.file,
+            segment.recorded_at_ms,
+            segment.span.map(|s| s.0),
+            segment.span.map(|s| s.1),
+            text
+        ])?;
+        insert_text.execute(params![tx.last_insert_rowid(), text])?;
+    }
+    Ok(())
+}
+
+/// Drops everything indexed from a source.
+fn clear(tx: &Transaction<'_>, source_id: i64) -> Result<()> {
+    tx.execute(
+        "INSERT INTO segments_fts (segments_fts, rowid, text)
+         SELECT 'delete', id, text FROM segments WHERE source_id = ?1",
+        params![source_id],
+    )?;
+    tx.execute(
+        "DELETE FROM segments WHERE source_id = ?1",
+        params![source_id],
+    )?;
+    Ok(())
+}
+
+/// The query in FTS5 syntax, with every word quoted so punctuation such as
+/// "don't" can't be read as an operator. `OR` is kept only between two
+/// terms; anywhere else FTS5 rejects the whole query.
+fn fts_query(query: &str) -> String {
+    let mut terms = Vec::new();
+    let mut rest = query.trim();
+    while !rest.is_empty() {
+        if let Some(phrase) = rest.strip_prefix('"') {
+            let end = phrase.find('"').unwrap_or(phrase.len());
+            terms.push(quote(&phrase[..end]));
+            rest = phrase.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = &rest[..end];
+            if word == "OR" {
+                if terms.last().is_some_and(|t| t != "OR") {
+                    terms.push(word.to_string());
+                }
+            } else {
+                terms.push(match word.strip_suffix('*') {
+                    Some(prefix) if !prefix.is_empty() => format!("{}*", quote(prefix)),
+                    _ => quote(word),
+                });
+            }
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    if terms.last().is_some_and(|t| t == "OR") {
+        terms.pop();
+    }
+    terms.join(" ")
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_words_phrases_and_prefixes() {
+        assert_eq!(
+            fts_query(r#"don't "standup notes" deploy* *"#),
+            r#""don't" "standup notes" "deploy"* "*""#
+        );
+    }
+
+    #[test]
+    fn keeps_or_only_between_terms() {
+        assert_eq!(fts_query("budget OR forecast"), r#""budget" OR "forecast""#);
+        assert_eq!(fts_query("OR budget OR"), r#""budget""#);
+        assert_eq!(
+            fts_query("budget OR OR  OR forecast"),
+            r#""budget" OR "forecast""#
+        );
+        assert_eq!(fts_query("OR OR"), "");
+        assert_eq!(fts_query(r#""OR" budget"#), r#""OR" "budget""#);
+    }
+}
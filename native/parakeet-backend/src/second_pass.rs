@@ -0,0 +1,223 @@
+//! `--second-pass`: re-decoding the hard parts of a transcript with a
+//! heavier model.
+//!
+//! transcribe-rs returns no token scores (see `words`), so confidence is
+//! measured by agreement instead. The transcript is cut into spans at
+//! pauses and sentence ends, and each span's audio is decoded again by
+//! Parakeet on its own. Where the decoder was sure of itself the two
+//! readings agree; where it was guessing, losing the surrounding context
+//! changes the words. A span's confidence is the share of its words that
+//! survive, by word-level edit distance, and spans below the threshold are
+//! decoded by the secondary model, whose text replaces them. That costs a
+//! second, fast Parakeet pass over everything and a slow pass only over
+//! what needs it.
+//!
+//! The secondary model is another Parakeet model directory or a Whisper
+//! GGML model file. Replaced spans are listed under `revisions` with the
+//! text they had before.
+
+use crate::incremental::SAMPLE_RATE;
+use crate::retry::{EngineResult, RetryPolicy};
+use crate::{AudioInput, Segment, TranscriptionOutput};
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use transcribe_rs::engines::parakeet::ParakeetEngine;
+use transcribe_rs::engines::whisper::WhisperEngine;
+use transcribe_rs::{TranscriptionEngine, TranscriptionResult};
+
+/// A pause this long ends a span
+const SPAN_GAP_SECS: f64 = 0.5;
+/// Spans end at a sentence end once they are this long
+const MIN_SPAN_SECS: f64 = 3.0;
+/// And are cut regardless once they reach this
+const MAX_SPAN_SECS: f64 = 15.0;
+/// Audio kept either side of a span when it is decoded on its own
+const PAD_SECS: f64 = 0.2;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Revision {
+    pub start: f64,
+    pub end: f64,
+    /// Agreement between the span's two Parakeet readings, 0 to 1
+    pub confidence: f32,
+    /// The span's text before the secondary model replaced it
+    pub original: String,
+}
+
+enum Secondary {
+    Parakeet(ParakeetEngine),
+    Whisper(WhisperEngine),
+}
+
+pub struct SecondPass {
+    engine: Secondary,
+    threshold: f32,
+    retry: RetryPolicy,
+}
+
+impl SecondPass {
+    /// Loads the secondary model: a directory is a Parakeet model, a file a
+    /// Whisper GGML model.
+    pub fn load(model: &Path, threshold: f32, retry: RetryPolicy) -> Result<Self> {
+        let load_error = |e| anyhow!("Failed to load second-pass model: {}", e);
+        let engine = if model.is_dir() {
+            crate::model::validate(model)?;
+            let mut engine = ParakeetEngine::new();
+            retry
+                .run("Second-pass model load", || engine.load_model(model))
+                .map_err(load_error)?;
+            Secondary::Parakeet(engine)
+        } else {
+            let mut engine = WhisperEngine::new();
+            retry
+                .run("Second-pass model load", || engine.load_model(model))
+                .map_err(load_error)?;
+            Secondary::Whisper(engine)
+        };
+        Ok(Self {
+            engine,
+            threshold,
+            retry,
+        })
+    }
+
+    /// Re-decodes the spans of `output` that `primary` is unsure of.
+    /// `samples` is the 16 kHz mono audio it was decoded from.
+    pub fn run(
+        &mut self,
+        primary: &mut ParakeetEngine,
+        samples: &[f32],
+        output: &mut TranscriptionOutput,
+    ) -> Result<()> {
+        let lens = span_lens(&output.segments);
+        let mut segments = Vec::with_capacity(output.segments.len());
+        let mut revisions = Vec::new();
+        let mut rest = std::mem::take(&mut output.segments).into_iter();
+
+        for len in lens {
+            let span: Vec<Segment> = rest.by_ref().take(len).collect();
+            let (start, end) = (span[0].start, span[len - 1].end);
+            let original: String = span.iter().map(|s| s.text.as_str()).collect();
+            let audio = pad(samples, start, end, PAD_SECS);
+
+            let alone = AudioInput::Samples(audio.to_vec())
+                .transcribe(primary, self.retry)
+                .map_err(|e| anyhow!("Transcription failed: {}", e))?;
+            let confidence = agreement(&original, &alone.text);
+            if confidence >= self.threshold {
+                segments.extend(span);
+                continue;
+            }
+
+            let redecoded = self
+                .transcribe(audio.to_vec())
+                .map_err(|e| anyhow!("Second-pass transcription failed: {}", e))?;
+            log::debug!(
+                "Re-decoded {:.1}-{:.1}s (confidence {:.2})",
+                start,
+                end,
+                confidence
+            );
+            segments.push(Segment {
+                start,
+                end,
+                text: format!(" {}", redecoded.text.trim()),
+                overlap: span.iter().any(|s| s.overlap),
+                suspect: None,
+            });
+            revisions.push(Revision {
+                start,
+                end,
+                confidence,
+                original: original.trim().to_string(),
+            });
+        }
+
+        if !revisions.is_empty() {
+            output.text = segments
+                .iter()
+                .map(|s| s.text.as_str())
+                .collect::<String>()
+                .trim()
+                .to_string();
+        }
+        output.segments = segments;
+        output.revisions = revisions;
+        Ok(())
+    }
+
+    fn transcribe(&mut self, samples: Vec<f32>) -> EngineResult<TranscriptionResult> {
+        match &mut self.engine {
+            Secondary::Parakeet(engine) => {
+                AudioInput::Samples(samples).transcribe(engine, self.retry)
+            }
+            Secondary::Whisper(engine) => self.retry.run("Second-pass transcription", || {
+                engine.transcribe_samples(samples.clone(), None)
+            }),
+        }
+    }
+}
+
+/// Number of segments in each span, in order.
+pub fn span_lens(segments: &[Segment]) -> Vec<usize> {
+    let mut lens = Vec::new();
+    let mut first = 0;
+
+    for i in 1..=segments.len() {
+        let ends_span = i == segments.len() || {
+            let (previous, next) = (&segments[i - 1], &segments[i]);
+            let duration = previous.end - segments[first].start;
+            next.start - previous.end >= SPAN_GAP_SECS
+                || duration >= MAX_SPAN_SECS
+                || (duration >= MIN_SPAN_SECS
+                    && previous.text.trim_end().ends_with(['.', '?', '!']))
+        };
+        if ends_span {
+            lens.push(i - first);
+            first = i;
+        }
+    }
+    lens
+}
+
+/// The audio from `start` to `end` with `pad_secs` more either side.
+pub fn pad(samples: &[f32], start: f64, end: f64, pad_secs: f64) -> &[f32] {
+    let at = |secs: f64| ((secs.max(0.0) * SAMPLE_RATE as f64) as usize).min(samples.len());
+    let (from, to) = (at(start - pad_secs), at(end + pad_secs));
+    &samples[from..to.max(from)]
+}
+
+/// Share of words the two readings have in common, by word-level edit
+/// distance over case-folded words without punctuation.
+fn agreement(a: &str, b: &str) -> f32 {
+    let words = |text: &str| -> Vec<String> {
+        text.split_whitespace()
+            .map(|w| {
+                w.chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .flat_map(char::to_lowercase)
+                    .collect::<String>()
+            })
+            .filter(|w| !w.is_empty())
+            .collect()
+    };
+    let (a, b) = (words(a), words(b));
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(x != y);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    1.0 - row[b.len()] as f32 / longest as f32
+}
@@ -0,0 +1,171 @@
+//! Context carried between utterances of one `session_id` in server mode.
+//!
+//! Parakeet takes no text prompt, so continuity comes from two places: the
+//! last second of the previous utterance is decoded again as left context
+//! (and dropped from the result), which stops the first word of every
+//! utterance being recognized cold; and an utterance that continues an
+//! unfinished sentence has the capital letter the model gives every
+//! utterance start removed (which also lowercases a name that happens to
+//! open such a continuation).
+
+use crate::incremental::SAMPLE_RATE;
+use crate::{Segment, TranscriptionOutput, TranscriptionStatus};
+
+/// Audio from the previous utterance decoded again as context.
+const CONTEXT_SECS: f64 = 1.0;
+
+/// What one utterance carries into the session, applied by `finish` once it
+/// has decoded.
+pub struct Carry {
+    /// Seconds of the decoded audio that are the previous utterance's tail
+    context_secs: f64,
+    /// This utterance's tail, context for the next one
+    tail: Vec<f32>,
+}
+
+#[derive(Default)]
+pub struct Session {
+    tail: Vec<f32>,
+    last_text: String,
+    paused: bool,
+}
+
+impl Session {
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Prepends the previous utterance's tail. Returns the audio to decode
+    /// and what to hand `finish` once it decoded; the session itself is
+    /// left alone until then, so a failed decode does not move it on.
+    pub fn with_context(&self, samples: &[f32]) -> (Vec<f32>, Carry) {
+        let context_secs = self.tail.len() as f64 / SAMPLE_RATE as f64;
+        let mut audio = Vec::with_capacity(self.tail.len() + samples.len());
+        audio.extend_from_slice(&self.tail);
+        audio.extend_from_slice(samples);
+
+        let keep = (CONTEXT_SECS * SAMPLE_RATE as f64) as usize;
+        let tail = audio[audio.len().saturating_sub(keep)..].to_vec();
+        (audio, Carry { context_secs, tail })
+    }
+
+    /// Removes the context from a successfully decoded `output` and records
+    /// the utterance for the next one.
+    pub fn finish(&mut self, output: &mut TranscriptionOutput, carry: Carry) {
+        let Carry { context_secs, tail } = carry;
+        self.tail = tail;
+
+        if context_secs > 0.0 && !output.segments.is_empty() {
+            output
+                .segments
+                .retain(|s| (s.start + s.end) / 2.0 >= context_secs);
+            for segment in &mut output.segments {
+                segment.start = (segment.start - context_secs).max(0.0);
+                segment.end -= context_secs;
+            }
+            output.text = join(&output.segments);
+        }
+
+        if continues_sentence(&self.last_text) {
+            lowercase_first_word(&mut output.text);
+            if let Some(first) = output.segments.first_mut() {
+                lowercase_first_word(&mut first.text);
+            }
+        }
+
+        output.status = if output.text.is_empty() {
+            TranscriptionStatus::NoSpeech
+        } else {
+            TranscriptionStatus::Ok
+        };
+        if !output.text.is_empty() {
+            self.last_text = output.text.clone();
+        }
+    }
+}
+
+/// The segments' text as one string. Token-level segments mark each word
+/// start with a leading space, so one without continues the word before it;
+/// word and sentence segments carry no spacing and are joined with spaces.
+fn join(segments: &[Segment]) -> String {
+    let texts = segments.iter().map(|s| s.text.as_str());
+    let spaced = segments
+        .iter()
+        .any(|s| s.text.starts_with(char::is_whitespace));
+    let text = if spaced {
+        texts.collect::<String>()
+    } else {
+        texts.collect::<Vec<_>>().join(" ")
+    };
+    text.trim().to_string()
+}
+
+fn continues_sentence(previous: &str) -> bool {
+    previous
+        .trim_end()
+        .chars()
+        .last()
+        .is_some_and(|c| !matches!(c, '.' | '?' | '!' | '…' | ':' | '"'))
+}
+
+/// Lowercases the first word unless it is "I" or looks like an acronym.
+fn lowercase_first_word(text: &mut String) {
+    let trimmed = text.trim_start();
+    let offset = text.len() - trimmed.len();
+    let word: &str = trimmed
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .next()
+        .unwrap_or("");
+
+    let is_pronoun = word == "I" || word.starts_with("I'");
+    let is_acronym = word.chars().filter(|c| c.is_uppercase()).count() > 1;
+    if word.is_empty() || is_pronoun || is_acronym {
+        return;
+    }
+
+    let mut chars = word.chars();
+    if let Some(first) = chars.next() {
+        let lowered: String = first.to_lowercase().chain(chars).collect();
+        text.replace_range(offset..offset + word.len(), &lowered);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(texts: &[&str]) -> Vec<Segment> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Segment {
+                start: i as f64,
+                end: i as f64 + 1.0,
+                text: text.to_string(),
+                overlap: false,
+                suspect: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn joins_word_and_sentence_segments_with_spaces() {
+        assert_eq!(join(&segments(&["Hello", "there."])), "Hello there.");
+        assert_eq!(
+            join(&segments(&["Hello there.", "How are you?"])),
+            "Hello there. How are you?"
+        );
+    }
+
+    #[test]
+    fn glues_token_segments_as_they_are() {
+        assert_eq!(
+            join(&segments(&[" Hel", "lo", " there", "."])),
+            "Hello there."
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Read-only access to POSIX shared memory written by the host app.
+//!
+//! The host creates the region with `shm_open`, fills it with 16 kHz mono
+//! native-endian f32 samples and passes its name in the `transcribe`
+//! command. The host owns the region and is responsible for unlinking it.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Copies `len` bytes of samples out of the shared memory object `name`,
+/// or the whole region when `len` is omitted.
+pub fn read_samples(name: &str, len: Option<usize>) -> Result<Vec<f32>> {
+    let c_name = CString::new(name).context("Shared memory name contains a NUL byte")?;
+
+    let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDONLY, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Failed to open shared memory {}", name));
+    }
+    let file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+
+    // macOS rounds the reported size up to a page, so an explicit `len` is
+    // the only way to know where the samples end.
+    let size = file.metadata()?.len() as usize;
+    let len = len.unwrap_or(size);
+    if len > size {
+        bail!(
+            "Requested {} bytes but shared memory {} holds only {}",
+            len,
+            name,
+            size
+        );
+    }
+    if !len.is_multiple_of(4) {
+        bail!(
+            "Shared memory length {} is not a whole number of f32 samples",
+            len
+        );
+    }
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Failed to map shared memory {}", name));
+    }
+
+    // mmap returns page-aligned memory, so viewing it as f32 is sound.
+    let samples = unsafe { std::slice::from_raw_parts(ptr as *const f32, len / 4) }.to_vec();
+
+    unsafe {
+        libc::munmap(ptr, len);
+    }
+
+    Ok(samples)
+}
@@ -0,0 +1,157 @@
+//! Enrolled speakers (`speakers enroll|list|remove`) and naming them in
+//! capture transcripts (`capture --identify-speakers`).
+//!
+//! Each enrolled speaker is one JSON file in the profile directory holding
+//! their voiceprint. During capture every utterance's own voiceprint is
+//! compared against them and the closest one above the threshold names the
+//! utterance's speaker.
+//!
+//! This is experimental. A voiceprint (see `voiceprint`) averages the
+//! microphone and room along with the voice, so two people enrolled on the
+//! same mic can look alike. Enrollment therefore checks that the sample
+//! matches itself, half against half, more closely than it matches anyone
+//! already enrolled, and refuses speakers it could not tell apart.
+
+use crate::voiceprint::{self, Voiceprint};
+use crate::wav;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub embedding: Vec<f32>,
+    pub enrolled_at_ms: u64,
+}
+
+/// `~/Library/Application Support/WhisperMac/speakers`
+pub fn default_dir() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").context("HOME is not set; pass --speaker-dir")?;
+    Ok(PathBuf::from(home).join("Library/Application Support/WhisperMac/speakers"))
+}
+
+fn profile_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Invalid speaker name '{}'", name);
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+/// Stores the voiceprint of `sample`, a 16 kHz mono WAV of `name` talking,
+/// replacing any earlier enrollment under that name.
+pub fn enroll(dir: &Path, name: &str, sample: &Path) -> Result<Profile> {
+    let path = profile_path(dir, name)?;
+    let samples = wav::read(sample)?;
+
+    let embedding =
+        embed(&samples).with_context(|| format!("No speech found in {}", sample.display()))?;
+
+    let (first, second) = samples.split_at(samples.len() / 2);
+    let (Some(first), Some(second)) = (embed(first), embed(second)) else {
+        bail!(
+            "{} has speech in only one half; record {} talking throughout",
+            sample.display(),
+            name
+        );
+    };
+    let own = voiceprint::similarity(&first, &second);
+    for other in load(dir)?.iter().filter(|p| p.name != name) {
+        let closeness = voiceprint::similarity(&embedding, &other.embedding);
+        if closeness >= own {
+            bail!(
+                "{} sounds as close to {} ({:.3}) as to itself ({:.3}), so the two \
+                 can't be told apart; voiceprints also capture the microphone and \
+                 room, so record each speaker in the setup they will be captured in",
+                name,
+                other.name,
+                closeness,
+                own
+            );
+        }
+    }
+
+    let profile = Profile {
+        name: name.to_string(),
+        embedding,
+        enrolled_at_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+    };
+
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create profile directory {}", dir.display()))?;
+    fs::write(&path, serde_json::to_vec_pretty(&profile)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(profile)
+}
+
+pub fn remove(dir: &Path, name: &str) -> Result<()> {
+    let path = profile_path(dir, name)?;
+    fs::remove_file(&path).with_context(|| format!("No speaker named '{}' is enrolled", name))
+}
+
+/// Every enrolled speaker; an absent directory means nobody is enrolled.
+pub fn load(dir: &Path) -> Result<Vec<Profile>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    let mut profiles = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let profile: Profile = serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("Corrupt speaker profile {}", path.display()))?;
+        profiles.push(profile);
+    }
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(profiles)
+}
+
+fn embed(samples: &[f32]) -> Option<Vec<f32>> {
+    let mut print = Voiceprint::default();
+    print.push(samples);
+    print.embedding()
+}
+
+/// Names the speaker of an utterance from the enrolled profiles.
+pub struct Identifier {
+    profiles: Vec<Profile>,
+    threshold: f32,
+}
+
+impl Identifier {
+    pub fn new(profiles: Vec<Profile>, threshold: f32) -> Self {
+        if profiles.len() == 1 {
+            log::warn!(
+                "Only {} is enrolled, so anyone on a similar microphone may be named {}",
+                profiles[0].name,
+                profiles[0].name
+            );
+        }
+        Self {
+            profiles,
+            threshold,
+        }
+    }
+
+    /// The enrolled speaker closest to `samples`, if any is similar enough.
+    pub fn identify(&self, samples: &[f32]) -> Option<String> {
+        let embedding = embed(samples)?;
+
+        self.profiles
+            .iter()
+            .map(|p| (p, voiceprint::similarity(&embedding, &p.embedding)))
+            .filter(|(_, similarity)| *similarity >= self.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(profile, _)| profile.name.clone())
+    }
+}
@@ -0,0 +1,87 @@
+//! SQLite transcript sink (`--out-sqlite`).
+//!
+//! Every run appends one row to `files` plus its segments and words, so a
+//! database accumulates a queryable archive across many transcriptions.
+
+use crate::{words, TranscriptionOutput};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    transcribed_at INTEGER NOT NULL,
+    processing_time_ms INTEGER NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS segments (
+    id INTEGER PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    start_time REAL NOT NULL,
+    end_time REAL NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS words (
+    id INTEGER PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    start_time REAL NOT NULL,
+    end_time REAL NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS files_path ON files(path);
+CREATE INDEX IF NOT EXISTS segments_file_start ON segments(file_id, start_time);
+CREATE INDEX IF NOT EXISTS words_file_start ON words(file_id, start_time);
+CREATE INDEX IF NOT EXISTS words_text ON words(text COLLATE NOCASE);
+-- Earlier archives indexed segment text, which no query uses.
+DROP INDEX IF EXISTS segments_text;
+";
+
+pub fn append(db_path: &Path, source: &Path, output: &TranscriptionOutput) -> Result<()> {
+    let mut conn = Connection::open(db_path)
+        .with_context(|| format!("Failed to open SQLite database {}", db_path.display()))?;
+    // Off by default in SQLite, which would leave ON DELETE CASCADE inert.
+    conn.pragma_update(None, "foreign_keys", true)
+        .context("Failed to enable foreign keys")?;
+    conn.execute_batch(SCHEMA)
+        .context("Failed to create transcript schema")?;
+
+    let source = std::fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+    let transcribed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO files (path, transcribed_at, processing_time_ms, text) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            source.to_string_lossy().into_owned(),
+            transcribed_at,
+            output.processing_time_ms as i64,
+            output.text
+        ],
+    )?;
+    let file_id = tx.last_insert_rowid();
+
+    {
+        let mut insert_segment = tx.prepare(
+            "INSERT INTO segments (file_id, start_time, end_time, text) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for segment in &output.segments {
+            insert_segment.execute(params![file_id, segment.start, segment.end, segment.text])?;
+        }
+
+        let mut insert_word = tx.prepare(
+            "INSERT INTO words (file_id, start_time, end_time, text) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for word in words::from_segments(&output.segments) {
+            insert_word.execute(params![file_id, word.start, word.end, word.text])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Per-speaker statistics for a capture (`capture --stats`).
+//!
+//! Speakers are who the capture already tells apart: each source, split
+//! further by enrolled speaker name when `--identify-speakers` names them.
+//! Talk time runs from the start of each utterance to the end of its speech
+//! (without the trailing silence the segmenter waits for), and an interruption
+//! is an utterance that starts while another speaker's is still going,
+//! with more than `MIN_INTERRUPTION_SECS` of it left. Utterances on one
+//! source never overlap, so only the mic and system sources can interrupt
+//! each other.
+
+use crate::capture::Source;
+use serde::Serialize;
+
+/// Overlap shorter than this is someone finishing as the other starts
+const MIN_INTERRUPTION_SECS: f64 = 0.3;
+
+struct Utterance {
+    source: Source,
+    speaker: Option<String>,
+    start: f64,
+    end: f64,
+    words: usize,
+}
+
+#[derive(Default)]
+pub struct Stats {
+    utterances: Vec<Utterance>,
+}
+
+#[derive(Serialize)]
+pub struct SpeakerStats {
+    pub source: Source,
+    /// Enrolled speaker, when one was identified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    pub utterances: usize,
+    pub talk_secs: f64,
+    pub words: usize,
+    pub words_per_minute: f64,
+    /// Times this speaker started while someone else was talking
+    pub interruptions: usize,
+    /// Times someone else started while this speaker was talking
+    pub interrupted: usize,
+}
+
+impl Stats {
+    pub fn record(
+        &mut self,
+        source: Source,
+        speaker: Option<&str>,
+        start: f64,
+        end: f64,
+        words: usize,
+    ) {
+        self.utterances.push(Utterance {
+            source,
+            speaker: speaker.map(str::to_string),
+            start,
+            end,
+            words,
+        });
+    }
+
+    /// One entry per speaker, in the order they first spoke.
+    pub fn report(&self) -> Vec<SpeakerStats> {
+        let mut speakers: Vec<SpeakerStats> = Vec::new();
+        let mut utterances: Vec<&Utterance> = self.utterances.iter().collect();
+        utterances.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        for utterance in &utterances {
+            let index = index_of(&mut speakers, utterance);
+            let entry = &mut speakers[index];
+            entry.utterances += 1;
+            entry.talk_secs += utterance.end - utterance.start;
+            entry.words += utterance.words;
+        }
+
+        for later in &utterances {
+            let interrupted = utterances.iter().find(|earlier| {
+                !same_speaker(earlier, later)
+                    && earlier.start < later.start
+                    && earlier.end - later.start > MIN_INTERRUPTION_SECS
+            });
+            if let Some(earlier) = interrupted {
+                let index = index_of(&mut speakers, later);
+                speakers[index].interruptions += 1;
+                let index = index_of(&mut speakers, earlier);
+                speakers[index].interrupted += 1;
+            }
+        }
+
+        for entry in &mut speakers {
+            if entry.talk_secs > 0.0 {
+                entry.words_per_minute = entry.words as f64 * 60.0 / entry.talk_secs;
+            }
+        }
+        speakers
+    }
+}
+
+fn same_speaker(a: &Utterance, b: &Utterance) -> bool {
+    a.source == b.source && a.speaker == b.speaker
+}
+
+fn index_of(speakers: &mut Vec<SpeakerStats>, utterance: &Utterance) -> usize {
+    let found = speakers
+        .iter()
+        .position(|s| s.source == utterance.source && s.speaker == utterance.speaker);
+    found.unwrap_or_else(|| {
+        speakers.push(SpeakerStats {
+            source: utterance.source,
+            speaker: utterance.speaker.clone(),
+            utterances: 0,
+            talk_secs: 0.0,
+            words: 0,
+            words_per_minute: 0.0,
+            interruptions: 0,
+            interrupted: 0,
+        });
+        speakers.len() - 1
+    })
+}
@@ -0,0 +1,394 @@
+//! Utterance segmentation for live audio.
+//!
+//! Captured audio arrives as a continuous 16 kHz stream. A simple energy
+//! detector splits it into utterances on pauses, which are then transcribed
+//! one at a time. Times are seconds since the stream started.
+
+use crate::capture::Source;
+use crate::dsp;
+use crate::incremental::SAMPLE_RATE;
+use crate::stats::SpeakerStats;
+use crate::timestamps::TimestampFormat;
+use crate::{Segment, SCHEMA_VERSION};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Analysis frame: 30 ms.
+const FRAME_LEN: usize = SAMPLE_RATE as usize * 30 / 1000;
+
+#[derive(Clone, Copy, Debug)]
+pub struct VadConfig {
+    /// Frames louder than this (dBFS RMS) count as speech
+    pub threshold_db: f32,
+    /// Silence that ends an utterance
+    pub min_silence_ms: u32,
+    /// Utterances with less speech than this are dropped as noise
+    pub min_speech_ms: u32,
+    /// Audio kept from before speech starts, so onsets aren't clipped
+    pub pre_roll_ms: u32,
+    /// Utterances are cut here even without a pause
+    pub max_utterance_secs: f32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            threshold_db: -45.0,
+            min_silence_ms: 600,
+            min_speech_ms: 250,
+            pre_roll_ms: 200,
+            max_utterance_secs: 30.0,
+        }
+    }
+}
+
+/// A stretch of speech ready for transcription.
+pub struct Utterance {
+    pub start: f64,
+    pub end: f64,
+    /// End of speech: `end` less the trailing silence that closed the
+    /// utterance
+    pub speech_end: f64,
+    pub samples: Vec<f32>,
+}
+
+pub enum VadEvent {
+    SpeechStart {
+        at: f64,
+    },
+    /// End of speech, not counting the trailing silence that confirmed it.
+    /// Balances every `SpeechStart`, even when the utterance is then
+    /// dropped as noise.
+    SpeechEnd {
+        at: f64,
+    },
+    Utterance(Utterance),
+}
+
+struct Active {
+    start_sample: u64,
+    samples: Vec<f32>,
+    speech_samples: usize,
+    silence_run: usize,
+}
+
+pub struct Segmenter {
+    config: VadConfig,
+    /// Samples not yet making up a whole frame
+    partial: Vec<f32>,
+    /// Samples consumed in whole frames
+    position: u64,
+    pre_roll: VecDeque<f32>,
+    active: Option<Active>,
+}
+
+impl Segmenter {
+    pub fn new(config: VadConfig) -> Self {
+        Self {
+            config,
+            partial: Vec::with_capacity(FRAME_LEN),
+            position: 0,
+            pre_roll: VecDeque::new(),
+            active: None,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) -> Vec<VadEvent> {
+        let mut events = Vec::new();
+        let mut rest = samples;
+
+        while !rest.is_empty() {
+            let take = (FRAME_LEN - self.partial.len()).min(rest.len());
+            self.partial.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+
+            if self.partial.len() == FRAME_LEN {
+                let frame = std::mem::replace(&mut self.partial, Vec::with_capacity(FRAME_LEN));
+                self.process_frame(frame, &mut events);
+            }
+        }
+
+        events
+    }
+
+    /// Start time and audio of the utterance in progress, if speech is
+    /// ongoing.
+    pub fn active(&self) -> Option<(f64, &[f32])> {
+        self.active
+            .as_ref()
+            .map(|active| (seconds(active.start_sample), active.samples.as_slice()))
+    }
+
+    /// Ends the stream, closing whatever speech is still open.
+    pub fn flush(&mut self) -> Vec<VadEvent> {
+        let partial = std::mem::take(&mut self.partial);
+        self.position += partial.len() as u64;
+
+        let mut events = Vec::new();
+        if let Some(mut active) = self.active.take() {
+            active.samples.extend_from_slice(&partial);
+            events.push(VadEvent::SpeechEnd {
+                at: seconds(self.position),
+            });
+            events.extend(self.finish(active).map(VadEvent::Utterance));
+        }
+        events
+    }
+
+    fn process_frame(&mut self, frame: Vec<f32>, events: &mut Vec<VadEvent>) {
+        let is_speech = rms_db(&frame) > self.config.threshold_db;
+        let frame_start = self.position;
+        self.position += frame.len() as u64;
+
+        match &mut self.active {
+            None if is_speech => {
+                let pre_roll: Vec<f32> = self.pre_roll.drain(..).collect();
+                let start_sample = frame_start - pre_roll.len() as u64;
+                let mut samples = pre_roll;
+                samples.extend_from_slice(&frame);
+
+                events.push(VadEvent::SpeechStart {
+                    at: seconds(start_sample),
+                });
+                self.active = Some(Active {
+                    start_sample,
+                    samples,
+                    speech_samples: frame.len(),
+                    silence_run: 0,
+                });
+            }
+            None => {
+                self.pre_roll.extend(&frame);
+                let keep = ms_to_samples(self.config.pre_roll_ms);
+                while self.pre_roll.len() > keep {
+                    self.pre_roll.pop_front();
+                }
+            }
+            Some(active) => {
+                active.samples.extend_from_slice(&frame);
+                if is_speech {
+                    active.speech_samples += frame.len();
+                    active.silence_run = 0;
+                } else {
+                    active.silence_run += frame.len();
+                }
+
+                let paused = active.silence_run >= ms_to_samples(self.config.min_silence_ms);
+                let too_long = active.samples.len() as f32
+                    >= self.config.max_utterance_secs * SAMPLE_RATE as f32;
+
+                if paused {
+                    events.push(VadEvent::SpeechEnd {
+                        at: seconds(self.position - active.silence_run as u64),
+                    });
+                }
+                if paused || too_long {
+                    let active = self.active.take().expect("active utterance");
+                    if let Some(utterance) = self.finish(active) {
+                        events.push(VadEvent::Utterance(utterance));
+                    }
+                    // A forced cut mid-speech carries straight on into the
+                    // next utterance.
+                    if too_long && !paused {
+                        self.active = Some(Active {
+                            start_sample: self.position,
+                            samples: Vec::new(),
+                            speech_samples: 0,
+                            silence_run: 0,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(&self, active: Active) -> Option<Utterance> {
+        if active.speech_samples < ms_to_samples(self.config.min_speech_ms) {
+            return None;
+        }
+
+        let start = seconds(active.start_sample);
+        let end_sample = active.start_sample + active.samples.len() as u64;
+        let end = seconds(end_sample);
+        let speech_end = seconds(end_sample - active.silence_run as u64);
+        Some(Utterance {
+            start,
+            end,
+            speech_end,
+            samples: active.samples,
+        })
+    }
+}
+
+/// Input level over one metering window.
+pub struct Level {
+    /// End of the window
+    pub at: f64,
+    pub rms_db: f32,
+    pub peak_db: f32,
+}
+
+/// Summarizes the stream into fixed windows for a level meter.
+pub struct LevelMeter {
+    window: usize,
+    count: usize,
+    sum_squares: f64,
+    peak: f32,
+    position: u64,
+}
+
+impl LevelMeter {
+    pub fn new(hz: f32) -> Self {
+        Self {
+            window: ((SAMPLE_RATE as f32 / hz) as usize).max(1),
+            count: 0,
+            sum_squares: 0.0,
+            peak: 0.0,
+            position: 0,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) -> Vec<Level> {
+        let mut levels = Vec::new();
+        for &sample in samples {
+            self.sum_squares += (sample * sample) as f64;
+            self.peak = self.peak.max(sample.abs());
+            self.count += 1;
+            self.position += 1;
+
+            if self.count == self.window {
+                let mean_square = (self.sum_squares / self.count as f64) as f32;
+                levels.push(Level {
+                    at: seconds(self.position),
+                    rms_db: 10.0 * mean_square.max(1e-12).log10(),
+                    peak_db: 20.0 * self.peak.max(1e-6).log10(),
+                });
+                self.count = 0;
+                self.sum_squares = 0.0;
+                self.peak = 0.0;
+            }
+        }
+        levels
+    }
+}
+
+fn seconds(samples: u64) -> f64 {
+    samples as f64 / SAMPLE_RATE as f64
+}
+
+fn ms_to_samples(ms: u32) -> usize {
+    SAMPLE_RATE as usize * ms as usize / 1000
+}
+
+/// Where to split `samples` for decoding in pieces: the middle of the
+/// longest run of silent frames from `from` on, or of the quietest frame
+/// there when nobody paused. Returns a sample index.
+pub fn pause_cut(samples: &[f32], from: usize) -> usize {
+    let threshold_db = VadConfig::default().threshold_db;
+    let mut longest: Option<(usize, usize)> = None;
+    let mut quietest: Option<(f32, usize)> = None;
+    let mut run_start = None;
+
+    let mut start = from;
+    while start + FRAME_LEN <= samples.len() {
+        let db = rms_db(&samples[start..start + FRAME_LEN]);
+        if quietest.is_none_or(|(quietest_db, _)| db < quietest_db) {
+            quietest = Some((db, start));
+        }
+        if db > threshold_db {
+            run_start = None;
+        } else {
+            let run_from = *run_start.get_or_insert(start);
+            let len = start + FRAME_LEN - run_from;
+            if longest.is_none_or(|(longest_len, _)| len > longest_len) {
+                longest = Some((len, run_from));
+            }
+        }
+        start += FRAME_LEN;
+    }
+
+    match (longest, quietest) {
+        (Some((len, run_from)), _) => run_from + len / 2,
+        (None, Some((_, frame))) => frame + FRAME_LEN / 2,
+        (None, None) => samples.len(),
+    }
+}
+
+pub fn rms_db(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let mean_square = dsp::sum_squares(samples) / samples.len() as f32;
+    10.0 * mean_square.max(1e-12).log10()
+}
+
+/// Events printed by live capture, one JSON object per line.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The voice activity detector heard speech begin. Sent as soon as it
+    /// is detected, ahead of any transcript.
+    SpeechStart { source: Source, at: f64 },
+    /// Speech stopped; its transcript follows
+    SpeechEnd { source: Source, at: f64 },
+    /// Input level for a meter, sent at `--level-hz`
+    Level {
+        source: Source,
+        at: f64,
+        rms_db: f32,
+        peak_db: f32,
+    },
+    /// Dictation progress: append `commit` to the text, and show `pending`
+    /// after it until the next event replaces it
+    Dictation {
+        source: Source,
+        commit: String,
+        pending: String,
+    },
+    /// A word of the utterance in progress has settled (`--word-events`)
+    Word {
+        source: Source,
+        start: f64,
+        end: f64,
+        text: String,
+    },
+    /// Voiceprint of one speaker heard on a source, sent for each of them
+    /// when capture ends (`--speaker-embeddings`)
+    Speaker {
+        source: Source,
+        /// The `cluster` of that speaker's transcripts
+        cluster: usize,
+        embedding: Vec<f32>,
+    },
+    /// An utterance was transcribed
+    Transcript {
+        source: Source,
+        /// Enrolled speaker it matched (`--identify-speakers`)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        speaker: Option<String>,
+        /// Which of the source's speakers said it (`--speaker-embeddings`)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cluster: Option<usize>,
+        start: f64,
+        end: f64,
+        text: String,
+        segments: Vec<Segment>,
+    },
+    /// Talk time, words and interruptions per speaker, sent when capture
+    /// ends (`--stats`)
+    Stats { speakers: Vec<SpeakerStats> },
+}
+
+#[derive(Serialize)]
+struct EventEnvelope<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+pub fn encode_event(event: &Event, timestamps: TimestampFormat) -> serde_json::Result<Vec<u8>> {
+    serde_json::to_vec(&timestamps.to_value(&EventEnvelope {
+        schema_version: SCHEMA_VERSION,
+        event,
+    })?)
+}
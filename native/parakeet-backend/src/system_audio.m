@@ -0,0 +1,190 @@
+// System audio capture through ScreenCaptureKit (macOS 13+).
+//
+// Exposes a two-function C API to the Rust side: start delivers float32
+// buffers of everything the Mac is playing (minus our own process) to a
+// callback on a private serial queue, stop tears the stream down and
+// returns only once no callback can run any more.
+
+#import <CoreMedia/CoreMedia.h>
+#import <Foundation/Foundation.h>
+#import <ScreenCaptureKit/ScreenCaptureKit.h>
+
+typedef void (*pk_audio_callback)(void *ctx, const float *samples, size_t frames,
+                                  double sample_rate);
+
+API_AVAILABLE(macos(13.0))
+@interface PKAudioOutput : NSObject <SCStreamOutput, SCStreamDelegate>
+@property(nonatomic) pk_audio_callback callback;
+@property(nonatomic) void *ctx;
+@end
+
+@implementation PKAudioOutput
+
+- (void)stream:(SCStream *)stream
+    didOutputSampleBuffer:(CMSampleBufferRef)sampleBuffer
+                   ofType:(SCStreamOutputType)type {
+    if (type != SCStreamOutputTypeAudio || !CMSampleBufferIsValid(sampleBuffer)) {
+        return;
+    }
+
+    CMFormatDescriptionRef format = CMSampleBufferGetFormatDescription(sampleBuffer);
+    const AudioStreamBasicDescription *asbd =
+        CMAudioFormatDescriptionGetStreamBasicDescription(format);
+    if (asbd == NULL || !(asbd->mFormatFlags & kAudioFormatFlagIsFloat)) {
+        return;
+    }
+
+    size_t listSize = 0;
+    CMSampleBufferGetAudioBufferListWithRetainedBlockBuffer(
+        sampleBuffer, &listSize, NULL, 0, NULL, NULL, 0, NULL);
+    AudioBufferList *list = malloc(listSize);
+    CMBlockBufferRef block = NULL;
+    OSStatus status = CMSampleBufferGetAudioBufferListWithRetainedBlockBuffer(
+        sampleBuffer, NULL, list, listSize, NULL, NULL,
+        kCMSampleBufferFlag_AudioBufferList_Assure16ByteAlignment, &block);
+
+    // The stream is configured for one channel, so the first buffer holds
+    // everything; with more channels it is the left one.
+    if (status == noErr && list->mNumberBuffers > 0) {
+        AudioBuffer buffer = list->mBuffers[0];
+        size_t frames = buffer.mDataByteSize / sizeof(float) /
+                        (asbd->mFormatFlags & kAudioFormatFlagIsNonInterleaved
+                             ? 1
+                             : MAX(buffer.mNumberChannels, 1));
+        self.callback(self.ctx, (const float *)buffer.mData, frames, asbd->mSampleRate);
+    }
+
+    if (block != NULL) {
+        CFRelease(block);
+    }
+    free(list);
+}
+
+- (void)stream:(SCStream *)stream didStopWithError:(NSError *)error {
+    NSLog(@"System audio capture stopped: %@", error.localizedDescription);
+}
+
+@end
+
+static SCStream *g_stream API_AVAILABLE(macos(13.0));
+static PKAudioOutput *g_output API_AVAILABLE(macos(13.0));
+static dispatch_queue_t g_queue;
+
+static void copy_error(char *error, size_t error_len, NSString *message) {
+    if (error != NULL && error_len > 0) {
+        strlcpy(error, message.UTF8String, error_len);
+    }
+}
+
+// Returns 0 on success; otherwise writes a message into `error`.
+int pk_system_audio_start(pk_audio_callback callback, void *ctx, uint32_t sample_rate,
+                          char *error, size_t error_len) {
+    if (@available(macOS 13.0, *)) {
+        if (g_stream != nil) {
+            copy_error(error, error_len, @"System audio capture is already running");
+            return -1;
+        }
+
+        __block SCShareableContent *content = nil;
+        __block NSError *contentError = nil;
+        dispatch_semaphore_t done = dispatch_semaphore_create(0);
+        [SCShareableContent
+            getShareableContentWithCompletionHandler:^(SCShareableContent *result, NSError *err) {
+              content = result;
+              contentError = err;
+              dispatch_semaphore_signal(done);
+            }];
+        dispatch_semaphore_wait(done, DISPATCH_TIME_FOREVER);
+
+        if (content == nil || content.displays.count == 0) {
+            copy_error(error, error_len,
+                       contentError
+                           ? [NSString stringWithFormat:@"%@ (grant Screen Recording permission "
+                                                        @"in System Settings > Privacy & Security)",
+                                                        contentError.localizedDescription]
+                           : @"No display available to capture audio from");
+            return -1;
+        }
+
+        // Audio capture still needs a display filter; the video it would
+        // produce is kept as small and infrequent as possible and ignored.
+        SCContentFilter *filter =
+            [[SCContentFilter alloc] initWithDisplay:content.displays.firstObject
+                                    excludingWindows:@[]];
+        SCStreamConfiguration *config = [[SCStreamConfiguration alloc] init];
+        config.capturesAudio = YES;
+        config.excludesCurrentProcessAudio = YES;
+        config.sampleRate = sample_rate;
+        config.channelCount = 1;
+        config.width = 2;
+        config.height = 2;
+        config.minimumFrameInterval = CMTimeMake(1, 1);
+
+        PKAudioOutput *output = [[PKAudioOutput alloc] init];
+        output.callback = callback;
+        output.ctx = ctx;
+
+        SCStream *stream = [[SCStream alloc] initWithFilter:filter
+                                              configuration:config
+                                                   delegate:output];
+        NSError *addError = nil;
+        dispatch_queue_t queue =
+            dispatch_queue_create("com.whispermac.parakeet.system-audio", DISPATCH_QUEUE_SERIAL);
+        if (![stream addStreamOutput:output
+                                type:SCStreamOutputTypeAudio
+                  sampleHandlerQueue:queue
+                               error:&addError]) {
+            copy_error(error, error_len, addError.localizedDescription);
+            return -1;
+        }
+
+        __block NSError *startError = nil;
+        [stream startCaptureWithCompletionHandler:^(NSError *err) {
+          startError = err;
+          dispatch_semaphore_signal(done);
+        }];
+        dispatch_semaphore_wait(done, DISPATCH_TIME_FOREVER);
+
+        if (startError != nil) {
+            copy_error(error, error_len, startError.localizedDescription);
+            return -1;
+        }
+
+        g_stream = stream;
+        g_output = output;
+        g_queue = queue;
+        return 0;
+    }
+
+    copy_error(error, error_len, @"System audio capture requires macOS 13 or later");
+    return -1;
+}
+
+void pk_system_audio_stop(void) {
+    if (@available(macOS 13.0, *)) {
+        if (g_stream == nil) {
+            return;
+        }
+
+        dispatch_semaphore_t done = dispatch_semaphore_create(0);
+        [g_stream stopCaptureWithCompletionHandler:^(NSError *err) {
+          dispatch_semaphore_signal(done);
+        }];
+        dispatch_semaphore_wait(done, DISPATCH_TIME_FOREVER);
+
+        // Stopping does not cancel buffers already queued for the output.
+        // Detach it so no more are queued, then let the serial queue run
+        // dry before the caller frees the callback's context.
+        NSError *removeError = nil;
+        if (![g_stream removeStreamOutput:g_output
+                                     type:SCStreamOutputTypeAudio
+                                    error:&removeError]) {
+            NSLog(@"Failed to detach system audio output: %@", removeError.localizedDescription);
+        }
+        dispatch_sync(g_queue, ^{});
+
+        g_stream = nil;
+        g_output = nil;
+        g_queue = nil;
+    }
+}
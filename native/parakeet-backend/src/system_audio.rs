@@ -0,0 +1,105 @@
+//! Rust side of `system_audio.m`: system output captured through
+//! ScreenCaptureKit, delivered as 16 kHz mono blocks.
+
+use crate::capture::{Block, Source};
+use crate::dsp::Resampler;
+use crate::incremental::SAMPLE_RATE;
+use anyhow::{bail, Result};
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+type AudioCallback =
+    extern "C" fn(ctx: *mut c_void, samples: *const f32, frames: usize, sample_rate: f64);
+
+extern "C" {
+    fn pk_system_audio_start(
+        callback: AudioCallback,
+        ctx: *mut c_void,
+        sample_rate: u32,
+        error: *mut c_char,
+        error_len: usize,
+    ) -> c_int;
+    fn pk_system_audio_stop();
+}
+
+struct State {
+    tx: Sender<Block>,
+    /// ScreenCaptureKit honours the requested rate in practice, but a
+    /// resampler is kept for whatever rate a buffer actually reports.
+    resampler: Option<(u32, Resampler)>,
+}
+
+/// Running capture; stops when dropped.
+pub struct SystemAudio {
+    state: *mut Mutex<State>,
+}
+
+impl SystemAudio {
+    pub fn start(tx: Sender<Block>) -> Result<Self> {
+        let state = Box::into_raw(Box::new(Mutex::new(State {
+            tx,
+            resampler: None,
+        })));
+
+        let mut error = [0 as c_char; 512];
+        let status = unsafe {
+            pk_system_audio_start(
+                on_audio,
+                state.cast(),
+                SAMPLE_RATE,
+                error.as_mut_ptr(),
+                error.len(),
+            )
+        };
+
+        if status != 0 {
+            drop(unsafe { Box::from_raw(state) });
+            let message = unsafe { CStr::from_ptr(error.as_ptr()) }.to_string_lossy();
+            bail!("Failed to start system audio capture: {}", message);
+        }
+
+        Ok(Self { state })
+    }
+}
+
+impl Drop for SystemAudio {
+    fn drop(&mut self) {
+        // Stop first: it drains the callback queue, so once it returns no
+        // callback can still be using `state`.
+        unsafe {
+            pk_system_audio_stop();
+            drop(Box::from_raw(self.state));
+        }
+    }
+}
+
+extern "C" fn on_audio(ctx: *mut c_void, samples: *const f32, frames: usize, sample_rate: f64) {
+    if samples.is_null() || frames == 0 {
+        return;
+    }
+
+    let state = unsafe { &*(ctx as *const Mutex<State>) };
+    let samples = unsafe { std::slice::from_raw_parts(samples, frames) };
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+
+    let rate = sample_rate.round() as u32;
+    let samples = if rate == SAMPLE_RATE {
+        samples.to_vec()
+    } else {
+        match &mut state.resampler {
+            Some((from, resampler)) if *from == rate => resampler.process(samples),
+            slot => {
+                let mut resampler = Resampler::new(rate, SAMPLE_RATE);
+                let out = resampler.process(samples);
+                *slot = Some((rate, resampler));
+                out
+            }
+        }
+    };
+
+    let _ = state.tx.send(Block {
+        source: Source::System,
+        samples,
+    });
+}
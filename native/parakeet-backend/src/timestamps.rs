@@ -0,0 +1,90 @@
+//! `--timestamp-format`: how times are written in the output.
+//!
+//! Everything inside the backend keeps times as seconds. The format is
+//! applied as output is written: every numeric `start`, `end` and `at`
+//! field gains a sibling in the chosen format (`start_ms`, `end_clock`,
+//! `at_samples`), so it reaches transcripts, cues, chapters, batch lines
+//! and capture events alike in every output encoding. The seconds stay
+//! where they were, so every output still matches the JSON schema
+//! (`schema`) of its `schema_version`. Storage is left alone: the cache,
+//! the journal and `--out-sqlite` always hold seconds, and live caption
+//! files use the timestamps their format prescribes.
+
+use crate::incremental::SAMPLE_RATE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const TIME_FIELDS: &[&str] = &["start", "end", "at"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// Seconds as a number, e.g. 83.52
+    #[default]
+    Seconds,
+    /// Whole milliseconds, e.g. 83520
+    Ms,
+    /// A clock string, e.g. "00:01:23.520"
+    #[value(name = "hh:mm:ss.mmm")]
+    #[serde(rename = "hh:mm:ss.mmm")]
+    Clock,
+    /// Offset in samples at the engine's 16 kHz, e.g. 1336320
+    Samples,
+}
+
+impl TimestampFormat {
+    /// `value` as JSON, with its times in this format.
+    pub fn to_value<T: Serialize>(self, value: &T) -> serde_json::Result<Value> {
+        let mut value = serde_json::to_value(value)?;
+        if self != TimestampFormat::Seconds {
+            self.rewrite(&mut value);
+        }
+        Ok(value)
+    }
+
+    fn rewrite(self, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                let mut formatted = Vec::new();
+                for (name, field) in fields.iter_mut() {
+                    match field.as_f64() {
+                        Some(secs) if TIME_FIELDS.contains(&name.as_str()) => formatted
+                            .push((format!("{}_{}", name, self.suffix()), self.format(secs))),
+                        _ => self.rewrite(field),
+                    }
+                }
+                fields.extend(formatted);
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.rewrite(item)),
+            _ => {}
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            TimestampFormat::Seconds => "secs",
+            TimestampFormat::Ms => "ms",
+            TimestampFormat::Clock => "clock",
+            TimestampFormat::Samples => "samples",
+        }
+    }
+
+    fn format(self, secs: f64) -> Value {
+        let secs = secs.max(0.0);
+        match self {
+            TimestampFormat::Seconds => Value::from(secs),
+            TimestampFormat::Ms => Value::from((secs * 1000.0).round() as u64),
+            TimestampFormat::Samples => Value::from((secs * SAMPLE_RATE as f64).round() as u64),
+            TimestampFormat::Clock => {
+                let millis = (secs * 1000.0).round() as u64;
+                Value::from(format!(
+                    "{:02}:{:02}:{:02}.{:03}",
+                    millis / 3_600_000,
+                    millis / 60_000 % 60,
+                    millis / 1000 % 60,
+                    millis % 1000
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Voiceprints: a fixed-length vector summarizing how a voice sounds.
+//!
+//! There is no speaker model in the tree, so this is not a neural speaker
+//! embedding. It is the long-term average of log mel band energies over
+//! voiced frames, with the overall level removed so the microphone gain
+//! does not matter. That separates a handful of voices on a call well
+//! enough to match them across recordings of the same meeting series, but
+//! it is no match for a trained embedding on large or acoustically varied
+//! sets of speakers.
+//!
+//! `Clusters` tells the speakers of one source apart the same way: each
+//! utterance's voiceprint joins the closest cluster it is similar enough
+//! to, or starts a new one.
+
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+/// 32 ms frames with 50% overlap at 16 kHz
+const FRAME_LEN: usize = 512;
+const HOP: usize = FRAME_LEN / 2;
+const BINS: usize = FRAME_LEN / 2 + 1;
+const BANDS: usize = 24;
+const LOW_HZ: f32 = 100.0;
+const HIGH_HZ: f32 = 7_600.0;
+/// Frames quieter than this (mean square) are skipped as silence
+const MIN_POWER: f32 = 1e-5;
+/// Similarity at which an utterance joins an existing speaker's cluster
+const SAME_SPEAKER: f32 = 0.85;
+
+/// Running sum of log band energies over voiced frames.
+#[derive(Clone, Default)]
+pub struct Voiceprint {
+    sum: Vec<f64>,
+    frames: usize,
+}
+
+impl Voiceprint {
+    /// Adds the voiced frames of a stretch of 16 kHz speech.
+    pub fn push(&mut self, samples: &[f32]) {
+        let analyzer = analyzer();
+        if self.sum.is_empty() {
+            self.sum = vec![0.0; BANDS];
+        }
+
+        let mut start = 0;
+        while start + FRAME_LEN <= samples.len() {
+            let frame = &samples[start..start + FRAME_LEN];
+            start += HOP;
+
+            let power = frame.iter().map(|s| s * s).sum::<f32>() / FRAME_LEN as f32;
+            if power < MIN_POWER {
+                continue;
+            }
+            for (sum, energy) in self.sum.iter_mut().zip(analyzer.band_energies(frame)) {
+                *sum += energy.max(1e-10).ln() as f64;
+            }
+            self.frames += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &Voiceprint) {
+        for (sum, other) in self.sum.iter_mut().zip(&other.sum) {
+            *sum += other;
+        }
+        self.frames += other.frames;
+    }
+
+    /// The voiceprint as a unit-length vector, once any speech was heard.
+    pub fn embedding(&self) -> Option<Vec<f32>> {
+        if self.frames == 0 {
+            return None;
+        }
+
+        let mean: Vec<f64> = self.sum.iter().map(|s| s / self.frames as f64).collect();
+        let level = mean.iter().sum::<f64>() / mean.len() as f64;
+        let centered: Vec<f64> = mean.iter().map(|m| m - level).collect();
+        let norm = centered.iter().map(|c| c * c).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            return None;
+        }
+        Some(centered.iter().map(|c| (c / norm) as f32).collect())
+    }
+}
+
+/// The speakers heard on one source, clustered utterance by utterance.
+#[derive(Default)]
+pub struct Clusters {
+    speakers: Vec<Voiceprint>,
+}
+
+impl Clusters {
+    /// Adds an utterance to its speaker's cluster, returning the cluster's
+    /// index; `None` when the utterance had no voiced frames.
+    pub fn push(&mut self, samples: &[f32]) -> Option<usize> {
+        let mut print = Voiceprint::default();
+        print.push(samples);
+        let embedding = print.embedding()?;
+
+        let closest = self
+            .speakers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, speaker)| Some((i, similarity(&embedding, &speaker.embedding()?))))
+            .filter(|(_, similarity)| *similarity >= SAME_SPEAKER)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match closest {
+            Some((index, _)) => {
+                self.speakers[index].merge(&print);
+                Some(index)
+            }
+            None => {
+                self.speakers.push(print);
+                Some(self.speakers.len() - 1)
+            }
+        }
+    }
+
+    /// Each cluster's index and voiceprint, in the order the speakers were
+    /// first heard.
+    pub fn embeddings(&self) -> impl Iterator<Item = (usize, Vec<f32>)> + '_ {
+        self.speakers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, speaker)| Some((i, speaker.embedding()?)))
+    }
+}
+
+/// Cosine similarity of two embeddings, from -1 to 1.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+struct Analyzer {
+    window: Vec<f32>,
+    /// Triangular mel filters as (first bin, weights)
+    filters: Vec<(usize, Vec<f32>)>,
+}
+
+fn analyzer() -> &'static Analyzer {
+    static ANALYZER: OnceLock<Analyzer> = OnceLock::new();
+    ANALYZER.get_or_init(Analyzer::new)
+}
+
+impl Analyzer {
+    fn new() -> Self {
+        let window = (0..FRAME_LEN)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_LEN as f32).cos())
+            .collect();
+
+        let mel = |hz: f32| 2595.0 * (1.0 + hz / 700.0).log10();
+        let hz = |mel: f32| 700.0 * (10f32.powf(mel / 2595.0) - 1.0);
+        let bin_hz = 16_000.0 / FRAME_LEN as f32;
+        let edges: Vec<f32> = (0..BANDS + 2)
+            .map(|i| {
+                let m = mel(LOW_HZ) + (mel(HIGH_HZ) - mel(LOW_HZ)) * i as f32 / (BANDS + 1) as f32;
+                hz(m) / bin_hz
+            })
+            .collect();
+
+        let filters = edges
+            .windows(3)
+            .map(|edge| {
+                let (low, mid, high) = (edge[0], edge[1], edge[2]);
+                let first = low.ceil() as usize;
+                let last = (high.floor() as usize).min(BINS - 1);
+                let weights = (first..=last)
+                    .map(|bin| {
+                        let bin = bin as f32;
+                        if bin <= mid {
+                            (bin - low) / (mid - low)
+                        } else {
+                            (high - bin) / (high - mid)
+                        }
+                    })
+                    .collect();
+                (first, weights)
+            })
+            .collect();
+
+        Self { window, filters }
+    }
+
+    fn band_energies(&self, frame: &[f32]) -> Vec<f32> {
+        let mut re: Vec<f32> = frame.iter().zip(&self.window).map(|(s, w)| s * w).collect();
+        let mut im = vec![0.0; FRAME_LEN];
+        fft(&mut re, &mut im);
+
+        let power: Vec<f32> = (0..BINS).map(|k| re[k] * re[k] + im[k] * im[k]).collect();
+        self.filters
+            .iter()
+            .map(|(first, weights)| {
+                weights
+                    .iter()
+                    .zip(&power[*first..])
+                    .map(|(w, p)| w * p)
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// In-place iterative radix-2 FFT; `re.len()` must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,252 @@
+//! Memory-mapped WAV input.
+//!
+//! Reading a file through the engine decodes it into one buffer and then
+//! converts that into the f32 samples it runs on, so an hour-long recording
+//! briefly costs twice its size in memory. Here the file is mapped read-only
+//! and its samples are decoded straight from the mapping into the single
+//! `Vec<f32>` the engine takes, with nothing read up front.
+
+use crate::dsp;
+use crate::incremental::SAMPLE_RATE;
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    F32,
+    I16,
+    I24,
+    I32,
+}
+
+impl Encoding {
+    fn bytes(self) -> usize {
+        match self {
+            Encoding::I16 => 2,
+            Encoding::I24 => 3,
+            Encoding::F32 | Encoding::I32 => 4,
+        }
+    }
+
+    fn decode(self, b: &[u8]) -> f32 {
+        match self {
+            Encoding::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            Encoding::I16 => i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0,
+            Encoding::I24 => (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+            Encoding::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        }
+    }
+}
+
+/// A read-only mapping of a whole file.
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(file: &File) -> io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "File is empty"));
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+pub struct Wav {
+    mapping: Mapping,
+    encoding: Encoding,
+    channels: u16,
+    sample_rate: u32,
+    data: Range<usize>,
+}
+
+impl Wav {
+    /// Maps `path` and parses its header. Fails for anything that is not
+    /// 16/24/32-bit integer or 32-bit float PCM.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mapping =
+            Mapping::new(&file).with_context(|| format!("Failed to map {}", path.display()))?;
+        let header = parse_header(mapping.bytes())
+            .with_context(|| format!("{} is not a supported WAV file", path.display()))?;
+
+        Ok(Self {
+            mapping,
+            encoding: header.encoding,
+            channels: header.channels,
+            sample_rate: header.sample_rate,
+            data: header.data,
+        })
+    }
+
+    /// Whether the samples can go to the engine as they are, without
+    /// resampling or downmixing.
+    pub fn is_engine_format(&self) -> bool {
+        self.channels == 1 && self.sample_rate == SAMPLE_RATE
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// `f32`, `s16`, `s24` or `s32`
+    pub fn sample_format(&self) -> &'static str {
+        match self.encoding {
+            Encoding::F32 => "f32",
+            Encoding::I16 => "s16",
+            Encoding::I24 => "s24",
+            Encoding::I32 => "s32",
+        }
+    }
+
+    /// Samples per channel
+    pub fn frames(&self) -> usize {
+        self.data.len() / (self.encoding.bytes() * self.channels as usize)
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        let encoding = self.encoding;
+        self.mapping.bytes()[self.data.clone()]
+            .chunks_exact(encoding.bytes())
+            .map(move |b| encoding.decode(b))
+    }
+
+    pub fn to_vec(&self) -> Vec<f32> {
+        // The common case, converted a vector at a time.
+        if self.encoding == Encoding::I16 {
+            return dsp::pcm16_to_f32(&self.mapping.bytes()[self.data.clone()]);
+        }
+        let mut samples = Vec::with_capacity(self.data.len() / self.encoding.bytes());
+        samples.extend(self.samples());
+        samples
+    }
+
+    pub fn is_silent(&self, peak: f32) -> bool {
+        self.samples().all(|s| s.abs() < peak)
+    }
+}
+
+/// Reads a 16 kHz mono WAV file into memory.
+pub fn read(path: &Path) -> Result<Vec<f32>> {
+    let wav = Wav::open(path)?;
+    if !wav.is_engine_format() {
+        bail!(
+            "{} is {} Hz with {} channel(s); expected {} Hz mono",
+            path.display(),
+            wav.sample_rate,
+            wav.channels,
+            SAMPLE_RATE
+        );
+    }
+    Ok(wav.to_vec())
+}
+
+struct Header {
+    encoding: Encoding,
+    channels: u16,
+    sample_rate: u32,
+    data: Range<usize>,
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        bail!("Missing RIFF/WAVE header");
+    }
+
+    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at =
+        |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    let mut format = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let size = u32_at(at + 4) as usize;
+        let body = at + 8;
+
+        match id {
+            b"fmt " if size >= 16 && body + 16 <= bytes.len() => {
+                let mut tag = u16_at(body);
+                if tag == WAVE_FORMAT_EXTENSIBLE && size >= 26 && body + 26 <= bytes.len() {
+                    // The first two bytes of the sub-format GUID hold the
+                    // actual format tag.
+                    tag = u16_at(body + 24);
+                }
+                let bits = u16_at(body + 14);
+                let encoding = match (tag, bits) {
+                    (WAVE_FORMAT_IEEE_FLOAT, 32) => Encoding::F32,
+                    (WAVE_FORMAT_PCM, 16) => Encoding::I16,
+                    (WAVE_FORMAT_PCM, 24) => Encoding::I24,
+                    (WAVE_FORMAT_PCM, 32) => Encoding::I32,
+                    _ => bail!("Unsupported sample format {} with {} bits", tag, bits),
+                };
+                format = Some((encoding, u16_at(body + 2), u32_at(body + 4)));
+            }
+            b"data" => {
+                let (encoding, channels, sample_rate) =
+                    format.context("data chunk comes before the fmt chunk")?;
+                if channels == 0 {
+                    bail!("Header declares zero channels");
+                }
+                // Recorders that were cut off leave the size unset; take
+                // whatever made it to disk.
+                let end = body.saturating_add(size).min(bytes.len());
+                let frame = encoding.bytes() * channels as usize;
+                let end = body + (end - body) / frame * frame;
+                return Ok(Header {
+                    encoding,
+                    channels,
+                    sample_rate,
+                    data: body..end,
+                });
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length.
+        at = body.saturating_add(size).saturating_add(size & 1);
+    }
+
+    bail!("No data chunk")
+}
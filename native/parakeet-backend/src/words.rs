@@ -0,0 +1,82 @@
+//! Word-level view of a transcript.
+//!
+//! Words carry timings only; confidence and alternatives for the words
+//! Parakeet is unsure of come from `alternatives`.
+
+use crate::Segment;
+
+pub struct Word {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// Splits segments into words.
+///
+/// Parakeet's default token-level segments mark word starts with a leading
+/// space, so a piece without one is glued onto the previous word. Segments
+/// holding several words are split on whitespace and their time span is
+/// shared out by character count.
+pub fn from_segments(segments: &[Segment]) -> Vec<Word> {
+    let mut words: Vec<Word> = Vec::new();
+
+    for segment in segments {
+        let pieces: Vec<&str> = segment.text.split_whitespace().collect();
+        let total_chars: usize = pieces.iter().map(|p| p.chars().count()).sum();
+        if total_chars == 0 {
+            continue;
+        }
+
+        let continues_word = !segment.text.starts_with(char::is_whitespace);
+        let duration = segment.end - segment.start;
+        let mut cursor = segment.start;
+
+        for (i, piece) in pieces.into_iter().enumerate() {
+            let end = cursor + duration * piece.chars().count() as f64 / total_chars as f64;
+
+            if i == 0 && continues_word {
+                if let Some(last) = words.last_mut() {
+                    last.text.push_str(piece);
+                    last.end = end;
+                    cursor = end;
+                    continue;
+                }
+            }
+
+            words.push(Word {
+                start: cursor,
+                end,
+                text: piece.to_string(),
+            });
+            cursor = end;
+        }
+    }
+
+    words
+}
+
+/// Rewrites whole-word, ASCII case-insensitive matches of each vocabulary
+/// entry to the entry's spelling. Entries may span several words.
+pub fn apply_vocabulary(text: &str, vocabulary: &[String]) -> String {
+    let mut text = text.to_string();
+
+    for entry in vocabulary.iter().filter(|e| !e.trim().is_empty()) {
+        // ASCII lowercasing keeps byte offsets identical between the two.
+        let needle = entry.to_ascii_lowercase();
+        let lower = text.to_ascii_lowercase();
+        let mut from = 0;
+
+        while let Some(found) = lower[from..].find(&needle) {
+            let start = from + found;
+            let end = start + needle.len();
+            let bounded = !text[..start].ends_with(char::is_alphanumeric)
+                && !text[end..].starts_with(char::is_alphanumeric);
+            if bounded {
+                text.replace_range(start..end, entry);
+            }
+            from = end;
+        }
+    }
+
+    text
+}
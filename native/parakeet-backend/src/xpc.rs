@@ -0,0 +1,154 @@
+//! XPC service mode.
+//!
+//! When bundled as `ParakeetBackend.xpc` inside the host app (see
+//! `scripts/afterPack.js`), launchd starts the binary on demand and hands us
+//! connections through `xpc_main`. Each message is a dictionary carrying the
+//! same JSON command the stdio server accepts under `request`; the reply
+//! carries the JSON response under `response`. A `transcribe` without a
+//! `path` or `shm` takes its samples from `audio`, raw little-endian f32
+//! data, so a sandboxed service never needs to read the host's files.
+//!
+//! launchd passes an XPC service no arguments, so the backend runs with the
+//! defaults unless started by hand with `--xpc` and other flags.
+
+use crate::{
+    encode_response, framing, new_request_id, process_command, request_id, Backend, Command,
+    Response,
+};
+use anyhow::Result;
+use block2::{Block, RcBlock};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr::addr_of;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Bundle identifier of the XPC service, as set in `xpc/Info.plist`.
+pub const SERVICE_NAME: &str = "com.whispermac.parakeet-backend";
+
+const REQUEST_KEY: &CStr = c"request";
+const RESPONSE_KEY: &CStr = c"response";
+const AUDIO_KEY: &CStr = c"audio";
+
+type XpcObject = *mut c_void;
+type XpcConnection = *mut c_void;
+
+extern "C" {
+    #[allow(non_upper_case_globals)]
+    static _xpc_type_dictionary: c_void;
+
+    fn xpc_main(handler: extern "C" fn(XpcConnection)) -> !;
+    fn xpc_connection_set_event_handler(
+        connection: XpcConnection,
+        handler: &Block<dyn Fn(XpcObject)>,
+    );
+    fn xpc_connection_resume(connection: XpcConnection);
+    fn xpc_connection_send_message(connection: XpcConnection, message: XpcObject);
+    fn xpc_get_type(object: XpcObject) -> *const c_void;
+    fn xpc_dictionary_get_string(xdict: XpcObject, key: *const c_char) -> *const c_char;
+    fn xpc_dictionary_get_data(
+        xdict: XpcObject,
+        key: *const c_char,
+        length: *mut usize,
+    ) -> *const c_void;
+    fn xpc_dictionary_create_reply(original: XpcObject) -> XpcObject;
+    fn xpc_dictionary_set_string(xdict: XpcObject, key: *const c_char, string: *const c_char);
+    fn xpc_release(object: XpcObject);
+}
+
+// Connections are served on their own dispatch queues but share one engine.
+static BACKEND: OnceLock<Mutex<Backend>> = OnceLock::new();
+
+/// Whether launchd started us as the bundled XPC service.
+pub fn launched_as_service() -> bool {
+    std::env::var("XPC_SERVICE_NAME").is_ok_and(|name| name == SERVICE_NAME)
+}
+
+pub fn run(backend: Backend) -> Result<()> {
+    BACKEND.get_or_init(|| Mutex::new(backend));
+    unsafe { xpc_main(handle_connection) }
+}
+
+extern "C" fn handle_connection(connection: XpcConnection) {
+    let handler = RcBlock::new(move |event: XpcObject| unsafe { handle_event(connection, event) });
+
+    unsafe {
+        xpc_connection_set_event_handler(connection, &handler);
+        xpc_connection_resume(connection);
+    }
+}
+
+unsafe fn handle_event(connection: XpcConnection, event: XpcObject) {
+    // Anything other than a dictionary is a connection error (peer went
+    // away, service asked to terminate); there is nobody to reply to.
+    if xpc_get_type(event) != addr_of!(_xpc_type_dictionary) {
+        return;
+    }
+
+    let request = xpc_dictionary_get_string(event, REQUEST_KEY.as_ptr());
+    let (request_id, response) = if request.is_null() {
+        (
+            new_request_id(),
+            Response::Error {
+                message: "Missing 'request' key in XPC message".to_string(),
+            },
+        )
+    } else {
+        let line = CStr::from_ptr(request).to_string_lossy();
+        let request_id = request_id(line.as_bytes());
+        let command = serde_json::from_str::<Command>(&line)
+            .map_err(|e| format!("Invalid JSON: {}", e))
+            .and_then(|mut command| {
+                read_audio(event, &mut command)
+                    .map_err(|e| format!("Invalid audio: {}", e))
+                    .map(|()| command)
+            });
+        let response = match command {
+            Ok(command) => match BACKEND.get() {
+                Some(backend) => {
+                    let mut backend = backend.lock().unwrap_or_else(PoisonError::into_inner);
+                    process_command(&mut backend, &request_id, command)
+                }
+                None => Response::Error {
+                    message: "Parakeet backend not initialized".to_string(),
+                },
+            },
+            Err(message) => Response::Error { message },
+        };
+        (request_id, response)
+    };
+
+    let reply = xpc_dictionary_create_reply(event);
+    if reply.is_null() {
+        return;
+    }
+
+    // serde_json escapes NUL, so the encoded response is always a valid C string.
+    let json = encode_response(&request_id, response)
+        .ok()
+        .and_then(|json| CString::new(json).ok())
+        .unwrap_or_else(|| {
+            c"{\"status\":\"error\",\"message\":\"Failed to encode response\"}".to_owned()
+        });
+
+    xpc_dictionary_set_string(reply, RESPONSE_KEY.as_ptr(), json.as_ptr());
+    xpc_connection_send_message(connection, reply);
+    xpc_release(reply);
+}
+
+/// Fills in a file-less `transcribe`'s samples from the message's `audio`.
+unsafe fn read_audio(event: XpcObject, command: &mut Command) -> std::io::Result<()> {
+    if let Command::Transcribe {
+        path: None,
+        shm: None,
+        samples,
+        ..
+    } = command
+    {
+        let mut len = 0;
+        let data = xpc_dictionary_get_data(event, AUDIO_KEY.as_ptr(), &mut len);
+        if !data.is_null() {
+            let bytes = std::slice::from_raw_parts(data.cast::<u8>(), len);
+            *samples = Some(framing::decode_samples(bytes)?);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use transcribe_rs::{engines::parakeet::ParakeetEngine, TranscriptionEngine, TranscriptionResult};
+
+// One engine per process, shared by every call. Tasks run on the libuv
+// threadpool, so concurrent transcriptions are serialized on this lock.
+static ENGINE: Mutex<Option<ParakeetEngine>> = Mutex::new(None);
+
+#[napi(object)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[napi(object)]
+pub struct TranscriptionOutput {
+    pub text: String,
+    pub segments: Vec<Segment>,
+    pub processing_time_ms: f64,
+}
+
+pub struct LoadModelTask {
+    path: PathBuf,
+}
+
+impl Task for LoadModelTask {
+    type Output = ();
+    type JsValue = JsUndefined;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let mut engine = ParakeetEngine::new();
+        engine
+            .load_model(&self.path)
+            .map_err(|e| Error::from_reason(format!("Failed to load model: {}", e)))?;
+
+        *lock_engine()? = Some(engine);
+        Ok(())
+    }
+
+    fn resolve(&mut self, env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+        env.get_undefined()
+    }
+}
+
+enum TranscribeInput {
+    File(PathBuf),
+    Samples(Vec<f32>),
+}
+
+pub struct TranscribeTask {
+    input: TranscribeInput,
+}
+
+impl Task for TranscribeTask {
+    type Output = TranscriptionOutput;
+    type JsValue = TranscriptionOutput;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let start_time = Instant::now();
+        let mut guard = lock_engine()?;
+        let engine = guard
+            .as_mut()
+            .ok_or_else(|| Error::from_reason("No model loaded; call loadModel() first"))?;
+
+        let result = match &mut self.input {
+            TranscribeInput::File(path) => engine.transcribe_file(path.as_path(), None),
+            TranscribeInput::Samples(samples) => {
+                engine.transcribe_samples(std::mem::take(samples), None)
+            }
+        }
+        .map_err(|e| Error::from_reason(format!("Transcription failed: {}", e)))?;
+
+        Ok(to_output(result, start_time.elapsed()))
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+/// Loads the model directory, replacing any previously loaded engine.
+#[napi]
+pub fn load_model(path: String) -> AsyncTask<LoadModelTask> {
+    AsyncTask::new(LoadModelTask {
+        path: PathBuf::from(path),
+    })
+}
+
+/// Transcribes a 16 kHz mono WAV file.
+#[napi]
+pub fn transcribe_file(path: String) -> AsyncTask<TranscribeTask> {
+    AsyncTask::new(TranscribeTask {
+        input: TranscribeInput::File(PathBuf::from(path)),
+    })
+}
+
+/// Transcribes raw 16 kHz mono samples. The buffer is copied before the
+/// task is queued, so the caller may reuse it immediately.
+#[napi]
+pub fn transcribe_buffer(samples: Float32Array) -> AsyncTask<TranscribeTask> {
+    AsyncTask::new(TranscribeTask {
+        input: TranscribeInput::Samples(samples.to_vec()),
+    })
+}
+
+fn lock_engine() -> Result<std::sync::MutexGuard<'static, Option<ParakeetEngine>>> {
+    ENGINE
+        .lock()
+        .map_err(|_| Error::from_reason("Parakeet engine lock poisoned"))
+}
+
+fn to_output(result: TranscriptionResult, duration: Duration) -> TranscriptionOutput {
+    let segments = result
+        .segments
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| Segment {
+            start: s.start as f64,
+            end: s.end as f64,
+            text: s.text,
+        })
+        .collect();
+
+    TranscriptionOutput {
+        text: result.text,
+        segments,
+        processing_time_ms: duration.as_millis() as f64,
+    }
+}
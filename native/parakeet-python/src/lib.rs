@@ -0,0 +1,96 @@
+use numpy::PyReadonlyArray1;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use transcribe_rs::{engines::parakeet::ParakeetEngine, TranscriptionEngine, TranscriptionResult};
+
+/// A loaded Parakeet model, configured exactly as the app's backend loads it.
+#[pyclass(module = "parakeet_backend")]
+struct Engine {
+    inner: ParakeetEngine,
+}
+
+#[pymethods]
+impl Engine {
+    #[new]
+    fn new(py: Python<'_>, model_path: PathBuf) -> PyResult<Self> {
+        let inner = py
+            .allow_threads(|| {
+                let mut engine = ParakeetEngine::new();
+                engine
+                    .load_model(&model_path)
+                    .map(|_| engine)
+                    .map_err(|e| e.to_string())
+            })
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to load model: {}", e)))?;
+
+        Ok(Self { inner })
+    }
+
+    /// Transcribes a 16 kHz mono WAV file.
+    fn transcribe_file<'py>(
+        &mut self,
+        py: Python<'py>,
+        path: PathBuf,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let start_time = Instant::now();
+        let result = py
+            .allow_threads(|| {
+                self.inner
+                    .transcribe_file(&path, None)
+                    .map_err(|e| e.to_string())
+            })
+            .map_err(|e| PyRuntimeError::new_err(format!("Transcription failed: {}", e)))?;
+
+        to_dict(py, result, start_time.elapsed())
+    }
+
+    /// Transcribes a 1-D float32 array of 16 kHz mono samples.
+    fn transcribe_array<'py>(
+        &mut self,
+        py: Python<'py>,
+        samples: PyReadonlyArray1<'py, f32>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let samples = samples.as_array().to_vec();
+        let start_time = Instant::now();
+        let result = py
+            .allow_threads(|| {
+                self.inner
+                    .transcribe_samples(samples, None)
+                    .map_err(|e| e.to_string())
+            })
+            .map_err(|e| PyRuntimeError::new_err(format!("Transcription failed: {}", e)))?;
+
+        to_dict(py, result, start_time.elapsed())
+    }
+}
+
+/// Builds the same shape as the backend's JSON output.
+fn to_dict(
+    py: Python<'_>,
+    result: TranscriptionResult,
+    duration: Duration,
+) -> PyResult<Bound<'_, PyDict>> {
+    let segments = PyList::empty_bound(py);
+    for s in result.segments.unwrap_or_default() {
+        let segment = PyDict::new_bound(py);
+        segment.set_item("start", s.start as f64)?;
+        segment.set_item("end", s.end as f64)?;
+        segment.set_item("text", s.text)?;
+        segments.append(segment)?;
+    }
+
+    let output = PyDict::new_bound(py);
+    output.set_item("text", result.text)?;
+    output.set_item("segments", segments)?;
+    output.set_item("processing_time_ms", duration.as_millis() as u64)?;
+    Ok(output)
+}
+
+#[pymodule]
+fn parakeet_backend(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Engine>()?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+// Client for the Parakeet backend's XPC service (ParakeetBackend.xpc,
+// bundled into the app by scripts/afterPack.js).
+//
+// request(json, audio?) sends one JSON command, with optional 16 kHz mono
+// samples for a file-less transcribe, and resolves with the JSON response.
+// Requests block a libuv worker thread until the service replies; launchd
+// starts the service on the first one.
+
+#include <napi.h>
+#include <xpc/xpc.h>
+#include <mutex>
+#include <string>
+#include <vector>
+
+static const char *kServiceName = "com.whispermac.parakeet-backend";
+
+static std::mutex g_lock;
+static xpc_connection_t g_connection = nil;
+
+static xpc_connection_t Connection() {
+    std::lock_guard<std::mutex> guard(g_lock);
+    if (g_connection != nil) {
+        return g_connection;
+    }
+
+    xpc_connection_t connection = xpc_connection_create(kServiceName, NULL);
+    // Not captured strongly, which would keep the connection alive forever.
+    void *identity = (__bridge void *)connection;
+    xpc_connection_set_event_handler(connection, ^(xpc_object_t event) {
+        // Replies come back through send_message_with_reply_sync, so only
+        // errors land here. An interrupted connection reconnects on its
+        // own; an invalid one is replaced on the next request.
+        if (event == XPC_ERROR_CONNECTION_INVALID) {
+            std::lock_guard<std::mutex> guard(g_lock);
+            if ((__bridge void *)g_connection == identity) {
+                g_connection = nil;
+            }
+        }
+    });
+    xpc_connection_resume(connection);
+    g_connection = connection;
+    return connection;
+}
+
+class RequestWorker : public Napi::AsyncWorker {
+public:
+    RequestWorker(Napi::Env env, std::string request, std::vector<float> audio, bool hasAudio)
+        : Napi::AsyncWorker(env),
+          m_deferred(Napi::Promise::Deferred::New(env)),
+          m_request(std::move(request)),
+          m_audio(std::move(audio)),
+          m_hasAudio(hasAudio) {}
+
+    Napi::Promise Promise() { return m_deferred.Promise(); }
+
+protected:
+    void Execute() override {
+        xpc_object_t message = xpc_dictionary_create(NULL, NULL, 0);
+        xpc_dictionary_set_string(message, "request", m_request.c_str());
+        if (m_hasAudio) {
+            xpc_dictionary_set_data(message, "audio", m_audio.data(),
+                                    m_audio.size() * sizeof(float));
+        }
+
+        xpc_object_t reply = xpc_connection_send_message_with_reply_sync(Connection(), message);
+        if (xpc_get_type(reply) == XPC_TYPE_ERROR) {
+            const char *description = xpc_dictionary_get_string(reply, XPC_ERROR_KEY_DESCRIPTION);
+            SetError(std::string("Parakeet XPC service unavailable: ") +
+                     (description != NULL ? description : "connection failed"));
+            return;
+        }
+
+        const char *response = xpc_dictionary_get_string(reply, "response");
+        if (response == NULL) {
+            SetError("Parakeet XPC service replied without a response");
+            return;
+        }
+        m_response = response;
+    }
+
+    void OnOK() override { m_deferred.Resolve(Napi::String::New(Env(), m_response)); }
+
+    void OnError(const Napi::Error &error) override { m_deferred.Reject(error.Value()); }
+
+private:
+    Napi::Promise::Deferred m_deferred;
+    std::string m_request;
+    std::vector<float> m_audio;
+    bool m_hasAudio;
+    std::string m_response;
+};
+
+static Napi::Value Request(const Napi::CallbackInfo &info) {
+    Napi::Env env = info.Env();
+    if (info.Length() < 1 || !info[0].IsString()) {
+        throw Napi::TypeError::New(env, "request(json: string, audio?: Float32Array)");
+    }
+
+    std::vector<float> audio;
+    bool hasAudio = false;
+    if (info.Length() > 1 && !info[1].IsUndefined()) {
+        if (!info[1].IsTypedArray() ||
+            info[1].As<Napi::TypedArray>().TypedArrayType() != napi_float32_array) {
+            throw Napi::TypeError::New(env, "audio must be a Float32Array");
+        }
+        Napi::Float32Array samples = info[1].As<Napi::Float32Array>();
+        audio.assign(samples.Data(), samples.Data() + samples.ElementLength());
+        hasAudio = true;
+    }
+
+    RequestWorker *worker =
+        new RequestWorker(env, info[0].As<Napi::String>().Utf8Value(), std::move(audio), hasAudio);
+    Napi::Promise promise = worker->Promise();
+    worker->Queue();
+    return promise;
+}
+
+Napi::Object Init(Napi::Env env, Napi::Object exports) {
+    exports.Set("request", Napi::Function::New(env, Request));
+    return exports;
+}
+
+NODE_API_MODULE(parakeet_xpc, Init)
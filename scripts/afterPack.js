@@ -1,5 +1,8 @@
 const fs = require('fs');
 const path = require('path');
+const { execFileSync } = require('child_process');
+
+const XPC_SOURCE_DIR = path.join(__dirname, '..', 'native', 'parakeet-backend', 'xpc');
 
 function rmSync(dir) {
   if (fs.existsSync(dir)) {
@@ -26,6 +29,37 @@ function removeFilesMatching(dir, pattern) {
   walk(dir);
 }
 
+// Wraps the bundled parakeet-backend binary as Contents/XPCServices/
+// ParakeetBackend.xpc, which launchd starts when the app connects to
+// com.whispermac.parakeet-backend.
+function bundleParakeetXpc(contentsDir) {
+  const binary = path.join(contentsDir, 'Resources', 'parakeet-backend');
+  if (!fs.existsSync(binary)) {
+    console.log('[afterPack] parakeet-backend not found; skipping XPC service');
+    return;
+  }
+
+  const bundleDir = path.join(contentsDir, 'XPCServices', 'ParakeetBackend.xpc');
+  const macosDir = path.join(bundleDir, 'Contents', 'MacOS');
+  rmSync(bundleDir);
+  fs.mkdirSync(macosDir, { recursive: true });
+  fs.copyFileSync(path.join(XPC_SOURCE_DIR, 'Info.plist'), path.join(bundleDir, 'Contents', 'Info.plist'));
+  fs.copyFileSync(binary, path.join(macosDir, 'parakeet-backend'));
+  fs.chmodSync(path.join(macosDir, 'parakeet-backend'), 0o755);
+
+  // The app itself is not signed (identity: null), so sign the service
+  // ad hoc to seal the bundle and apply its sandbox entitlements.
+  if (process.platform === 'darwin') {
+    execFileSync('codesign', [
+      '--force',
+      '--sign', '-',
+      '--entitlements', path.join(XPC_SOURCE_DIR, 'parakeet-backend.entitlements'),
+      bundleDir,
+    ], { stdio: 'inherit' });
+  }
+  console.log(`  Bundled: ${bundleDir}`);
+}
+
 exports.default = async function(context) {
   const { appOutDir, arch } = context;
   const archName = arch === 1 ? 'x64' : 'arm64';
@@ -45,6 +79,9 @@ exports.default = async function(context) {
   
   console.log('[afterPack] Removing source maps from asar.unpacked...');
   removeFilesMatching(unpackedDir, /\.map$/);
+
+  console.log('[afterPack] Bundling the Parakeet XPC service...');
+  bundleParakeetXpc(path.join(appOutDir, 'WhisperMac.app', 'Contents'));
   
   console.log('[afterPack] Cleanup complete!\n');
 };
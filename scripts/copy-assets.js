@@ -30,6 +30,18 @@ const audioCaptureBuiltPath = path.join(
 );
 const audioCaptureDistPath = path.join(macInputDistDir, "audio_capture.node");
 
+const parakeetNodeBuiltPath = path.join(
+  __dirname,
+  "../native/parakeet-node/target/release/parakeet_node.node",
+);
+const parakeetNodeDistPath = path.join(macInputDistDir, "parakeet_node.node");
+
+const parakeetXpcBuiltPath = path.join(
+  __dirname,
+  "../native/parakeet-xpc/build/Release/parakeet_xpc.node",
+);
+const parakeetXpcDistPath = path.join(macInputDistDir, "parakeet_xpc.node");
+
 const EXTENSIONS = [
   ".html",
   ".js",
@@ -403,6 +415,34 @@ async function main() {
       console.log("audio_capture.node not found; skipping native audio capture addon copy");
     }
 
+    // Copy native parakeet_node.node if it exists
+    if (fs.existsSync(parakeetNodeBuiltPath)) {
+      await fsPromises.mkdir(macInputDistDir, { recursive: true });
+      await fsPromises.copyFile(parakeetNodeBuiltPath, parakeetNodeDistPath);
+      console.log(
+        `Copied native parakeet addon to ${path.relative(
+          path.join(__dirname, ".."),
+          parakeetNodeDistPath,
+        )}`,
+      );
+    } else {
+      console.log("parakeet_node.node not found; skipping native parakeet addon copy");
+    }
+
+    // Copy native parakeet_xpc.node if it exists
+    if (fs.existsSync(parakeetXpcBuiltPath)) {
+      await fsPromises.mkdir(macInputDistDir, { recursive: true });
+      await fsPromises.copyFile(parakeetXpcBuiltPath, parakeetXpcDistPath);
+      console.log(
+        `Copied native parakeet XPC client to ${path.relative(
+          path.join(__dirname, ".."),
+          parakeetXpcDistPath,
+        )}`,
+      );
+    } else {
+      console.log("parakeet_xpc.node not found; skipping native parakeet XPC client copy");
+    }
+
     // Copy assets (depends on discovering files, so run after other operations)
     await copyAssetsParallel(assetsSrcDir, assetsDistDir);
 
@@ -1,4 +1,4 @@
-import { writeFileSync } from "fs";
+import { readFileSync, writeFileSync } from "fs";
 import { join } from "path";
 
 /**
@@ -85,4 +85,67 @@ export class WavProcessor {
 
     return buffer;
   }
+
+  /**
+   * Read a mono WAV file of 16-bit PCM or 32-bit float samples at the given
+   * rate. Returns null for any other layout.
+   */
+  static readMonoWav(
+    filePath: string,
+    sampleRate: number = 16000,
+  ): Float32Array | null {
+    const bytes = readFileSync(filePath);
+    const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+    if (
+      bytes.byteLength < 12 ||
+      view.getUint32(0, false) !== 0x52494646 || // "RIFF"
+      view.getUint32(8, false) !== 0x57415645 // "WAVE"
+    ) {
+      return null;
+    }
+
+    let format: { tag: number; bits: number } | null = null;
+    let offset = 12;
+    while (offset + 8 <= bytes.byteLength) {
+      const id = view.getUint32(offset, false);
+      const size = view.getUint32(offset + 4, true);
+      const body = offset + 8;
+
+      if (id === 0x666d7420 && body + 16 <= bytes.byteLength) {
+        // "fmt "
+        if (
+          view.getUint16(body + 2, true) !== 1 ||
+          view.getUint32(body + 4, true) !== sampleRate
+        ) {
+          return null;
+        }
+        format = {
+          tag: view.getUint16(body, true),
+          bits: view.getUint16(body + 14, true),
+        };
+      } else if (id === 0x64617461 && format) {
+        // "data"; recorders that were cut off leave the size unset
+        const end = Math.min(body + size, bytes.byteLength);
+        if (format.tag === 1 && format.bits === 16) {
+          const samples = new Float32Array(Math.floor((end - body) / 2));
+          for (let i = 0; i < samples.length; i++) {
+            samples[i] = view.getInt16(body + i * 2, true) / 32768;
+          }
+          return samples;
+        }
+        if (format.tag === 3 && format.bits === 32) {
+          const samples = new Float32Array(Math.floor((end - body) / 4));
+          for (let i = 0; i < samples.length; i++) {
+            samples[i] = view.getFloat32(body + i * 4, true);
+          }
+          return samples;
+        }
+        return null;
+      }
+
+      // Chunks are padded to an even length
+      offset = body + size + (size & 1);
+    }
+    return null;
+  }
 }
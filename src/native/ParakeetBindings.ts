@@ -0,0 +1,53 @@
+// Runtime-safe loader for the native Parakeet addon (native/parakeet-node)
+// Handles loading from different paths for dev/prod environments
+
+// eslint-disable-next-line @typescript-eslint/no-explicit-any
+let nativeBinding: any = null;
+
+try {
+  // Primary: next to compiled file (e.g., dist/native/parakeet_node.node)
+  // eslint-disable-next-line @typescript-eslint/no-var-requires
+  nativeBinding = require("./parakeet_node.node");
+} catch (_) {
+  try {
+    // Dev: built via cargo under native/parakeet-node
+    // eslint-disable-next-line @typescript-eslint/no-var-requires
+    nativeBinding = require("../../native/parakeet-node/target/release/parakeet_node.node");
+  } catch (err) {
+    console.warn(
+      "parakeet_node native module could not be loaded. Falling back to the parakeet-backend subprocess.",
+      err,
+    );
+    nativeBinding = null;
+  }
+}
+
+export interface ParakeetNativeSegment {
+  start: number;
+  end: number;
+  text: string;
+}
+
+export interface ParakeetNativeResult {
+  text: string;
+  segments: ParakeetNativeSegment[];
+  processingTimeMs: number;
+}
+
+export interface ParakeetNativeBinding {
+  loadModel: (path: string) => Promise<void>;
+  transcribeFile: (path: string) => Promise<ParakeetNativeResult>;
+  // Samples must be 16 kHz mono
+  transcribeBuffer: (samples: Float32Array) => Promise<ParakeetNativeResult>;
+}
+
+export function isParakeetNativeAvailable(): boolean {
+  return nativeBinding !== null;
+}
+
+export function getParakeetNative(): ParakeetNativeBinding {
+  if (!nativeBinding) {
+    throw new Error("parakeet_node native module is not available");
+  }
+  return nativeBinding as ParakeetNativeBinding;
+}
@@ -0,0 +1,40 @@
+// Runtime-safe loader for the Parakeet XPC client (native/parakeet-xpc)
+// Handles loading from different paths for dev/prod environments
+
+// eslint-disable-next-line @typescript-eslint/no-explicit-any
+let nativeBinding: any = null;
+
+try {
+  // Primary: next to compiled file (e.g., dist/native/parakeet_xpc.node)
+  // eslint-disable-next-line @typescript-eslint/no-var-requires
+  nativeBinding = require("./parakeet_xpc.node");
+} catch (_) {
+  try {
+    // Dev: built via node-gyp under native/parakeet-xpc
+    // eslint-disable-next-line @typescript-eslint/no-var-requires
+    nativeBinding = require("../../native/parakeet-xpc/build/Release/parakeet_xpc.node");
+  } catch (err) {
+    console.warn(
+      "parakeet_xpc native module could not be loaded. The Parakeet XPC service will not be used.",
+      err,
+    );
+    nativeBinding = null;
+  }
+}
+
+export interface ParakeetXpcBinding {
+  // Sends one backend command as JSON and resolves with the JSON response.
+  // Samples (16 kHz mono) go along for a transcribe without a path.
+  request: (json: string, audio?: Float32Array) => Promise<string>;
+}
+
+export function isParakeetXpcAvailable(): boolean {
+  return nativeBinding !== null;
+}
+
+export function getParakeetXpc(): ParakeetXpcBinding {
+  if (!nativeBinding) {
+    throw new Error("parakeet_xpc native module is not available");
+  }
+  return nativeBinding as ParakeetXpcBinding;
+}
@@ -26,9 +26,35 @@ import {
 } from "./TranscriptionPlugin";
 import { WavProcessor } from "../helpers/WavProcessor";
 import { FileSystemService } from "../services/FileSystemService";
+import {
+  getParakeetNative,
+  isParakeetNativeAvailable,
+  ParakeetNativeResult,
+} from "../native/ParakeetBindings";
+import {
+  getParakeetXpc,
+  isParakeetXpcAvailable,
+} from "../native/ParakeetXpcBindings";
+
+// Must match PROTOCOL_VERSION and SCHEMA_VERSION in the Rust backend.
+const PARAKEET_PROTOCOL_VERSION = 1;
+const PARAKEET_SCHEMA_VERSION = 1;
+
+// The addon's result in the shape the server's `transcribe` answers with.
+function fromNativeResult(result: ParakeetNativeResult): any {
+  return {
+    status: result.text.trim().length === 0 ? "no_speech" : "ok",
+    text: result.text,
+    segments: result.segments,
+    processing_time_ms: result.processingTimeMs,
+  };
+}
 
 /**
- * Parakeet transcription plugin using custom Rust backend in server mode
+ * Parakeet transcription plugin using the Rust engine in-process through the
+ * parakeet_node addon, or the custom Rust backend in server mode when the
+ * addon can't be loaded. With the "xpc" process option the backend runs as
+ * the bundled ParakeetBackend.xpc service instead.
  */
 export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
   readonly name = "parakeet";
@@ -47,12 +73,18 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
   private isCurrentlyTranscribing = false;
   private isWindowVisible = false;
 
+  // Model the addon's engine holds, when running in-process
+  private nativeModelPath: string | null = null;
+
   private serverProcess: ChildProcess | null = null;
   private serverReadline: createInterface.Interface | null = null;
-  private requestQueue: Array<{
-    resolve: (val: any) => void;
-    reject: (err: any) => void;
-  }> = [];
+  private pendingRequests = new Map<
+    string,
+    {
+      resolve: (val: any) => void;
+      reject: (err: any) => void;
+    }
+  >();
   private isServerReady = false;
 
   private shutdownTimeout: NodeJS.Timeout | null = null;
@@ -107,8 +139,7 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
   }
 
   async isAvailable(): Promise<boolean> {
-    // We can just check if binary exists for now
-    return existsSync(this.binaryPath);
+    return isParakeetNativeAvailable() || existsSync(this.binaryPath);
   }
 
   private async ensureServerStarted(): Promise<void> {
@@ -196,8 +227,9 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
 
     try {
       const response = JSON.parse(line);
-      const request = this.requestQueue.shift();
+      const request = this.pendingRequests.get(response.request_id);
       if (request) {
+        this.pendingRequests.delete(response.request_id);
         if (response.status === "ok") {
           request.resolve(response.data);
         } else {
@@ -215,26 +247,53 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
   }
 
   private rejectAllPending(error: Error) {
-    while (this.requestQueue.length > 0) {
-      const req = this.requestQueue.shift();
-      req?.reject(error);
+    const pending = [...this.pendingRequests.values()];
+    this.pendingRequests.clear();
+    for (const req of pending) {
+      req.reject(error);
     }
   }
 
+  // Whether requests go to the XPC service: chosen, and its client loaded.
+  private usesXpc(): boolean {
+    return this.options.process === "xpc" && isParakeetXpcAvailable();
+  }
+
+  private async sendXpcRequest(
+    command: any,
+    audio?: Float32Array,
+  ): Promise<any> {
+    const line = await getParakeetXpc().request(
+      JSON.stringify({ ...command, request_id: uuidv4() }),
+      audio,
+    );
+    const response = JSON.parse(line);
+    if (response.status !== "ok") {
+      throw new Error(response.message || "Unknown server error");
+    }
+    return response.data;
+  }
+
   private async sendRequest(command: any): Promise<any> {
+    if (this.usesXpc()) {
+      return this.sendXpcRequest(command);
+    }
+
     // Ensure server is physically running (this should be fast if already running)
     // Note: ensureServerStarted handles the checking of existing process
     if (!this.serverProcess) {
       throw new Error("Server process not active");
     }
 
+    const requestId = uuidv4();
     return new Promise((resolve, reject) => {
-      this.requestQueue.push({ resolve, reject });
+      this.pendingRequests.set(requestId, { resolve, reject });
       try {
-        const cmdString = JSON.stringify(command) + "\n";
+        const cmdString =
+          JSON.stringify({ ...command, request_id: requestId }) + "\n";
         this.serverProcess?.stdin?.write(cmdString);
       } catch (e) {
-        const req = this.requestQueue.pop(); // Remove the one we just added
+        this.pendingRequests.delete(requestId);
         reject(e);
       }
     });
@@ -264,16 +323,50 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
     this.rejectAllPending(new Error("Server killed"));
   }
 
+  // Fails fast when the bundled backend speaks another protocol version
+  // than this app, instead of mid-transcription.
+  private async handshake(): Promise<void> {
+    const info = await this.sendRequest({
+      command: "hello",
+      protocol_version: PARAKEET_PROTOCOL_VERSION,
+    });
+    if (info?.schema_version !== PARAKEET_SCHEMA_VERSION) {
+      throw new Error(
+        `Parakeet backend ${info?.backend_version} emits schema version ${info?.schema_version}, expected ${PARAKEET_SCHEMA_VERSION}`,
+      );
+    }
+    console.log(
+      `[parakeet] Connected to backend ${info.backend_version} (protocol ${info.protocol_version})`,
+    );
+  }
+
   // Background initialization to prevent blocking the UI
   private async initializeBackend(
     onProgress?: (p: TranscriptionSetupProgress) => void,
   ) {
     try {
+      if (this.usesXpc()) {
+        await this.handshake();
+        onProgress?.({
+          status: "starting",
+          message: "Loading model into memory...",
+        });
+        await this.loadXpcModel();
+        onProgress?.({ status: "complete", message: "Parakeet backend ready" });
+        return;
+      }
+
+      if (isParakeetNativeAvailable()) {
+        await this.loadNativeModel(onProgress);
+        return;
+      }
+
       const needsModelLoad = !this.serverProcess; // If no process (or killed), we need to load model.
 
       await this.ensureServerStarted();
 
       if (needsModelLoad) {
+        await this.handshake();
         onProgress?.({
           status: "starting",
           message: "Loading model into memory...",
@@ -293,6 +386,65 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
     }
   }
 
+  private async loadNativeModel(
+    onProgress?: (p: TranscriptionSetupProgress) => void,
+  ): Promise<void> {
+    const modelPath = this.resolveModelPath();
+    if (this.nativeModelPath === modelPath) {
+      return;
+    }
+
+    onProgress?.({
+      status: "starting",
+      message: "Loading model into memory...",
+    });
+    await getParakeetNative().loadModel(modelPath);
+    this.modelPath = modelPath;
+    this.nativeModelPath = modelPath;
+    onProgress?.({ status: "complete", message: "Parakeet backend ready" });
+  }
+
+  // launchd may have stopped the service since the last request, taking the
+  // model with it; loading the model it already has is a no-op.
+  private async loadXpcModel(): Promise<void> {
+    this.modelPath = this.resolveModelPath();
+    await this.sendXpcRequest({ command: "load_model", path: this.modelPath });
+  }
+
+  // Through the XPC service or in-process when either is in use; otherwise
+  // through a temporary WAV file handed to the server.
+  private async transcribeSamples(audioData: Float32Array): Promise<any> {
+    if (this.usesXpc()) {
+      await this.loadXpcModel();
+      return this.sendXpcRequest(
+        { command: "transcribe", options: {} },
+        audioData,
+      );
+    }
+
+    if (isParakeetNativeAvailable()) {
+      return fromNativeResult(
+        await getParakeetNative().transcribeBuffer(audioData),
+      );
+    }
+
+    const tempAudioPath = await this.saveAudioAsWav(audioData);
+    try {
+      return await this.sendRequest({
+        command: "transcribe",
+        path: tempAudioPath,
+        options: {},
+      });
+    } finally {
+      // The server has read the file by the time it answers.
+      try {
+        unlinkSync(tempAudioPath);
+      } catch (err) {
+        console.warn("[parakeet] Failed to delete temp audio file:", err);
+      }
+    }
+  }
+
   async startTranscription(
     onUpdate: (update: SegmentUpdate) => void,
     onProgress?: (progress: TranscriptionSetupProgress) => void,
@@ -347,11 +499,8 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
       return;
     }
 
-    let tempAudioPath: string | null = null;
     try {
       this.isCurrentlyTranscribing = true;
-      tempAudioPath = await this.saveAudioAsWav(audioData);
-
       const inProgressSegment: InProgressSegment = {
         id: uuidv4(),
         type: "inprogress",
@@ -370,17 +519,13 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
         await this.readyPromise;
       }
 
-      // Send transcribe command
-      const result = await this.sendRequest({
-        command: "transcribe",
-        path: tempAudioPath,
-        options: {},
-      });
+      const result = await this.transcribeSamples(audioData);
 
       const completedSegment: TranscribedSegment = {
         id: uuidv4(),
         type: "transcribed",
-        text: result.text,
+        text:
+          result.status === "no_speech" ? "[No speech detected]" : result.text,
         completed: true,
         timestamp: Date.now(),
         start: result.segments?.[0]?.start,
@@ -413,17 +558,6 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
       }
     } finally {
       this.isCurrentlyTranscribing = false;
-      // Should properly delete temp files, or let the OS handle it if we are confident.
-      // Using a queue ensures we don't delete before server reads it?
-      // Actually, server reads it during 'transcribe' call, which awaits until done.
-      // So it is safe to delete here.
-      if (tempAudioPath) {
-        try {
-          unlinkSync(tempAudioPath);
-        } catch (err) {
-          console.warn("[parakeet] Failed to delete temp audio file:", err);
-        }
-      }
     }
   }
 
@@ -433,6 +567,32 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
       await this.readyPromise;
     }
 
+    if (this.usesXpc()) {
+      // The service may be sandboxed away from the file, so 16 kHz mono WAV
+      // goes over as samples; anything else is left for it to open.
+      const samples = WavProcessor.readMonoWav(filePath);
+      await this.loadXpcModel();
+      const result = samples
+        ? await this.sendXpcRequest(
+            { command: "transcribe", options: {} },
+            samples,
+          )
+        : await this.sendXpcRequest({ command: "transcribe", path: filePath });
+      return result.status === "no_speech"
+        ? "[No speech detected]"
+        : result.text;
+    }
+
+    if (isParakeetNativeAvailable()) {
+      await this.loadNativeModel();
+      const result = fromNativeResult(
+        await getParakeetNative().transcribeFile(filePath),
+      );
+      return result.status === "no_speech"
+        ? "[No speech detected]"
+        : result.text;
+    }
+
     if (!this.serverProcess) {
       await this.ensureServerStarted();
       await this.sendRequest({
@@ -445,7 +605,7 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
       command: "transcribe",
       path: filePath,
     });
-    return result.text;
+    return result.status === "no_speech" ? "[No speech detected]" : result.text;
   }
 
   async stopTranscription(): Promise<void> {
@@ -515,6 +675,28 @@ export class ParakeetTranscriptionPlugin extends BaseTranscriptionPlugin {
         ],
         required: true,
       },
+      {
+        key: "process",
+        type: "select",
+        label: "Engine Process",
+        description:
+          "Where the Parakeet engine runs. The XPC service keeps the model in a separate sandboxed process that macOS starts on demand.",
+        default: "in-app",
+        category: "advanced",
+        options: [
+          {
+            value: "in-app",
+            label: "In-app",
+            description:
+              "Native addon, or a helper process if the addon can't be loaded",
+          },
+          {
+            value: "xpc",
+            label: "XPC service",
+            description: "The ParakeetBackend.xpc service bundled with the app",
+          },
+        ],
+      },
       {
         key: "runOnAll",
         type: "boolean",